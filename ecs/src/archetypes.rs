@@ -5,6 +5,12 @@ use crate::{
 
 pub struct Archetype {
     components: Vec<Option<Box<dyn ComponentStorage>>>,
+    // Parallel to components, the change tick each row was last written at.
+    // Queries stamp a row when its component is written through Mut, bulk
+    // column access can't tell which rows were written and stamps them all.
+    change_ticks: Vec<Option<Vec<u32>>>,
+    // The world's current change tick, what writes get stamped with.
+    tick: u32,
     pub entities: Vec<EntityId>,
 }
 
@@ -13,20 +19,47 @@ impl Archetype {
         let total_types = registry.len();
         let mut components = Vec::with_capacity(total_types);
         components.resize_with(total_types, || None);
+        let mut change_ticks = vec![None; total_types];
         for &index in component_indices {
             assert!(
                 index < total_types,
-                "component index {} out of bounds",
-                index
+                "component index {index} out of bounds, only {total_types} component types are registered"
             );
             components[index] = Some(registry.create_empty_column(index));
+            change_ticks[index] = Some(Vec::new());
         }
         Self {
             components,
+            change_ticks,
+            tick: 0,
             entities: Vec::new(),
         }
     }
 
+    pub fn set_tick(&mut self, tick: u32) {
+        self.tick = tick;
+    }
+
+    // None if the archetype has no such column.
+    pub fn change_ticks(&self, index: usize) -> Option<&[u32]> {
+        self.change_ticks.get(index)?.as_deref()
+    }
+
+    // Only stamps `row`, unlike get_column_mut.
+    pub fn get_component_mut<T: 'static>(&mut self, index: usize, row: usize) -> Option<&mut T> {
+        let component = self
+            .components
+            .get_mut(index)?
+            .as_mut()?
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()?
+            .get_mut(row)?;
+        if let Some(Some(ticks)) = self.change_ticks.get_mut(index) {
+            ticks[row] = self.tick;
+        }
+        Some(component)
+    }
+
     pub fn get_column<T: 'static>(&self, index: usize) -> Option<&Vec<T>> {
         self.components.get(index).and_then(|opt_storage| {
             opt_storage
@@ -35,20 +68,122 @@ impl Archetype {
         })
     }
 
+    // Stamps every row, for bulk writes through a slice.
     pub fn get_column_mut<T: 'static>(&mut self, index: usize) -> Option<&mut Vec<T>> {
-        self.components.get_mut(index).and_then(|opt_storage| {
+        let column = self.components.get_mut(index).and_then(|opt_storage| {
             opt_storage
                 .as_mut()
                 .and_then(|storage| storage.as_any_mut().downcast_mut::<Vec<T>>())
-        })
+        })?;
+        if let Some(Some(ticks)) = self.change_ticks.get_mut(index) {
+            ticks.fill(self.tick);
+        }
+        Some(column)
+    }
+
+    // The column with its change ticks and the tick to stamp them with,
+    // nothing is stamped until a row is actually written.
+    pub fn get_column_mut_tracked<T: 'static>(
+        &mut self,
+        index: usize,
+    ) -> Option<(&mut Vec<T>, &mut Vec<u32>, u32)> {
+        let column = self
+            .components
+            .get_mut(index)?
+            .as_mut()?
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()?;
+        let ticks = self.change_ticks.get_mut(index)?.as_mut()?;
+        Some((column, ticks, self.tick))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = (usize, &dyn ComponentStorage)> {
+        self.components
+            .iter()
+            .enumerate()
+            .filter_map(|(index, column)| Some((index, column.as_deref()?)))
+    }
+
+    // Allocated rather than used, so it includes what reserve and removals
+    // left spare.
+    pub fn column_bytes(&self) -> usize {
+        self.components
+            .iter()
+            .flatten()
+            .map(|column| column.capacity() * column.element_size())
+            .sum()
+    }
+
+    // The entity list and change ticks kept alongside the columns.
+    pub fn bookkeeping_bytes(&self) -> usize {
+        let ticks: usize = self
+            .change_ticks
+            .iter()
+            .flatten()
+            .map(|ticks| ticks.capacity() * size_of::<u32>())
+            .sum();
+        self.entities.capacity() * size_of::<EntityId>() + ticks
     }
 
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        for column in self.components.iter_mut().flatten() {
+            column.reserve(additional);
+        }
+        for ticks in self.change_ticks.iter_mut().flatten() {
+            ticks.reserve(additional);
+        }
+    }
+
+    // Returns the entity that was moved into `row` to fill the gap, if any.
+    pub fn swap_remove(&mut self, row: usize) -> Option<EntityId> {
+        self.entities.swap_remove(row);
+        for column in self.components.iter_mut().flatten() {
+            column.swap_remove(row);
+        }
+        for ticks in self.change_ticks.iter_mut().flatten() {
+            ticks.swap_remove(row);
+        }
+        self.entities.get(row).copied()
+    }
+
+    // Everything is checked before the row is written, so a mismatched
+    // ComponentTuple leaves the archetype untouched instead of with columns
+    // of different lengths.
     pub fn insert(
         &mut self,
         entity: EntityId,
-        component_indices: Vec<usize>,
+        component_indices: &[usize],
         mut components: Vec<Box<dyn ComponentStorage>>,
-    ) {
+    ) -> Result<(), String> {
+        if component_indices.len() != components.len() {
+            return Err(format!(
+                "entity {entity:?} has {} component indices but {} components",
+                component_indices.len(),
+                components.len()
+            ));
+        }
+        let column_count = self.components.iter().flatten().count();
+        if component_indices.len() != column_count {
+            return Err(format!(
+                "entity {entity:?} has {} components but the archetype has {column_count} columns",
+                component_indices.len()
+            ));
+        }
+        for &index in component_indices {
+            if index >= self.components.len() {
+                return Err(format!(
+                    "component index {index} out of bounds for {} registered types",
+                    self.components.len()
+                ));
+            }
+            if self.components[index].is_none() {
+                return Err(format!(
+                    "archetype has no column for component index {index}"
+                ));
+            }
+        }
+
         self.entities.push(entity);
 
         for (i, storage) in component_indices.iter().enumerate() {
@@ -58,10 +193,14 @@ impl Archetype {
 
             column.push_from_other(&mut components[i]);
         }
+        for ticks in self.change_ticks.iter_mut().flatten() {
+            ticks.push(self.tick);
+        }
+        Ok(())
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Hash, Clone)]
 pub struct ArchetypeKey(Vec<usize>);
 
 impl ArchetypeKey {
@@ -70,8 +209,13 @@ impl ArchetypeKey {
         key.sort_unstable();
         ArchetypeKey(key)
     }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.0
+    }
 }
 
+#[allow(dead_code)]
 pub trait GetColumns<'world, T> {
     fn get_columns(&'world self, indices: &[usize]) -> Option<T>;
     fn get_columns_mut(&'world mut self, indices: &[usize]) -> Option<T>;
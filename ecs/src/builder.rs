@@ -0,0 +1,134 @@
+use std::{any::Any, collections::HashMap};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    World,
+    components::{
+        Billboard, BonePose, Bounds, Camera, ComponentTypeIndexRegistry, DirectionalLight,
+        FpsCamera, LodGroup, MeshHandle, PipelineKey, PlaySound, Position, Rotation, Scale,
+        Transform, Translation, Visible,
+    },
+    entities::{EntityAllocator, EntityLocationMap},
+    meshes::MeshId,
+    resources::Resources,
+};
+
+// Components are otherwise registered the first time they're spawned, so
+// their indices depend on spawn order. Registering them here up front gives
+// the same indices on every run. The engine's own components always come
+// first, in the order below.
+pub struct WorldBuilder {
+    type_registry: ComponentTypeIndexRegistry,
+    resources: Resources,
+}
+
+impl Default for WorldBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorldBuilder {
+    pub fn new() -> Self {
+        let mut type_registry = ComponentTypeIndexRegistry::new();
+        type_registry.register_serializable::<Camera>("Camera");
+        type_registry.register_serializable::<FpsCamera>("FpsCamera");
+        type_registry.register_serializable::<Position>("Position");
+        type_registry.register_serializable::<Transform>("Transform");
+        type_registry.register_serializable::<Translation>("Translation");
+        type_registry.register_serializable::<Rotation>("Rotation");
+        type_registry.register_serializable::<Scale>("Scale");
+        type_registry.register_serializable::<Bounds>("Bounds");
+        type_registry.register_serializable::<DirectionalLight>("DirectionalLight");
+        type_registry.register_serializable::<MeshHandle>("MeshHandle");
+        type_registry.register_serializable::<LodGroup>("LodGroup");
+        type_registry.register_serializable::<BonePose>("BonePose");
+        type_registry.register_serializable::<PlaySound>("PlaySound");
+        type_registry.register_serializable::<Visible>("Visible");
+        type_registry.register_serializable::<PipelineKey>("PipelineKey");
+        type_registry.register_serializable::<Billboard>("Billboard");
+        type_registry.register_serializable::<MeshId>("MeshId");
+
+        Self {
+            type_registry,
+            resources: Resources::new(),
+        }
+    }
+
+    pub fn with_component<T: Send + Sync + 'static>(mut self) -> Self {
+        self.type_registry.get_or_register::<T>();
+        self
+    }
+
+    pub fn with_serializable_component<T>(mut self, name: &'static str) -> Self
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.type_registry.register_serializable::<T>(name);
+        self
+    }
+
+    pub fn with_resource<R: Any + Send + Sync>(mut self, resource: R) -> Self {
+        self.resources.insert(resource);
+        self
+    }
+
+    pub fn build(self) -> World {
+        World {
+            archetypes: Vec::new(),
+            archetype_lookup: HashMap::new(),
+            type_registry: self.type_registry,
+            resources: self.resources,
+            entity_allocator: EntityAllocator::new(),
+            entity_location_map: EntityLocationMap::new(),
+            startup_systems: Vec::new(),
+            startup_ran: false,
+            systems: Vec::new(),
+            observers: Vec::new(),
+            change_tick: 1,
+            transform_compose_tick: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Health;
+    struct Armor;
+
+    fn build() -> World {
+        WorldBuilder::new()
+            .with_component::<Health>()
+            .with_component::<Armor>()
+            .build()
+    }
+
+    #[test]
+    fn registered_components_keep_their_indices() {
+        let mut first = build();
+        let mut second = build();
+
+        // Spawning in a different order doesn't move registered components.
+        first.spawn((Health,));
+        first.spawn((Armor,));
+        second.spawn((Armor,));
+        second.spawn((Health,));
+
+        assert_eq!(first.component_index::<Camera>(), Some(0));
+        assert_eq!(
+            first.component_index::<Health>(),
+            second.component_index::<Health>()
+        );
+        assert_eq!(
+            first.component_index::<Armor>(),
+            second.component_index::<Armor>()
+        );
+        assert_ne!(
+            first.component_index::<Health>(),
+            first.component_index::<Armor>()
+        );
+    }
+}
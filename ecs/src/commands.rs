@@ -20,6 +20,7 @@ impl Default for IndirectDrawCommand {
                 index_offset: 0,
                 vertex_count: 0,
                 index_count: 0,
+                stream: 0,
             },
             transform: vec![Transform(Mat4::IDENTITY)],
         }
@@ -1,42 +1,378 @@
-use std::any::{Any, TypeId};
+use std::{
+    any::{Any, TypeId, type_name},
+    ops::Range,
+};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
 
-#[derive(Debug, Clone, Copy)]
+use crate::{entities::EntityId, input::InputState, math::Aabb, rng::Rng};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Camera;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct FpsCamera {
     pub yaw: f32,
     pub pitch: f32,
+    // Radians around the forward axis, positive tilts the up vector to the right.
+    #[serde(default)]
+    pub roll: f32,
+    // Units per second at a walk.
     pub speed: f32,
+    // Scales `speed` while shift is held.
+    #[serde(default = "default_sprint_multiplier")]
+    pub sprint_multiplier: f32,
     pub sensitivity: f32,
 }
 
-#[derive(Debug, Copy, Clone)]
+fn default_sprint_multiplier() -> f32 {
+    2.0
+}
+
+impl FpsCamera {
+    pub fn move_speed(&self, input: &InputState) -> f32 {
+        if input.key_shift {
+            self.speed * self.sprint_multiplier
+        } else {
+            self.speed
+        }
+    }
+
+    // Moves `position` along the held movement keys and applies mouse look.
+    pub fn update(&mut self, position: &mut Position, input: &InputState, delta_time: f32) {
+        let forward = self.forward();
+        let up = self.up();
+        let right = forward.cross(up).normalize();
+
+        // Movement
+        let mut velocity = Vec3::ZERO;
+        if input.key_w {
+            velocity += forward;
+        }
+        if input.key_s {
+            velocity -= forward;
+        }
+        if input.key_d {
+            velocity += right;
+        }
+        if input.key_a {
+            velocity -= right;
+        }
+        if input.key_space {
+            velocity += up;
+        }
+        if input.key_ctrl {
+            velocity -= up;
+        }
+
+        if velocity.length_squared() > 0.0 {
+            position.0 += velocity.normalize() * self.move_speed(input) * delta_time;
+        }
+
+        self.yaw += input.mouse_delta_x * self.sensitivity;
+        self.pitch -= input.mouse_delta_y * self.sensitivity;
+        self.pitch = self
+            .pitch
+            .clamp(-89.9_f32.to_radians(), 89.9_f32.to_radians());
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        )
+        .normalize()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        // World up made perpendicular to forward, written out rather than
+        // taken from cross products, which collapse to zero and normalize to
+        // NaN when looking straight up or down.
+        let level_up = Vec3::new(
+            -self.pitch.sin() * self.yaw.cos(),
+            self.pitch.cos(),
+            -self.pitch.sin() * self.yaw.sin(),
+        );
+        Quat::from_axis_angle(self.forward(), self.roll) * level_up
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(self.up()).normalize()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Position(pub Vec3);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Transform(pub Mat4);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Bounds(pub Aabb);
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MeshHandle {
     pub vertex_offset: u64,
     pub index_offset: u64,
     pub vertex_count: u32,
     pub index_count: u32,
+    // Which of the mesh allocator's vertex/index buffer streams the offsets
+    // point into.
+    #[serde(default)]
+    pub stream: u32,
+}
+
+// Entities with all three of these and a Transform have the Transform
+// rebuilt from them by compose_transform_system whenever one is written.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Translation(pub Vec3);
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rotation(pub Quat);
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scale(pub Vec3);
+
+// Which of the renderer's pipelines draws the entity, see
+// Engine::add_pipeline. Entities without one use the default pipeline.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct PipelineKey(pub u32);
+
+impl PipelineKey {
+    pub const DEFAULT: PipelineKey = PipelineKey(0);
+}
+
+// Marks a Transform whose rotation billboard_system replaces every tick so
+// the local +Z axis points at the camera. Scale and translation are kept.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Billboard;
+
+// Entities without this component are drawn. Toggling the flag is cheaper
+// than adding or removing a component, which moves the entity's archetype.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Visible(pub bool);
+
+// Sent by collision_system for each pair of overlapping Bounds, with a < b.
+// Read them with World::events or take_events; unread ones only last one
+// tick.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CollisionEvent {
+    pub a: EntityId,
+    pub b: EntityId,
+}
+
+// Spawned on its own entity as a one-shot event; the engine plays the sound
+// with this label and despawns the entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaySound {
+    pub sound: String,
+}
+
+impl PlaySound {
+    pub fn new(sound: impl Into<String>) -> Self {
+        Self {
+            sound: sound.into(),
+        }
+    }
+}
+
+type SerializeComponent = Box<dyn Fn(&dyn ComponentStorage, usize) -> Option<Value> + Send + Sync>;
+type DeserializeComponent =
+    Box<dyn Fn(Value) -> Result<Box<dyn ComponentStorage>, String> + Send + Sync>;
+
+// Joint matrices for a skinned mesh, already multiplied by the inverse bind
+// pose so a vertex in bind pose maps straight to the animated model space.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BonePose {
+    pub matrices: Vec<Mat4>,
+}
+
+impl BonePose {
+    // CPU version of the vertex shader skinning, joints past the end of the
+    // palette are clamped the same way.
+    pub fn skin_point(
+        &self,
+        joint_indices: [u32; 4],
+        joint_weights: [f32; 4],
+        point: Vec3,
+    ) -> Vec3 {
+        let Some(last) = self.matrices.len().checked_sub(1) else {
+            return point;
+        };
+        joint_indices
+            .iter()
+            .zip(joint_weights)
+            .map(|(&joint, weight)| {
+                self.matrices[(joint as usize).min(last)].transform_point3(point) * weight
+            })
+            .sum()
+    }
+}
+
+// (distance, mesh) pairs sorted by distance. A mesh is used from its distance
+// out to the next entry's, the first entry also covers anything closer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LodGroup {
+    pub handles: Vec<(f32, MeshHandle)>,
+}
+
+impl LodGroup {
+    pub fn new(mut handles: Vec<(f32, MeshHandle)>) -> Self {
+        handles.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { handles }
+    }
+
+    pub fn select(&self, distance: f32) -> Option<MeshHandle> {
+        self.handles
+            .iter()
+            .take_while(|(threshold, _)| *threshold <= distance)
+            .last()
+            .or(self.handles.first())
+            .map(|(_, handle)| *handle)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    // Seconds since it was spawned.
+    pub age: f32,
+}
+
+// Spawns `rate` particles a second at the entity's Transform, each with a
+// velocity picked per axis from `velocity_range`, and lets them fly for
+// `lifetime` seconds. Particles are plain entries on the emitter rather than
+// entities, expired ones are kept past the live ones and reused by the next
+// spawn. Not saved with scenes, the particles are runtime state.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub rate: f32,
+    pub lifetime: f32,
+    pub velocity_range: Range<Vec3>,
+    // World space width of each particle's quad.
+    pub size: f32,
+    // The first `live` are alive, the rest are waiting to be recycled.
+    particles: Vec<Particle>,
+    live: usize,
+    // Fractional particles carried over between steps.
+    spawn_accumulator: f32,
+    rng: Rng,
+}
+
+impl ParticleEmitter {
+    pub fn new(rate: f32, lifetime: f32, velocity_range: Range<Vec3>) -> Self {
+        Self {
+            rate,
+            lifetime,
+            velocity_range,
+            size: 0.1,
+            particles: Vec::new(),
+            live: 0,
+            spawn_accumulator: 0.0,
+            rng: Rng::new(0),
+        }
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    // Emitters sharing a seed spawn the same velocities.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles[..self.live]
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.live
+    }
+
+    // Ages and moves the live particles, retiring any past their lifetime,
+    // then spawns this step's share of `rate` at `origin`.
+    pub fn step(&mut self, origin: Vec3, delta_time: f32) {
+        let mut index = 0;
+        while index < self.live {
+            let particle = &mut self.particles[index];
+            particle.age += delta_time;
+            if particle.age >= self.lifetime {
+                // Swapped behind the live ones, the slot it leaves is checked
+                // again.
+                self.live -= 1;
+                self.particles.swap(index, self.live);
+                continue;
+            }
+            particle.position += particle.velocity * delta_time;
+            index += 1;
+        }
+
+        self.spawn_accumulator += self.rate.max(0.0) * delta_time;
+        let spawns = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= spawns;
+        for _ in 0..spawns as usize {
+            let range = &self.velocity_range;
+            let velocity = Vec3::new(
+                self.rng.range(range.start.x..range.end.x),
+                self.rng.range(range.start.y..range.end.y),
+                self.rng.range(range.start.z..range.end.z),
+            );
+            let particle = Particle {
+                position: origin,
+                velocity,
+                age: 0.0,
+            };
+            match self.particles.get_mut(self.live) {
+                Some(slot) => *slot = particle,
+                None => self.particles.push(particle),
+            }
+            self.live += 1;
+        }
+    }
+}
+
+pub struct ComponentSerializer {
+    pub name: &'static str,
+    serialize: SerializeComponent,
+    deserialize: DeserializeComponent,
 }
 
 pub struct ComponentTypeIndexRegistry {
     type_to_index: Vec<TypeId>,
+    type_names: Vec<&'static str>,
     factories: Vec<Box<dyn Fn() -> Box<dyn ComponentStorage> + Send + Sync>>,
+    serializers: Vec<Option<ComponentSerializer>>,
+}
+
+impl Default for ComponentTypeIndexRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ComponentTypeIndexRegistry {
     pub fn new() -> Self {
         Self {
             type_to_index: Vec::new(),
+            type_names: Vec::new(),
             factories: Vec::new(),
+            serializers: Vec::new(),
         }
     }
 
@@ -47,28 +383,90 @@ impl ComponentTypeIndexRegistry {
         }
         let index = self.type_to_index.len();
         self.type_to_index.push(type_id);
+        self.type_names.push(type_name::<T>());
 
         self.factories.push(Box::new(|| {
             Box::new(Vec::<T>::new()) as Box<dyn ComponentStorage>
         }));
+        self.serializers.push(None);
+        index
+    }
+
+    // The name is what the component is stored under in a saved scene, so it
+    // has to stay stable across builds unlike the type id.
+    pub fn register_serializable<T>(&mut self, name: &'static str) -> usize
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let index = self.get_or_register::<T>();
+        self.serializers[index] = Some(ComponentSerializer {
+            name,
+            serialize: Box::new(|storage, row| {
+                let column = storage.as_any().downcast_ref::<Vec<T>>()?;
+                serde_json::to_value(column.get(row)?).ok()
+            }),
+            deserialize: Box::new(move |value| {
+                let component = serde_json::from_value::<T>(value)
+                    .map_err(|err| format!("failed to deserialize {name}: {err}"))?;
+                Ok(Box::new(vec![component]) as Box<dyn ComponentStorage>)
+            }),
+        });
         index
     }
 
+    pub fn serializer(&self, index: usize) -> Option<&ComponentSerializer> {
+        self.serializers.get(index)?.as_ref()
+    }
+
+    pub fn serializable_index(&self, name: &str) -> Option<usize> {
+        self.serializers
+            .iter()
+            .position(|serializer| serializer.as_ref().is_some_and(|s| s.name == name))
+    }
+
     pub fn get_index(&self, type_id: TypeId) -> Option<usize> {
         self.type_to_index.iter().position(|&id| id == type_id)
     }
 
+    // For error messages and introspection, not stable across builds like a
+    // serializable name.
+    pub fn type_name(&self, index: usize) -> &'static str {
+        self.type_names
+            .get(index)
+            .copied()
+            .unwrap_or("<unregistered component>")
+    }
+
     pub fn len(&self) -> usize {
         self.type_to_index.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.type_to_index.is_empty()
+    }
+
     pub fn create_empty_column(&self, index: usize) -> Box<dyn ComponentStorage> {
         (self.factories[index])()
     }
 }
 
+impl ComponentSerializer {
+    pub fn serialize(&self, storage: &dyn ComponentStorage, row: usize) -> Option<Value> {
+        (self.serialize)(storage, row)
+    }
+
+    pub fn deserialize(&self, value: Value) -> Result<Box<dyn ComponentStorage>, String> {
+        (self.deserialize)(value)
+    }
+}
+
 pub trait ComponentStorage: Send + Sync {
     fn push_from_other(&mut self, other: &mut Box<dyn ComponentStorage>);
+    fn reserve(&mut self, additional: usize);
+    fn swap_remove(&mut self, row: usize);
+    // Bytes per component, the allocation is capacity() of them.
+    fn element_size(&self) -> usize;
+    fn capacity(&self) -> usize;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
@@ -89,6 +487,22 @@ impl<T: Send + Sync + 'static> ComponentStorage for Vec<T> {
             .expect("type mismatch");
         self.push(other_vec.remove(0));
     }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+
+    fn swap_remove(&mut self, row: usize) {
+        Vec::swap_remove(self, row);
+    }
+
+    fn element_size(&self) -> usize {
+        size_of::<T>()
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
 }
 
 pub trait ComponentTuple {
@@ -103,6 +517,7 @@ macro_rules! impl_component_tuple {
                 vec![$(registry.get_or_register::<$name>()),*]
             }
 
+            #[allow(non_snake_case)]
             fn into_components(self) -> Vec<Box<dyn ComponentStorage>> {
                 let ($($name,)*) = self;
                 vec![$(Box::new(vec![$name]) as Box<dyn ComponentStorage>),*]
@@ -127,3 +542,49 @@ impl_component_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M);
 impl_component_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
 impl_component_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
 impl_component_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emitter_keeps_what_it_spawned_minus_what_expired() {
+        let (rate, lifetime, delta_time) = (8.0, 1.0, 0.25);
+        let mut emitter = ParticleEmitter::new(rate, lifetime, Vec3::ZERO..Vec3::ONE);
+        for step in 1..=12 {
+            emitter.step(Vec3::ZERO, delta_time);
+            let elapsed = step as f32 * delta_time;
+            let expired = rate * (elapsed - lifetime).max(0.0);
+            assert_eq!(emitter.live_count() as f32, rate * elapsed - expired);
+            assert!(
+                emitter
+                    .particles()
+                    .iter()
+                    .all(|particle| particle.age < lifetime)
+            );
+        }
+    }
+
+    #[test]
+    fn sprinting_camera_moves_speed_times_multiplier_per_second() {
+        let mut camera = FpsCamera {
+            yaw: 0.3,
+            pitch: 0.1,
+            roll: 0.0,
+            speed: 5.0,
+            sprint_multiplier: 2.0,
+            sensitivity: 0.002,
+        };
+        let mut position = Position(Vec3::new(1.0, 2.0, 3.0));
+        let input = InputState {
+            key_w: true,
+            key_shift: true,
+            ..InputState::default()
+        };
+        let delta_time = 0.5;
+
+        camera.update(&mut position, &input, delta_time);
+        let moved = position.0.distance(Vec3::new(1.0, 2.0, 3.0));
+        assert!((moved - 5.0 * 2.0 * delta_time).abs() < 1e-5, "{moved}");
+    }
+}
@@ -0,0 +1,143 @@
+use std::cmp::Ordering;
+
+use crate::entities::EntityId;
+
+// One component type's values keyed by entity, sorted by EntityId so two
+// snapshots can be compared regardless of archetype row order.
+#[derive(Debug, Clone)]
+pub struct Snapshot<T> {
+    entries: Vec<(EntityId, T)>,
+}
+
+impl<T> Default for Snapshot<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> Snapshot<T> {
+    pub fn new(mut entries: Vec<(EntityId, T)>) -> Self {
+        entries.sort_unstable_by_key(|(entity, _)| *entity);
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[(EntityId, T)] {
+        &self.entries
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&T> {
+        self.entries
+            .binary_search_by_key(&entity, |(entity, _)| *entity)
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Every list is in ascending EntityId order. A despawned entity whose index
+// was reused shows up as removed plus added, since the ids differ.
+#[derive(Debug, Clone)]
+pub struct Diff<T> {
+    pub added: Vec<(EntityId, T)>,
+    pub changed: Vec<(EntityId, T)>,
+    pub removed: Vec<EntityId>,
+}
+
+impl<T> Default for Diff<T> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+impl<T> Diff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<T: PartialEq + Clone> Diff<T> {
+    pub fn between(prev: &Snapshot<T>, current: &Snapshot<T>) -> Self {
+        let mut diff = Self::default();
+        let mut prev_entries = prev.entries.iter().peekable();
+        let mut current_entries = current.entries.iter().peekable();
+
+        loop {
+            match (prev_entries.peek(), current_entries.peek()) {
+                (Some((prev_entity, prev_value)), Some((entity, value))) => {
+                    match prev_entity.cmp(entity) {
+                        Ordering::Less => {
+                            diff.removed.push(*prev_entity);
+                            prev_entries.next();
+                        }
+                        Ordering::Greater => {
+                            diff.added.push((*entity, value.clone()));
+                            current_entries.next();
+                        }
+                        Ordering::Equal => {
+                            if prev_value != value {
+                                diff.changed.push((*entity, value.clone()));
+                            }
+                            prev_entries.next();
+                            current_entries.next();
+                        }
+                    }
+                }
+                (Some((prev_entity, _)), None) => {
+                    diff.removed.push(*prev_entity);
+                    prev_entries.next();
+                }
+                (None, Some((entity, value))) => {
+                    diff.added.push((*entity, value.clone()));
+                    current_entries.next();
+                }
+                (None, None) => break,
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::World;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Hp(u32);
+
+    #[test]
+    fn between_reports_added_changed_removed_and_reused_entities() {
+        let mut world = World::new();
+        let kept = world.spawn((Hp(10),));
+        let hurt = world.spawn((Hp(10),));
+        let dead = world.spawn((Hp(10),));
+        let prev = world.component_snapshot::<Hp>();
+        assert!(Diff::between(&prev, &prev).is_empty());
+
+        world.set_component(hurt, Hp(4));
+        world.despawn(dead);
+        let reused = world.spawn((Hp(10),));
+        let fresh = world.spawn((Hp(1),));
+        assert_eq!(reused.index, dead.index);
+        assert_ne!(reused, dead);
+
+        let diff = Diff::between(&prev, &world.component_snapshot::<Hp>());
+        assert_eq!(diff.changed, [(hurt, Hp(4))]);
+        assert_eq!(diff.removed, [dead]);
+        assert_eq!(diff.added, [(reused, Hp(10)), (fresh, Hp(1))]);
+        assert!(diff.added.iter().all(|(entity, _)| *entity != kept));
+    }
+}
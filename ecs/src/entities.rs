@@ -1,14 +1,28 @@
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct EntityId {
     pub index: u32,
     generation: u32,
 }
 
+impl EntityId {
+    // Bumped each time the index is recycled, so a stale id no longer matches
+    // the entity now living at its index.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
 pub struct EntityAllocator {
     generations: Vec<u32>,
     free_list: Vec<u32>,
 }
 
+impl Default for EntityAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EntityAllocator {
     pub fn new() -> Self {
         Self {
@@ -39,10 +53,20 @@ impl EntityAllocator {
         }
     }
 
+    // Frees every index. Generations are bumped rather than reset, so no id
+    // handed out before the clear matches one handed out after it.
+    pub fn clear(&mut self) {
+        for generation in &mut self.generations {
+            *generation += 1;
+        }
+        // Reversed so indices are handed out from zero again.
+        self.free_list = (0..self.generations.len() as u32).rev().collect();
+    }
+
     pub fn is_alive(&self, entity: EntityId) -> bool {
         self.generations
             .get(entity.index as usize)
-            .map_or(false, |&generation| generation == entity.generation)
+            .is_some_and(|&generation| generation == entity.generation)
     }
 }
 
@@ -53,12 +77,18 @@ pub struct EntityLocationMap {
     slots: Vec<Option<(ArchetypeIndex, RowIndex)>>,
 }
 
+impl Default for EntityLocationMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EntityLocationMap {
     pub fn new() -> Self {
         Self { slots: Vec::new() }
     }
 
-    pub fn insert(&mut self, entity: EntityId, location: (usize, usize)) {
+    pub fn insert(&mut self, entity: EntityId, location: (ArchetypeIndex, RowIndex)) {
         let idx = entity.index as usize;
         if self.slots.len() <= idx {
             self.slots.resize(idx + 1, None);
@@ -66,7 +96,7 @@ impl EntityLocationMap {
         self.slots[idx] = Some(location);
     }
 
-    pub fn get(&self, entity: EntityId) -> Option<(usize, usize)> {
+    pub fn get(&self, entity: EntityId) -> Option<(ArchetypeIndex, RowIndex)> {
         self.slots.get(entity.index as usize).copied().flatten()
     }
 
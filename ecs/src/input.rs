@@ -1,3 +1,85 @@
+use std::time::Instant;
+
+// Where mouse input comes from. A grabbed cursor feeds relative motion to
+// camera look, a free one feeds absolute positions to UI and picking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseMode {
+    Relative,
+    Absolute,
+}
+
+// The keys and mouse buttons the engine tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    W,
+    A,
+    S,
+    D,
+    Space,
+    Ctrl,
+    Shift,
+    MouseLeft,
+    MouseRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ButtonSet(u32);
+
+impl ButtonSet {
+    pub fn contains(&self, button: Button) -> bool {
+        self.0 & (1 << button as u32) != 0
+    }
+
+    pub fn insert(&mut self, button: Button) {
+        self.0 |= 1 << button as u32;
+    }
+
+    pub fn remove(&mut self, button: Button) {
+        self.0 &= !(1 << button as u32);
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub button: Button,
+    pub pressed: bool,
+    pub time: Instant,
+}
+
+// Button transitions in the order they arrived. Drained when a sim tick
+// runs, so a press and release between two ticks both reach it instead of
+// cancelling out.
+#[derive(Debug, Clone, Default)]
+pub struct InputQueue {
+    events: Vec<InputEvent>,
+}
+
+impl InputQueue {
+    pub fn push(&mut self, button: Button, pressed: bool, time: Instant) {
+        self.events.push(InputEvent {
+            button,
+            pressed,
+            time,
+        });
+    }
+
+    pub fn drain(&mut self) -> Vec<InputEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct InputState {
     pub key_w: bool,
@@ -6,8 +88,17 @@ pub struct InputState {
     pub key_d: bool,
     pub key_space: bool,
     pub key_ctrl: bool,
+    pub key_shift: bool,
     pub mouse_delta_x: f32,
     pub mouse_delta_y: f32,
+    pub cursor_x: f32,
+    pub cursor_y: f32,
+    pub cursor_in_window: bool,
+    pub mouse_mode: MouseMode,
+    pub held: ButtonSet,
+    // Transitions since the last tick, see apply_events.
+    pub just_pressed: ButtonSet,
+    pub just_released: ButtonSet,
 }
 
 impl Default for InputState {
@@ -19,8 +110,120 @@ impl Default for InputState {
             key_d: false,
             key_space: false,
             key_ctrl: false,
+            key_shift: false,
             mouse_delta_x: 0.0,
             mouse_delta_y: 0.0,
+            cursor_x: 0.0,
+            cursor_y: 0.0,
+            cursor_in_window: false,
+            mouse_mode: MouseMode::Absolute,
+            held: ButtonSet::default(),
+            just_pressed: ButtonSet::default(),
+            just_released: ButtonSet::default(),
+        }
+    }
+}
+
+impl InputState {
+    // Key repeats of a held button aren't presses. A press and release in
+    // the same batch leave the button up with both transitions recorded.
+    pub fn apply_events(&mut self, events: &[InputEvent]) {
+        for event in events {
+            let held = self.held.contains(event.button);
+            if event.pressed && !held {
+                self.just_pressed.insert(event.button);
+            } else if !event.pressed && held {
+                self.just_released.insert(event.button);
+            }
+            self.set_held(event.button, event.pressed);
+        }
+    }
+
+    // Called once the transitions have been seen by a tick.
+    pub fn clear_transitions(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+
+    pub fn is_held(&self, button: Button) -> bool {
+        self.held.contains(button)
+    }
+
+    pub fn just_pressed(&self, button: Button) -> bool {
+        self.just_pressed.contains(button)
+    }
+
+    pub fn just_released(&self, button: Button) -> bool {
+        self.just_released.contains(button)
+    }
+
+    fn set_held(&mut self, button: Button, held: bool) {
+        if held {
+            self.held.insert(button);
+        } else {
+            self.held.remove(button);
+        }
+        match button {
+            Button::W => self.key_w = held,
+            Button::A => self.key_a = held,
+            Button::S => self.key_s = held,
+            Button::D => self.key_d = held,
+            Button::Space => self.key_space = held,
+            Button::Ctrl => self.key_ctrl = held,
+            Button::Shift => self.key_shift = held,
+            Button::MouseLeft | Button::MouseRight => {}
         }
     }
+
+    // Motion gathered in the old mode is dropped so a switch never shows up
+    // as a jump in camera look.
+    pub fn set_mouse_mode(&mut self, mouse_mode: MouseMode) {
+        self.mouse_mode = mouse_mode;
+        self.mouse_delta_x = 0.0;
+        self.mouse_delta_y = 0.0;
+    }
+
+    // Raw device motion, ignored unless the cursor is grabbed.
+    pub fn mouse_motion(&mut self, dx: f32, dy: f32) {
+        if self.mouse_mode == MouseMode::Relative {
+            self.mouse_delta_x += dx;
+            self.mouse_delta_y += dy;
+        }
+    }
+
+    // Ignored while grabbed, the position of a locked cursor means nothing.
+    pub fn cursor_moved(&mut self, x: f32, y: f32) {
+        self.cursor_in_window = true;
+        if self.mouse_mode == MouseMode::Absolute {
+            self.cursor_x = x;
+            self.cursor_y = y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_and_release_in_one_tick_are_both_seen() {
+        let mut queue = InputQueue::default();
+        let mut input = InputState::default();
+        let now = Instant::now();
+        queue.push(Button::Space, true, now);
+        queue.push(Button::Space, false, now);
+
+        input.apply_events(&queue.drain());
+        assert!(queue.is_empty());
+        assert!(input.just_pressed(Button::Space));
+        assert!(input.just_released(Button::Space));
+        assert!(!input.is_held(Button::Space));
+        assert!(!input.key_space);
+
+        // The next tick only sees them again if they happen again.
+        input.clear_transitions();
+        input.apply_events(&queue.drain());
+        assert!(!input.just_pressed(Button::Space));
+        assert!(!input.just_released(Button::Space));
+    }
 }
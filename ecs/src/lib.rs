@@ -1,80 +1,730 @@
-use std::any::TypeId;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    time::Instant,
+};
+
+use serde_json::Value;
 
 use crate::{
     archetypes::{Archetype, ArchetypeKey},
+    builder::WorldBuilder,
     components::{
-        ComponentTuple, ComponentTypeIndexRegistry
+        BonePose, Bounds, Camera, ComponentStorage, ComponentTuple, ComponentTypeIndexRegistry,
+        DirectionalLight, FpsCamera, MeshHandle, ParticleEmitter, PipelineKey, Position, Transform,
+        Visible,
     },
-    entities::{EntityAllocator, EntityId},
+    diff::{Diff, Snapshot},
+    entities::{EntityAllocator, EntityId, EntityLocationMap},
     input::InputState,
+    math::Ray,
+    meshes::{MeshId, MeshManager},
+    observers::{Observer, notify_added, notify_removed},
     queries::Query,
+    resources::{Events, Resources, SystemStats, SystemTiming, Time},
+    snapshot::{BoneRange, CameraSnapshot, ParticleSnapshot, RenderSnapshot},
+    stats::{ArchetypeMemory, MemoryReport},
+    system_params::{BoxedSystem, IntoSystem},
 };
 
+// Lets the derive macros refer to `::ecs` from inside this crate as well.
+extern crate self as ecs;
+
+pub use ecs_macros::Bundle;
+pub use queries::Mut;
+
 mod archetypes;
+pub mod builder;
 pub mod commands;
 pub mod components;
-mod entities;
+pub mod diff;
+pub mod entities;
 pub mod input;
+pub mod math;
+pub mod meshes;
+mod observers;
 mod queries;
+pub mod resources;
+pub mod rng;
+pub mod snapshot;
+pub mod spatial;
+pub mod stats;
+pub mod system_params;
 mod systems;
 
+pub type StartupSystem = Box<dyn FnOnce(&mut World) + Send>;
+
 pub struct World {
     archetypes: Vec<(ArchetypeKey, Archetype)>,
+    // Archetypes stay in the Vec in creation order so iteration is
+    // deterministic, the map only speeds up finding one by key.
+    archetype_lookup: HashMap<ArchetypeKey, usize>,
     type_registry: ComponentTypeIndexRegistry,
+    resources: Resources,
     entity_allocator: EntityAllocator,
-    entity_location_map: Vec<Option<(usize, usize)>>,
+    entity_location_map: EntityLocationMap,
+    startup_systems: Vec<StartupSystem>,
+    startup_ran: bool,
+    systems: Vec<BoxedSystem>,
+    observers: Vec<Observer>,
+    // What component writes are stamped with, see Archetype::change_ticks.
+    change_tick: u32,
+    // The change tick compose_transform_system last ran at.
+    transform_compose_tick: u32,
+}
+
+fn time_system(timings: &mut Option<Vec<SystemTiming>>, name: &'static str, run: impl FnOnce()) {
+    match timings {
+        Some(timings) => {
+            let start = Instant::now();
+            run();
+            timings.push(SystemTiming {
+                name,
+                duration: start.elapsed(),
+            });
+        }
+        None => run(),
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
-        Self {
-            archetypes: Vec::new(),
-            type_registry: ComponentTypeIndexRegistry::new(),
-            entity_allocator: EntityAllocator::new(),
-            entity_location_map: Vec::new(),
+        WorldBuilder::new().build()
+    }
+
+    pub fn builder() -> WorldBuilder {
+        WorldBuilder::new()
+    }
+
+    pub fn archetype_count(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub fn component_index<T: 'static>(&self) -> Option<usize> {
+        self.type_registry.get_index(TypeId::of::<T>())
+    }
+
+    pub fn insert_resource<R: Any + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(resource)
+    }
+
+    pub fn resource<R: Any>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+
+    pub fn resource_mut<R: Any>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut::<R>()
+    }
+
+    pub fn remove_resource<R: Any>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+
+    // Runs once, before the systems of the next run_systems call. Systems
+    // added after startup already ran still run once, on the next call.
+    pub fn add_startup_system(&mut self, system: impl FnOnce(&mut World) + Send + 'static) {
+        self.startup_systems.push(Box::new(system));
+    }
+
+    pub fn startup_ran(&self) -> bool {
+        self.startup_ran
+    }
+
+    // Runs every tick after the built-in systems, in the order added. Its
+    // parameters are fetched from the world each run, e.g.
+    // `fn gravity(mut bodies: Query<(&mut Velocity,)>, time: Res<Time>)`.
+    // Fails if two parameters borrow the same data and one of them mutably, or
+    // if it takes more than one Query. A system whose resource is missing is
+    // skipped.
+    pub fn add_system<Params>(&mut self, system: impl IntoSystem<Params>) -> Result<(), String> {
+        let system = system.into_system();
+        system_params::validate_access(system.name(), system.access(), system.query_count())?;
+        self.systems.push(system);
+        Ok(())
+    }
+
+    pub fn run_systems(&mut self, _frame_index: usize, input: &InputState, delta_time: f32) {
+        for system in std::mem::take(&mut self.startup_systems) {
+            system(self);
+        }
+        self.startup_ran = true;
+
+        let time = self.resources.get_mut::<Time>();
+        match time {
+            Some(time) => {
+                time.delta_time = delta_time;
+                time.elapsed += delta_time;
+                time.ticks += 1;
+            }
+            None => {
+                self.resources.insert(Time {
+                    delta_time,
+                    elapsed: delta_time,
+                    ticks: 1,
+                });
+            }
+        }
+        self.resources.insert(*input);
+
+        // Timing is only paid for while a SystemStats resource is present.
+        let mut timings = self.resources.contains::<SystemStats>().then(Vec::new);
+
+        time_system(&mut timings, "update_fps_camera_system", || {
+            systems::update_fps_camera_system(self, input, delta_time)
+        });
+        time_system(&mut timings, "compose_transform_system", || {
+            systems::compose_transform_system(self)
+        });
+        time_system(&mut timings, "billboard_system", || {
+            systems::billboard_system(self)
+        });
+        time_system(&mut timings, "particle_system", || {
+            systems::particle_system(self, delta_time)
+        });
+        time_system(&mut timings, "update_lod_system", || {
+            systems::update_lod_system(self)
+        });
+        time_system(&mut timings, "collision_system", || {
+            systems::collision_system(self)
+        });
+
+        let mut user_systems = std::mem::take(&mut self.systems);
+        for system in &mut user_systems {
+            time_system(&mut timings, system.name(), || {
+                system.run(self);
+            });
+        }
+        // Keep any systems added while the others ran.
+        user_systems.append(&mut self.systems);
+        self.systems = user_systems;
+
+        if let Some(timings) = timings
+            && let Some(stats) = self.resources.get_mut::<SystemStats>()
+        {
+            stats.timings = timings;
         }
     }
 
-    pub fn run_systems(
+    // Records how long each system takes per tick into a SystemStats
+    // resource. Off by default.
+    pub fn set_system_timing(&mut self, enabled: bool) {
+        if !enabled {
+            self.resources.remove::<SystemStats>();
+        } else if !self.resources.contains::<SystemStats>() {
+            self.resources.insert(SystemStats::default());
+        }
+    }
+
+    // Calls `on_add` whenever an entity with a T is spawned and `on_remove`
+    // whenever one is despawned or cleared. The callbacks run in the middle
+    // of the structural change, so they only get the entity id.
+    pub fn add_observer<T: Send + Sync + 'static>(
         &mut self,
-        frame_index: usize,
-        input: &InputState,
-        delta_time: f32,
+        on_add: impl FnMut(EntityId) + Send + 'static,
+        on_remove: impl FnMut(EntityId) + Send + 'static,
     ) {
-        systems::update_fps_camera_system(self, input, delta_time);
+        let component = self.type_registry.get_or_register::<T>();
+        self.observers.push(Observer {
+            component,
+            on_add: Box::new(on_add),
+            on_remove: Box::new(on_remove),
+        });
     }
 
     pub fn spawn<T: ComponentTuple>(&mut self, components: T) -> EntityId {
-        let entity = self.entity_allocator.allocate();
         let component_indices = T::component_indices(&mut self.type_registry);
-        let component_data = components.into_components();
+        self.spawn_components(component_indices, components.into_components())
+    }
+
+    fn spawn_components(
+        &mut self,
+        component_indices: Vec<usize>,
+        component_data: Vec<Box<dyn ComponentStorage>>,
+    ) -> EntityId {
+        let entity = self.entity_allocator.allocate();
         let layout_key = ArchetypeKey::new_sorted(&component_indices);
         let archetype_index = self.find_or_create_archetype(&layout_key, &component_indices);
         let (_, archetype) = &mut self.archetypes[archetype_index];
         let row = archetype.entities.len();
-        archetype.insert(entity.clone(), component_indices, component_data);
+        if let Err(err) = archetype.insert(entity, &component_indices, component_data) {
+            let names: Vec<_> = component_indices
+                .iter()
+                .map(|&index| self.type_registry.type_name(index))
+                .collect();
+            panic!("failed to spawn entity with ({}): {err}", names.join(", "));
+        }
 
         self.entity_location_map
-            .resize_with(entity.index as usize + 1, || None);
-
-        self.entity_location_map[entity.index as usize] = Some((archetype_index, row));
+            .insert(entity, (archetype_index, row));
+        notify_added(&mut self.observers, entity, &component_indices);
         entity
     }
 
+    pub fn spawn_batch<T, I>(&mut self, batch: I) -> Vec<EntityId>
+    where
+        T: ComponentTuple,
+        I: IntoIterator<Item = T>,
+    {
+        let batch = batch.into_iter();
+        let component_indices = T::component_indices(&mut self.type_registry);
+        let layout_key = ArchetypeKey::new_sorted(&component_indices);
+        let archetype_index = self.find_or_create_archetype(&layout_key, &component_indices);
+        let (_, archetype) = &mut self.archetypes[archetype_index];
+        archetype.reserve(batch.size_hint().0);
+
+        let mut entities = Vec::with_capacity(batch.size_hint().0);
+        for components in batch {
+            let entity = self.entity_allocator.allocate();
+            let row = archetype.entities.len();
+            if let Err(err) =
+                archetype.insert(entity, &component_indices, components.into_components())
+            {
+                let names: Vec<_> = component_indices
+                    .iter()
+                    .map(|&index| self.type_registry.type_name(index))
+                    .collect();
+                panic!("failed to spawn entity with ({}): {err}", names.join(", "));
+            }
+            self.entity_location_map
+                .insert(entity, (archetype_index, row));
+            entities.push(entity);
+        }
+        for &entity in &entities {
+            notify_added(&mut self.observers, entity, &component_indices);
+        }
+        entities
+    }
+
+    // Swap-removes the entity's row, so the last entity of the archetype
+    // takes its place and iteration order within the archetype changes.
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        if !self.entity_allocator.is_alive(entity) {
+            return false;
+        }
+        let Some((archetype_index, row)) = self.entity_location_map.get(entity) else {
+            return false;
+        };
+
+        let (key, archetype) = &mut self.archetypes[archetype_index];
+        if let Some(moved) = archetype.swap_remove(row) {
+            self.entity_location_map
+                .insert(moved, (archetype_index, row));
+        }
+        self.entity_location_map.remove(entity);
+        self.entity_allocator.deallocate(entity);
+        notify_removed(&mut self.observers, entity, key.indices());
+        true
+    }
+
+    // Each archetype's T components as one contiguous slice, for bulk loops
+    // the compiler can vectorize. Empty archetypes are skipped.
+    pub fn column<T: 'static>(&self) -> impl Iterator<Item = &[T]> {
+        let index = self.type_registry.get_index(TypeId::of::<T>());
+        self.archetypes
+            .iter()
+            .filter_map(move |(_, archetype)| archetype.get_column::<T>(index?))
+            .filter(|column| !column.is_empty())
+            .map(Vec::as_slice)
+    }
+
+    pub fn column_mut<T: 'static>(&mut self) -> impl Iterator<Item = &mut [T]> {
+        let index = self.type_registry.get_index(TypeId::of::<T>());
+        self.archetypes
+            .iter_mut()
+            .filter_map(move |(_, archetype)| archetype.get_column_mut::<T>(index?))
+            .filter(|column| !column.is_empty())
+            .map(Vec::as_mut_slice)
+    }
+
+    pub fn component_snapshot<T: Clone + 'static>(&self) -> Snapshot<T> {
+        let Some(index) = self.type_registry.get_index(TypeId::of::<T>()) else {
+            return Snapshot::default();
+        };
+
+        let mut entries = Vec::new();
+        for (_, archetype) in &self.archetypes {
+            if let Some(column) = archetype.get_column::<T>(index) {
+                entries.extend(
+                    archetype
+                        .entities
+                        .iter()
+                        .copied()
+                        .zip(column.iter().cloned()),
+                );
+            }
+        }
+        Snapshot::new(entries)
+    }
+
+    // Compares the current T components against an earlier component_snapshot,
+    // e.g. to send only what changed since the last network update.
+    pub fn component_diff<T: PartialEq + Clone + 'static>(&self, prev: &Snapshot<T>) -> Diff<T> {
+        Diff::between(prev, &self.component_snapshot())
+    }
+
+    pub fn send_event<T: Send + Sync + 'static>(&mut self, event: T) {
+        match self.resources.get_mut::<Events<T>>() {
+            Some(events) => events.send(event),
+            None => {
+                let mut events = Events::default();
+                events.send(event);
+                self.resources.insert(events);
+            }
+        }
+    }
+
+    // The events sent since they were last taken or cleared, without taking
+    // them. Entities carrying T aren't included.
+    pub fn events<T: 'static>(&self) -> &[T] {
+        self.resources
+            .get::<Events<T>>()
+            .map(Events::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn clear_events<T: 'static>(&mut self) {
+        if let Some(events) = self.resources.get_mut::<Events<T>>() {
+            events.clear();
+        }
+    }
+
+    // Returns the events sent with send_event, then treats every entity
+    // carrying T as an event too: returns the components and despawns the
+    // entities, so only use it for types spawned on their own.
+    pub fn take_events<T: Clone + 'static>(&mut self) -> Vec<T> {
+        let mut events: Vec<T> = self
+            .resources
+            .get_mut::<Events<T>>()
+            .map(|events| events.drain().collect())
+            .unwrap_or_default();
+        let Some(index) = self.type_registry.get_index(TypeId::of::<T>()) else {
+            return events;
+        };
+
+        let mut entities = Vec::new();
+        for (_, archetype) in &self.archetypes {
+            if let Some(column) = archetype.get_column::<T>(index) {
+                events.extend_from_slice(column);
+                entities.extend_from_slice(&archetype.entities);
+            }
+        }
+        for entity in entities {
+            self.despawn(entity);
+        }
+        events
+    }
+
+    // Only components registered with register_serializable are written, the
+    // rest of an entity is dropped. Entities are stored in archetype order.
+    pub fn save_scene(&self) -> String {
+        let mut entities = Vec::new();
+        for (_, archetype) in &self.archetypes {
+            for row in 0..archetype.entities.len() {
+                let mut entity = serde_json::Map::new();
+                for (index, storage) in archetype.columns() {
+                    if let Some(serializer) = self.type_registry.serializer(index)
+                        && let Some(value) = serializer.serialize(storage, row)
+                    {
+                        entity.insert(serializer.name.to_string(), value);
+                    }
+                }
+                if !entity.is_empty() {
+                    entities.push(Value::Object(entity));
+                }
+            }
+        }
+
+        let mut scene = serde_json::Map::new();
+        scene.insert("entities".to_string(), Value::Array(entities));
+        serde_json::to_string_pretty(&Value::Object(scene)).expect("scene should serialize")
+    }
+
+    // The whole scene is deserialized before anything is spawned so a bad
+    // file leaves the world untouched.
+    pub fn load_scene(&mut self, scene: &str) -> Result<Vec<EntityId>, String> {
+        let scene: Value =
+            serde_json::from_str(scene).map_err(|err| format!("failed to parse scene {err}"))?;
+        let Some(Value::Array(entities)) = scene.get("entities") else {
+            return Err("scene is missing an entities array".to_string());
+        };
+
+        let mut spawns = Vec::with_capacity(entities.len());
+        for entity in entities {
+            let Value::Object(components) = entity else {
+                return Err(format!("scene entity {entity} is not an object"));
+            };
+
+            let mut component_indices = Vec::with_capacity(components.len());
+            let mut component_data = Vec::with_capacity(components.len());
+            for (name, value) in components {
+                let index = self
+                    .type_registry
+                    .serializable_index(name)
+                    .ok_or_else(|| format!("unknown scene component {name}"))?;
+                let serializer = self
+                    .type_registry
+                    .serializer(index)
+                    .expect("serializable index should have a serializer");
+                component_indices.push(index);
+                component_data.push(serializer.deserialize(value.clone())?);
+            }
+            spawns.push((component_indices, component_data));
+        }
+
+        Ok(spawns
+            .into_iter()
+            .map(|(component_indices, component_data)| {
+                self.spawn_components(component_indices, component_data)
+            })
+            .collect())
+    }
+
+    pub fn all_entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.archetypes
+            .iter()
+            .flat_map(|(_, archetype)| archetype.entities.iter().copied())
+    }
+
+    // Drops every entity and archetype. Indices are reused afterwards but with
+    // a newer generation, so ids from before the clear are no longer alive.
+    // Component registrations, resources, systems and observers are kept.
+    pub fn clear(&mut self) {
+        if !self.observers.is_empty() {
+            for (key, archetype) in &self.archetypes {
+                for &entity in &archetype.entities {
+                    notify_removed(&mut self.observers, entity, key.indices());
+                }
+            }
+        }
+        self.archetypes.clear();
+        self.archetype_lookup.clear();
+        self.entity_allocator.clear();
+        self.entity_location_map = EntityLocationMap::new();
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.archetypes
+            .iter()
+            .map(|(_, archetype)| archetype.entities.len())
+            .sum()
+    }
+
+    // Heap memory only, per archetype in creation order. Resources and the
+    // entity allocator aren't counted.
+    pub fn memory_report(&self) -> MemoryReport {
+        let archetypes = self
+            .archetypes
+            .iter()
+            .map(|(key, archetype)| ArchetypeMemory {
+                components: key
+                    .indices()
+                    .iter()
+                    .map(|&index| self.type_registry.type_name(index))
+                    .collect(),
+                entity_count: archetype.entities.len(),
+                component_bytes: archetype.column_bytes(),
+                bookkeeping_bytes: archetype.bookkeeping_bytes(),
+            })
+            .collect();
+        MemoryReport { archetypes }
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        self.entity_allocator.is_alive(entity)
+    }
+
+    // The location map is keyed by index only, so the generation has to be
+    // checked first or a despawned id reads whoever reused its index.
     pub fn get_component<T: 'static>(&self, entity: EntityId) -> Option<&T> {
-        let type_id = TypeId::of::<T>();
-        let index = self.type_registry.get_index(type_id).unwrap();
-
-        let (archetype_index, row) = self
-            .entity_location_map
-            .get(entity.index as usize)
-            .unwrap()
-            .as_ref()
-            .unwrap();
-        let (_, archetype) = &self.archetypes[*archetype_index];
+        if !self.entity_allocator.is_alive(entity) {
+            return None;
+        }
+        let index = self.type_registry.get_index(TypeId::of::<T>())?;
+
+        let (archetype_index, row) = self.entity_location_map.get(entity)?;
+        let (_, archetype) = &self.archetypes[archetype_index];
         archetype
             .get_column::<T>(index)
-            .and_then(|vec| vec.get(*row))
+            .and_then(|vec| vec.get(row))
+    }
+
+    // Overwrites a component the entity already has. Adding a new component
+    // type would need the entity to move archetypes, so that returns false.
+    pub fn set_component<T: 'static>(&mut self, entity: EntityId, value: T) -> bool {
+        if !self.entity_allocator.is_alive(entity) {
+            return false;
+        }
+        let Some(index) = self.type_registry.get_index(TypeId::of::<T>()) else {
+            return false;
+        };
+        let Some((archetype_index, row)) = self.entity_location_map.get(entity) else {
+            return false;
+        };
+
+        let (_, archetype) = &mut self.archetypes[archetype_index];
+        match archetype.get_component_mut::<T>(index, row) {
+            Some(component) => {
+                *component = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn extract_render_snapshot(&mut self, snapshot: &mut RenderSnapshot) {
+        snapshot.clear();
+
+        // In query order, walked directly to pick up each camera's entity.
+        if let (Some(camera_index), Some(position_index), Some(marker_index)) = (
+            self.type_registry.get_index(TypeId::of::<FpsCamera>()),
+            self.type_registry.get_index(TypeId::of::<Position>()),
+            self.type_registry.get_index(TypeId::of::<Camera>()),
+        ) {
+            for (_, archetype) in &self.archetypes {
+                let (Some(cameras), Some(positions), Some(_)) = (
+                    archetype.get_column::<FpsCamera>(camera_index),
+                    archetype.get_column::<Position>(position_index),
+                    archetype.get_column::<Camera>(marker_index),
+                ) else {
+                    continue;
+                };
+                snapshot
+                    .views
+                    .extend(cameras.iter().zip(positions).zip(&archetype.entities).map(
+                        |((camera, position), &entity)| CameraSnapshot {
+                            entity,
+                            camera: *camera,
+                            position: *position,
+                        },
+                    ));
+            }
+        }
+        snapshot.camera = snapshot.views.first().copied();
+
+        // Walks the archetypes directly rather than querying so skinned and
+        // unskinned renderables come out of the same pass in the same order.
+        // Renderables carry either a MeshHandle or a MeshId, ids that no
+        // longer resolve are skipped.
+        if let Some(transform_index) = self.type_registry.get_index(TypeId::of::<Transform>()) {
+            let mesh_index = self.type_registry.get_index(TypeId::of::<MeshHandle>());
+            let mesh_id_index = self.type_registry.get_index(TypeId::of::<MeshId>());
+            let bone_pose_index = self.type_registry.get_index(TypeId::of::<BonePose>());
+            let visible_index = self.type_registry.get_index(TypeId::of::<Visible>());
+            let bounds_index = self.type_registry.get_index(TypeId::of::<Bounds>());
+            let pipeline_index = self.type_registry.get_index(TypeId::of::<PipelineKey>());
+            let mesh_manager = self.resources.get::<MeshManager>();
+            for (_, archetype) in &self.archetypes {
+                let Some(transforms) = archetype.get_column::<Transform>(transform_index) else {
+                    continue;
+                };
+                let meshes = mesh_index.and_then(|index| archetype.get_column::<MeshHandle>(index));
+                let mesh_ids =
+                    mesh_id_index.and_then(|index| archetype.get_column::<MeshId>(index));
+                if meshes.is_none() && mesh_ids.is_none() {
+                    continue;
+                }
+                let bone_poses =
+                    bone_pose_index.and_then(|index| archetype.get_column::<BonePose>(index));
+                let visible =
+                    visible_index.and_then(|index| archetype.get_column::<Visible>(index));
+                let bounds = bounds_index.and_then(|index| archetype.get_column::<Bounds>(index));
+                let pipelines =
+                    pipeline_index.and_then(|index| archetype.get_column::<PipelineKey>(index));
+
+                for (row, transform) in transforms.iter().enumerate() {
+                    if visible.is_some_and(|visible| !visible[row].0) {
+                        continue;
+                    }
+                    let mesh = match (meshes, mesh_ids) {
+                        (Some(meshes), _) => Some(meshes[row]),
+                        (None, Some(mesh_ids)) => {
+                            mesh_manager.and_then(|manager| manager.get(mesh_ids[row]))
+                        }
+                        (None, None) => None,
+                    };
+                    let Some(mesh) = mesh else {
+                        continue;
+                    };
+                    snapshot.renderables.push((*transform, mesh));
+                    snapshot.entities.push(archetype.entities[row]);
+                    let bone_range = match bone_poses.map(|poses| &poses[row]) {
+                        Some(pose) => {
+                            let range = BoneRange {
+                                offset: snapshot.bone_palette.len() as u32,
+                                count: pose.matrices.len() as u32,
+                            };
+                            snapshot.bone_palette.extend_from_slice(&pose.matrices);
+                            range
+                        }
+                        None => BoneRange::default(),
+                    };
+                    snapshot.bone_ranges.push(bone_range);
+                    snapshot.bounds.push(bounds.map(|bounds| bounds[row].0));
+                    snapshot
+                        .pipelines
+                        .push(pipelines.map_or(PipelineKey::DEFAULT, |pipelines| pipelines[row]));
+                }
+            }
+        }
+
+        snapshot
+            .lights
+            .extend(self.query::<(&DirectionalLight,)>().copied());
+
+        for emitter in self.query::<(&ParticleEmitter,)>() {
+            let lifetime = emitter.lifetime.max(f32::EPSILON);
+            snapshot
+                .particles
+                .extend(emitter.particles().iter().map(|particle| ParticleSnapshot {
+                    position: particle.position,
+                    size: emitter.size,
+                    life: particle.age / lifetime,
+                }));
+        }
+    }
+
+    pub fn pick(&self, ray: &Ray) -> Option<EntityId> {
+        let transform_index = self.type_registry.get_index(TypeId::of::<Transform>())?;
+        let bounds_index = self.type_registry.get_index(TypeId::of::<Bounds>())?;
+
+        let mut nearest: Option<(f32, EntityId)> = None;
+        for (_, archetype) in &self.archetypes {
+            let (Some(transforms), Some(bounds)) = (
+                archetype.get_column::<Transform>(transform_index),
+                archetype.get_column::<Bounds>(bounds_index),
+            ) else {
+                continue;
+            };
+
+            for ((entity, transform), bounds) in
+                archetype.entities.iter().zip(transforms).zip(bounds)
+            {
+                let world_bounds = bounds.0.transform(&transform.0);
+                if let Some(distance) = ray.intersect_aabb(&world_bounds)
+                    && nearest.is_none_or(|(nearest_distance, _)| distance < nearest_distance)
+                {
+                    nearest = Some((distance, *entity));
+                }
+            }
+        }
+        nearest.map(|(_, entity)| entity)
+    }
+
+    // Writes from now on get a newer stamp than anything written so far.
+    // Returns the tick that was current until now.
+    fn increment_change_tick(&mut self) -> u32 {
+        let previous = self.change_tick;
+        self.change_tick += 1;
+        for (_, archetype) in &mut self.archetypes {
+            archetype.set_tick(self.change_tick);
+        }
+        previous
     }
 
     fn find_or_create_archetype(
@@ -82,15 +732,16 @@ impl World {
         key: &ArchetypeKey,
         component_indices: &[usize],
     ) -> usize {
-        for (i, arch) in self.archetypes.iter().enumerate() {
-            if &arch.0 == key {
-                return i;
-            }
+        if let Some(&index) = self.archetype_lookup.get(key) {
+            return index;
         }
 
-        let new_arch = Archetype::new(component_indices, &self.type_registry);
+        let mut new_arch = Archetype::new(component_indices, &self.type_registry);
+        new_arch.set_tick(self.change_tick);
         self.archetypes.push((key.clone(), new_arch));
-        self.archetypes.len() - 1
+        let index = self.archetypes.len() - 1;
+        self.archetype_lookup.insert(key.clone(), index);
+        index
     }
 
     pub fn query<'world, Q>(&'world mut self) -> impl Iterator<Item = Q::Item>
@@ -100,6 +751,76 @@ impl World {
         self.archetypes
             .iter_mut()
             .filter_map(|(_, archetype)| Q::query_archetype(archetype, &self.type_registry))
-            .flat_map(|it| it)
+            .flatten()
+    }
+
+    // Same as query, but each archetype yields its entities in ascending id
+    // order instead of row order, which despawns shuffle. Archetypes still come
+    // in creation order. Collects each archetype's items, so only use it where
+    // determinism matters more than speed.
+    pub fn query_sorted<'world, Q>(&'world mut self) -> impl Iterator<Item = Q::Item>
+    where
+        Q: Query<'world>,
+    {
+        let type_registry = &self.type_registry;
+        self.archetypes
+            .iter_mut()
+            .filter_map(move |(_, archetype)| {
+                let mut order: Vec<usize> = (0..archetype.entities.len()).collect();
+                order.sort_unstable_by_key(|&row| archetype.entities[row].index);
+
+                let mut items: Vec<Option<Q::Item>> = Q::query_archetype(archetype, type_registry)?
+                    .map(Some)
+                    .collect();
+                Some(order.into_iter().filter_map(move |row| items[row].take()))
+            })
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Hp(u32);
+
+    #[test]
+    fn stale_id_does_not_reach_the_entity_reusing_its_index() {
+        let mut world = World::new();
+        let stale = world.spawn((Hp(1),));
+        assert!(world.despawn(stale));
+        let reused = world.spawn((Hp(2),));
+        assert_eq!(reused.index, stale.index);
+
+        assert_eq!(world.get_component::<Hp>(stale), None);
+        assert!(!world.set_component(stale, Hp(3)));
+        assert!(!world.despawn(stale));
+
+        assert!(world.is_alive(reused));
+        assert_eq!(world.get_component::<Hp>(reused), Some(&Hp(2)));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Armor(u32);
+
+    #[test]
+    fn clear_empties_the_world_and_retires_old_ids() {
+        let mut world = World::new();
+        let old = world.spawn((Hp(1),));
+        world.spawn((Hp(2), Armor(1)));
+        world.spawn((Armor(2),));
+        assert_eq!(world.all_entities().count(), 3);
+
+        world.clear();
+        assert_eq!(world.all_entities().count(), 0);
+        assert_eq!(world.entity_count(), 0);
+        assert!(!world.is_alive(old));
+
+        let new = world.spawn((Hp(3),));
+        assert_eq!(new.index, old.index);
+        assert!(!world.is_alive(old));
+        assert_eq!(world.get_component::<Hp>(old), None);
+        assert_eq!(world.get_component::<Hp>(new), Some(&Hp(3)));
     }
 }
@@ -0,0 +1,284 @@
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for point in points {
+            min = min.min(*point);
+            max = max.max(*point);
+        }
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    // Boxes that only touch along a face don't count as intersecting.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && other.min.cmplt(self.max).all()
+    }
+
+    // Arvo's method: transform the center and project the extents onto the
+    // absolute rotation/scale so the result stays tight for rotated boxes.
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        let extents = matrix.x_axis.truncate().abs() * half_extents.x
+            + matrix.y_axis.truncate().abs() * half_extents.y
+            + matrix.z_axis.truncate().abs() * half_extents.z;
+        Self {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_vec4(v: Vec4) -> Self {
+        let normal = v.truncate();
+        let length = normal.length();
+        Self {
+            normal: normal / length,
+            distance: v.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Gribb/Hartmann plane extraction. wgpu clip space has z in [0, w], so the
+    // near plane is the third row on its own rather than row3 + row2.
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let row0 = view_proj.row(0);
+        let row1 = view_proj.row(1);
+        let row2 = view_proj.row(2);
+        let row3 = view_proj.row(3);
+
+        Self {
+            planes: [
+                Plane::from_vec4(row3 + row0),
+                Plane::from_vec4(row3 - row0),
+                Plane::from_vec4(row3 + row1),
+                Plane::from_vec4(row3 - row1),
+                Plane::from_vec4(row2),
+                Plane::from_vec4(row3 - row2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let positive_vertex = Vec3::select(plane.normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.signed_distance(positive_vertex) >= 0.0
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    // Unprojects the cursor onto the near (z = 0) and far (z = 1) planes of
+    // wgpu clip space. Screen y grows downwards, NDC y grows upwards.
+    pub fn from_screen(cursor: Vec2, viewport_size: Vec2, inverse_view_proj: Mat4) -> Self {
+        let ndc = Vec2::new(
+            2.0 * cursor.x / viewport_size.x - 1.0,
+            1.0 - 2.0 * cursor.y / viewport_size.y,
+        );
+        let near = inverse_view_proj.project_point3(ndc.extend(0.0));
+        let far = inverse_view_proj.project_point3(ndc.extend(1.0));
+        Self::new(near, far - near)
+    }
+
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    // Slab test, returns the distance along the ray to the first hit. An
+    // axis the ray runs parallel to is checked directly, dividing by its zero
+    // component would give 0 * inf = NaN for an origin on the slab's plane.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut t_enter = 0.0_f32;
+        let mut t_exit = f32::INFINITY;
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            let (min, max) = (aabb.min[axis], aabb.max[axis]);
+            let direction = self.direction[axis];
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min - origin) / direction;
+            let t1 = (max - origin) / direction;
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        (t_exit >= t_enter).then_some(t_enter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        }
+    }
+
+    #[test]
+    fn transformed_box_stays_tight() {
+        let matrix = Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0))
+            * Mat4::from_rotation_y(45_f32.to_radians());
+        let aabb = unit_box().transform(&matrix);
+
+        let diagonal = 2_f32.sqrt();
+        let expected = Aabb::new(
+            Vec3::new(10.0 - diagonal, -1.0, -diagonal),
+            Vec3::new(10.0 + diagonal, 1.0, diagonal),
+        );
+        assert!(aabb.min.abs_diff_eq(expected.min, 1e-5));
+        assert!(aabb.max.abs_diff_eq(expected.max, 1e-5));
+    }
+
+    #[test]
+    fn boxes_touching_on_a_face_do_not_intersect() {
+        let shifted =
+            |x: f32| Aabb::new(Vec3::new(x - 1.0, -1.0, -1.0), Vec3::new(x + 1.0, 1.0, 1.0));
+        assert!(unit_box().intersects(&shifted(1.5)));
+        assert!(!unit_box().intersects(&shifted(2.0)));
+        assert!(!unit_box().intersects(&shifted(5.0)));
+    }
+
+    #[test]
+    fn frustum_near_plane_uses_zero_to_one_depth() {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let projection = Mat4::perspective_rh(90_f32.to_radians(), 1.0, 1.0, 10.0);
+        let frustum = Frustum::from_view_proj(projection * view);
+
+        // A -1..1 extraction would put the near plane behind the camera and
+        // keep the point at z = -0.5.
+        assert!(
+            frustum.planes[4]
+                .signed_distance(Vec3::new(0.0, 0.0, -1.0))
+                .abs()
+                < 1e-5
+        );
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.5)));
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -9.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -10.5)));
+
+        let at = |center: Vec3| Aabb::new(center - 0.5, center + 0.5);
+        assert!(frustum.intersects_aabb(&at(Vec3::new(0.0, 0.0, -1.0))));
+        assert!(!frustum.intersects_aabb(&at(Vec3::new(0.0, 0.0, 5.0))));
+        assert!(!frustum.intersects_aabb(&at(Vec3::new(20.0, 0.0, -5.0))));
+    }
+
+    #[test]
+    fn ray_hits_the_near_face() {
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(4.0));
+
+        let inside = Ray::new(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(inside.intersect_aabb(&unit_box()), Some(0.0));
+
+        let behind = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(behind.intersect_aabb(&unit_box()), None);
+    }
+
+    #[test]
+    fn axis_parallel_ray_on_a_slab_plane() {
+        // y and z are zero in the direction and y sits exactly on the top
+        // face, which used to compute 0 * inf.
+        let on_plane = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::X);
+        assert_eq!(on_plane.intersect_aabb(&unit_box()), Some(4.0));
+
+        let above = Ray::new(Vec3::new(-5.0, 1.5, 0.0), Vec3::X);
+        assert_eq!(above.intersect_aabb(&unit_box()), None);
+    }
+
+    #[test]
+    fn screen_ray_through_center_and_corner() {
+        let eye = Vec3::new(0.0, 0.0, 5.0);
+        let view = Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y);
+        let projection = Mat4::perspective_rh(90_f32.to_radians(), 1.0, 0.1, 100.0);
+        let inverse_view_proj = (projection * view).inverse();
+        let viewport = Vec2::splat(800.0);
+
+        let center = Ray::from_screen(Vec2::splat(400.0), viewport, inverse_view_proj);
+        assert!(center.origin.abs_diff_eq(Vec3::new(0.0, 0.0, 4.9), 1e-4));
+        assert!(center.direction.abs_diff_eq(Vec3::NEG_Z, 1e-4));
+        assert_eq!(
+            center.intersect_aabb(&unit_box()).map(f32::round),
+            Some(4.0)
+        );
+
+        // Screen y grows downwards, so the top left corner looks up and left.
+        let corner = Ray::from_screen(Vec2::ZERO, viewport, inverse_view_proj);
+        let expected = Vec3::new(-1.0, 1.0, -1.0).normalize();
+        assert!(corner.direction.abs_diff_eq(expected, 1e-4));
+    }
+}
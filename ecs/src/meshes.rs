@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use crate::components::MeshHandle;
+
+// A renderable's mesh, looked up in the world's MeshManager when the render
+// snapshot is extracted. Unlike a MeshHandle it stops resolving once the mesh
+// is removed, even if the slot has been reused since.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MeshId {
+    pub index: u32,
+    generation: u32,
+}
+
+impl MeshId {
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Debug, Default)]
+struct MeshSlot {
+    generation: u32,
+    handle: Option<MeshHandle>,
+    // Reserved for a mesh that is still loading.
+    pending: bool,
+}
+
+// Generational arena of uploaded meshes. Removing a mesh only frees its id,
+// the allocator still owns the vertex and index ranges it points at.
+#[derive(Debug, Default)]
+pub struct MeshManager {
+    slots: Vec<MeshSlot>,
+    free_list: Vec<u32>,
+}
+
+impl MeshManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, handle: MeshHandle) -> MeshId {
+        self.allocate(Some(handle))
+    }
+
+    // An id for a mesh that hasn't been uploaded yet. It doesn't resolve until
+    // `resolve` gives it a handle, and can be removed like any other.
+    pub fn reserve(&mut self) -> MeshId {
+        self.allocate(None)
+    }
+
+    // Returns false if `id` isn't a pending reservation, e.g. it was removed
+    // while its mesh was loading.
+    pub fn resolve(&mut self, id: MeshId, handle: MeshHandle) -> bool {
+        let Some(slot) = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.pending)
+        else {
+            return false;
+        };
+        slot.pending = false;
+        slot.handle = Some(handle);
+        true
+    }
+
+    pub fn is_pending(&self, id: MeshId) -> bool {
+        self.slots
+            .get(id.index as usize)
+            .is_some_and(|slot| slot.generation == id.generation && slot.pending)
+    }
+
+    fn allocate(&mut self, handle: Option<MeshHandle>) -> MeshId {
+        let pending = handle.is_none();
+        if let Some(index) = self.free_list.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.handle = handle;
+            slot.pending = pending;
+            MeshId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            let index = self.slots.len() as u32;
+            self.slots.push(MeshSlot {
+                generation: 0,
+                handle,
+                pending,
+            });
+            MeshId {
+                index,
+                generation: 0,
+            }
+        }
+    }
+
+    pub fn get(&self, id: MeshId) -> Option<MeshHandle> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.handle)
+    }
+
+    // Bumps the slot's generation so every copy of `id` stops resolving.
+    // Removing a pending id frees it and returns None.
+    pub fn remove(&mut self, id: MeshId) -> Option<MeshHandle> {
+        let slot = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)?;
+        if slot.handle.is_none() && !slot.pending {
+            return None;
+        }
+        let handle = slot.handle.take();
+        slot.pending = false;
+        slot.generation += 1;
+        self.free_list.push(id.index);
+        handle
+    }
+
+    pub fn contains(&self, id: MeshId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(vertex_offset: u64) -> MeshHandle {
+        MeshHandle {
+            vertex_offset,
+            index_offset: 0,
+            vertex_count: 3,
+            index_count: 3,
+            stream: 0,
+        }
+    }
+
+    #[test]
+    fn removed_ids_stop_resolving_and_their_slot_gets_a_new_generation() {
+        let mut meshes = MeshManager::new();
+        let first = meshes.insert(handle(0));
+        let second = meshes.insert(handle(64));
+        assert_eq!(meshes.get(first), Some(handle(0)));
+        assert_eq!(meshes.len(), 2);
+
+        assert_eq!(meshes.remove(first), Some(handle(0)));
+        assert_eq!(meshes.get(first), None);
+        assert_eq!(meshes.remove(first), None);
+
+        let reused = meshes.insert(handle(128));
+        assert_eq!(reused.index, first.index);
+        assert_eq!(reused.generation(), first.generation() + 1);
+        assert_eq!(meshes.get(first), None);
+        assert_eq!(meshes.get(reused), Some(handle(128)));
+        assert_eq!(meshes.get(second), Some(handle(64)));
+        assert_eq!(meshes.len(), 2);
+    }
+
+    #[test]
+    fn reserved_ids_resolve_once_unless_removed_first() {
+        let mut meshes = MeshManager::new();
+        let loading = meshes.reserve();
+        assert!(meshes.is_pending(loading));
+        assert_eq!(meshes.get(loading), None);
+
+        assert!(meshes.resolve(loading, handle(0)));
+        assert!(!meshes.is_pending(loading));
+        assert_eq!(meshes.get(loading), Some(handle(0)));
+        assert!(!meshes.resolve(loading, handle(64)));
+
+        // Removed while loading, the upload finishing later is dropped.
+        let cancelled = meshes.reserve();
+        assert_eq!(meshes.remove(cancelled), None);
+        assert!(!meshes.is_pending(cancelled));
+        assert!(!meshes.resolve(cancelled, handle(64)));
+        assert_eq!(meshes.len(), 1);
+    }
+}
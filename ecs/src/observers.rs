@@ -0,0 +1,26 @@
+use crate::entities::EntityId;
+
+type ObserverCallback = Box<dyn FnMut(EntityId) + Send>;
+
+// Callbacks for one component type, see World::add_observer.
+pub(crate) struct Observer {
+    pub component: usize,
+    pub on_add: ObserverCallback,
+    pub on_remove: ObserverCallback,
+}
+
+pub(crate) fn notify_added(observers: &mut [Observer], entity: EntityId, components: &[usize]) {
+    for observer in observers {
+        if components.contains(&observer.component) {
+            (observer.on_add)(entity);
+        }
+    }
+}
+
+pub(crate) fn notify_removed(observers: &mut [Observer], entity: EntityId, components: &[usize]) {
+    for observer in observers {
+        if components.contains(&observer.component) {
+            (observer.on_remove)(entity);
+        }
+    }
+}
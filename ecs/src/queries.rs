@@ -1,3 +1,5 @@
+use std::ops::{Deref, DerefMut};
+
 use crate::archetypes::Archetype;
 use crate::components::ComponentTypeIndexRegistry;
 
@@ -7,6 +9,15 @@ use ecs_macros::impl_query_combinations;
 
 impl_query_combinations!(crate);
 
+// A query spelled with any lifetimes, e.g. `(&mut Position, &Velocity)` in a
+// system's signature, and the same query borrowing for 'world. `access` lists
+// each component's TypeId and name and whether it's borrowed mutably.
+pub trait QueryData {
+    type Fetch<'world>: Query<'world> + QueryData;
+
+    fn access() -> Vec<(std::any::TypeId, &'static str, bool)>;
+}
+
 pub trait Query<'world> {
     type Item;
 
@@ -15,3 +26,62 @@ pub trait Query<'world> {
         registry: &ComponentTypeIndexRegistry,
     ) -> Option<Box<dyn Iterator<Item = Self::Item> + 'world>>;
 }
+
+// A component borrowed mutably by a query. Reading it leaves the row alone,
+// writing through it stamps the row's change tick, so change detection only
+// sees the rows that were written.
+pub struct Mut<'world, T> {
+    value: &'world mut T,
+    changed_at: &'world mut u32,
+    tick: u32,
+}
+
+impl<'world, T> Mut<'world, T> {
+    pub(crate) fn new(value: &'world mut T, changed_at: &'world mut u32, tick: u32) -> Self {
+        Self {
+            value,
+            changed_at,
+            tick,
+        }
+    }
+
+    // Marks the row changed, for handing the reference on.
+    pub fn into_inner(self) -> &'world mut T {
+        *self.changed_at = self.tick;
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        *self.changed_at = self.tick;
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat4;
+
+    use crate::{World, components::Transform};
+
+    // Debug builds panic, release builds skip the archetype instead.
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        should_panic(expected = "query borrows a component mutably and also borrows it again")
+    )]
+    fn mutable_borrow_aliasing_another_borrow_is_rejected() {
+        let mut world = World::new();
+        world.spawn((Transform(Mat4::IDENTITY),));
+        assert_eq!(world.query::<(&Transform, &mut Transform)>().count(), 0);
+    }
+}
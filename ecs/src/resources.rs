@@ -0,0 +1,128 @@
+use std::{
+    any::{Any, TypeId},
+    time::Duration,
+};
+
+// Singleton values that belong to the world rather than to an entity, e.g.
+// settings or an rng. There is at most one value per type.
+#[derive(Default)]
+pub struct Resources {
+    values: Vec<(TypeId, Box<dyn Any + Send + Sync>)>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    // Replaces and returns the previous value of the same type, if any.
+    pub fn insert<R: Any + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        let type_id = TypeId::of::<R>();
+        match self.values.iter_mut().find(|(id, _)| *id == type_id) {
+            Some((_, value)) => std::mem::replace(value, Box::new(resource))
+                .downcast::<R>()
+                .ok()
+                .map(|previous| *previous),
+            None => {
+                self.values.push((type_id, Box::new(resource)));
+                None
+            }
+        }
+    }
+
+    pub fn get<R: Any>(&self) -> Option<&R> {
+        let type_id = TypeId::of::<R>();
+        self.values
+            .iter()
+            .find(|(id, _)| *id == type_id)
+            .and_then(|(_, value)| value.downcast_ref::<R>())
+    }
+
+    pub fn get_mut<R: Any>(&mut self) -> Option<&mut R> {
+        let type_id = TypeId::of::<R>();
+        self.values
+            .iter_mut()
+            .find(|(id, _)| *id == type_id)
+            .and_then(|(_, value)| value.downcast_mut::<R>())
+    }
+
+    pub fn remove<R: Any>(&mut self) -> Option<R> {
+        let type_id = TypeId::of::<R>();
+        let index = self.values.iter().position(|(id, _)| *id == type_id)?;
+        self.values
+            .swap_remove(index)
+            .1
+            .downcast::<R>()
+            .ok()
+            .map(|resource| *resource)
+    }
+
+    pub fn contains<R: Any>(&self) -> bool {
+        self.get::<R>().is_some()
+    }
+}
+
+// Events of one type sent with World::send_event, kept until taken or
+// cleared. Lives in the resources so sending one doesn't spawn an entity.
+#[derive(Debug, Clone)]
+pub struct Events<T> {
+    events: Vec<T>,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.events.push(event);
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.events
+    }
+
+    // Keeps the allocation for the next tick's events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.events.drain(..)
+    }
+}
+
+// Kept up to date by run_systems for systems that take `Res<Time>`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Time {
+    pub delta_time: f32,
+    pub elapsed: f32,
+    pub ticks: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SystemTiming {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+// The last tick's systems in the order they ran, see World::set_system_timing.
+#[derive(Debug, Clone, Default)]
+pub struct SystemStats {
+    pub timings: Vec<SystemTiming>,
+}
+
+impl SystemStats {
+    pub fn get(&self, name: &str) -> Option<Duration> {
+        self.timings
+            .iter()
+            .find(|timing| timing.name == name)
+            .map(|timing| timing.duration)
+    }
+
+    pub fn total(&self) -> Duration {
+        self.timings.iter().map(|timing| timing.duration).sum()
+    }
+}
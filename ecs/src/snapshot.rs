@@ -0,0 +1,93 @@
+use glam::{Mat4, Vec3};
+
+use crate::{
+    components::{DirectionalLight, FpsCamera, MeshHandle, PipelineKey, Position, Transform},
+    entities::EntityId,
+    math::Aabb,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSnapshot {
+    pub entity: EntityId,
+    pub camera: FpsCamera,
+    pub position: Position,
+}
+
+// Where a renderable's joints start in the bone palette, count 0 means the
+// mesh isn't skinned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoneRange {
+    pub offset: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleSnapshot {
+    pub position: Vec3,
+    pub size: f32,
+    // How far through its lifetime, 0 when spawned and 1 when it expires.
+    pub life: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderSnapshot {
+    // The first of `views`, the camera the main pass draws with.
+    pub camera: Option<CameraSnapshot>,
+    // Every active camera in query order, uploaded as view 0, 1, ...
+    pub views: Vec<CameraSnapshot>,
+    pub renderables: Vec<(Transform, MeshHandle)>,
+    // Parallel to renderables.
+    pub entities: Vec<EntityId>,
+    // Parallel to renderables.
+    pub bone_ranges: Vec<BoneRange>,
+    // Parallel to renderables, local space. None for entities without Bounds.
+    pub bounds: Vec<Option<Aabb>>,
+    // Parallel to renderables.
+    pub pipelines: Vec<PipelineKey>,
+    pub bone_palette: Vec<Mat4>,
+    pub lights: Vec<DirectionalLight>,
+    // Every live particle of every emitter.
+    pub particles: Vec<ParticleSnapshot>,
+}
+
+impl RenderSnapshot {
+    pub fn clear(&mut self) {
+        self.camera = None;
+        self.views.clear();
+        self.renderables.clear();
+        self.entities.clear();
+        self.bone_ranges.clear();
+        self.bounds.clear();
+        self.pipelines.clear();
+        self.bone_palette.clear();
+        self.lights.clear();
+        self.particles.clear();
+    }
+
+    // Where `entity` ended up in `views`, None if it isn't an active camera.
+    pub fn view_index(&self, entity: EntityId) -> Option<usize> {
+        self.views.iter().position(|view| view.entity == entity)
+    }
+}
+
+// Front is what the renderer reads, back is what the next extraction writes.
+// Both slots keep their allocations so steady-state extraction doesn't allocate.
+#[derive(Debug, Default)]
+pub struct SnapshotBuffer {
+    snapshots: [RenderSnapshot; 2],
+    front: usize,
+}
+
+impl SnapshotBuffer {
+    pub fn front(&self) -> &RenderSnapshot {
+        &self.snapshots[self.front]
+    }
+
+    pub fn back_mut(&mut self) -> &mut RenderSnapshot {
+        &mut self.snapshots[1 - self.front]
+    }
+
+    pub fn swap(&mut self) {
+        self.front = 1 - self.front;
+    }
+}
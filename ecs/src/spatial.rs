@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use glam::IVec3;
+
+use crate::math::Aabb;
+
+// A box overlapping more cells than this is kept out of the grid and paired
+// with everything instead, so one huge box can't insert thousands of cells.
+pub const MAX_CELLS_PER_BOX: i64 = 64;
+
+// Uniform grid broad phase. Every box is stored in each cell it overlaps, so
+// two boxes can only intersect if they share a cell.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<usize>>,
+    inserted: Vec<usize>,
+    oversized: Vec<usize>,
+}
+
+impl SpatialHash {
+    // A cell size that isn't a positive number falls back to 1.
+    pub fn new(cell_size: f32) -> Self {
+        let cell_size = if cell_size.is_finite() && cell_size > 0.0 {
+            cell_size
+        } else {
+            1.0
+        };
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            inserted: Vec::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, point: glam::Vec3) -> IVec3 {
+        (point / self.cell_size).floor().as_ivec3()
+    }
+
+    // Boxes that aren't finite are ignored, their cell range would be
+    // unbounded.
+    pub fn insert(&mut self, index: usize, aabb: &Aabb) {
+        if !(aabb.min.is_finite() && aabb.max.is_finite()) {
+            return;
+        }
+        self.inserted.push(index);
+        let min = self.cell(aabb.min);
+        let max = self.cell(aabb.max);
+        let cell_count = (max.as_i64vec3() - min.as_i64vec3() + 1).element_product();
+        if cell_count > MAX_CELLS_PER_BOX {
+            self.oversized.push(index);
+            return;
+        }
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.cells
+                        .entry(IVec3::new(x, y, z))
+                        .or_default()
+                        .push(index);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.inserted.clear();
+        self.oversized.clear();
+    }
+
+    // Pairs of inserted indices sharing at least one cell, each as (lower,
+    // higher) and listed once, in ascending order.
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for indices in self.cells.values() {
+            for (i, &a) in indices.iter().enumerate() {
+                for &b in &indices[i + 1..] {
+                    pairs.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+        for &a in &self.oversized {
+            for &b in &self.inserted {
+                if a != b {
+                    pairs.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn aabb(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    #[test]
+    fn pairs_sharing_many_cells_are_listed_once() {
+        let mut spatial_hash = SpatialHash::new(1.0);
+        spatial_hash.insert(3, &aabb(Vec3::ZERO, Vec3::splat(2.5)));
+        spatial_hash.insert(1, &aabb(Vec3::splat(0.5), Vec3::splat(2.0)));
+        spatial_hash.insert(2, &aabb(Vec3::splat(10.0), Vec3::splat(11.0)));
+
+        assert_eq!(spatial_hash.candidate_pairs(), [(1, 3)]);
+    }
+
+    #[test]
+    fn oversized_boxes_pair_with_everything() {
+        let mut spatial_hash = SpatialHash::new(1.0);
+        spatial_hash.insert(0, &aabb(Vec3::splat(-1000.0), Vec3::splat(1000.0)));
+        spatial_hash.insert(1, &aabb(Vec3::ZERO, Vec3::splat(0.5)));
+        spatial_hash.insert(2, &aabb(Vec3::splat(5.0), Vec3::splat(5.5)));
+
+        assert_eq!(spatial_hash.candidate_pairs(), [(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn non_finite_boxes_are_ignored() {
+        let mut spatial_hash = SpatialHash::new(f32::NAN);
+        assert_eq!(spatial_hash.cell_size(), 1.0);
+        spatial_hash.insert(0, &aabb(Vec3::splat(f32::NAN), Vec3::ZERO));
+        spatial_hash.insert(1, &aabb(Vec3::ZERO, Vec3::splat(f32::INFINITY)));
+        spatial_hash.insert(2, &aabb(Vec3::ZERO, Vec3::ONE));
+
+        assert!(spatial_hash.candidate_pairs().is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+// Estimated heap memory of a world's component storage, for profilers and
+// deciding when a scene is worth compacting.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    pub archetypes: Vec<ArchetypeMemory>,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.archetypes
+            .iter()
+            .map(ArchetypeMemory::total_bytes)
+            .sum()
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.archetypes
+            .iter()
+            .map(|archetype| archetype.entity_count)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeMemory {
+    pub components: Vec<&'static str>,
+    pub entity_count: usize,
+    // Column capacity times component size, summed over the columns.
+    pub component_bytes: usize,
+    // The entity list and per row change ticks.
+    pub bookkeeping_bytes: usize,
+}
+
+impl ArchetypeMemory {
+    pub fn total_bytes(&self) -> usize {
+        self.component_bytes + self.bookkeeping_bytes
+    }
+}
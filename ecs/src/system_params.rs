@@ -0,0 +1,320 @@
+use std::{
+    any::{Any, TypeId, type_name},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{World, queries::QueryData};
+
+// What one system parameter borrows from the world. Two parameters of the
+// same system conflict when they touch the same component or resource and
+// at least one of them writes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub resource: bool,
+    pub write: bool,
+}
+
+impl Access {
+    fn conflicts_with(&self, other: &Access) -> bool {
+        self.type_id == other.type_id
+            && self.resource == other.resource
+            && (self.write || other.write)
+    }
+}
+
+// A value a system can take as an argument, fetched from the world each
+// time the system runs.
+pub trait SystemParam {
+    type Item<'world>;
+
+    fn access() -> Vec<Access>;
+
+    // A Query borrows every archetype mutably while it iterates, so a system
+    // can only take one.
+    fn is_query() -> bool {
+        false
+    }
+
+    // Returns None when something the parameter needs is missing, e.g. a
+    // resource that was never inserted.
+    //
+    // # Safety
+    // `world` must be valid for 'world and nothing else may hold a borrow
+    // that conflicts with `access` while the item is alive.
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn fetch<'world>(world: *mut World) -> Option<Self::Item<'world>>;
+}
+
+// Iterates every entity matching Q, e.g. `Query<(&mut Position, &Velocity)>`.
+pub struct Query<'world, Q: QueryData> {
+    world: *mut World,
+    _marker: PhantomData<(&'world mut World, Q)>,
+}
+
+impl<Q: QueryData> Query<'_, Q> {
+    pub fn iter(
+        &mut self,
+    ) -> impl Iterator<Item = <Q::Fetch<'_> as crate::queries::Query<'_>>::Item> + '_ {
+        // Only the archetypes and registry are borrowed so Res parameters can
+        // hold on to the resources at the same time. Systems are limited to
+        // one Query, so nothing else borrows the archetypes meanwhile.
+        let (archetypes, type_registry) =
+            unsafe { (&mut (*self.world).archetypes, &(*self.world).type_registry) };
+        archetypes
+            .iter_mut()
+            .filter_map(move |(_, archetype)| {
+                <Q::Fetch<'_> as crate::queries::Query<'_>>::query_archetype(
+                    archetype,
+                    type_registry,
+                )
+            })
+            .flatten()
+    }
+}
+
+impl<Q: QueryData> SystemParam for Query<'_, Q> {
+    type Item<'world> = Query<'world, Q::Fetch<'world>>;
+
+    fn access() -> Vec<Access> {
+        Q::access()
+            .into_iter()
+            .map(|(type_id, type_name, write)| Access {
+                type_id,
+                type_name,
+                resource: false,
+                write,
+            })
+            .collect()
+    }
+
+    fn is_query() -> bool {
+        true
+    }
+
+    unsafe fn fetch<'world>(world: *mut World) -> Option<Self::Item<'world>> {
+        Some(Query {
+            world,
+            _marker: PhantomData,
+        })
+    }
+}
+
+pub struct Res<'world, R: Any>(&'world R);
+
+impl<R: Any> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.0
+    }
+}
+
+impl<R: Any> SystemParam for Res<'_, R> {
+    type Item<'world> = Res<'world, R>;
+
+    fn access() -> Vec<Access> {
+        vec![Access {
+            type_id: TypeId::of::<R>(),
+            type_name: type_name::<R>(),
+            resource: true,
+            write: false,
+        }]
+    }
+
+    unsafe fn fetch<'world>(world: *mut World) -> Option<Self::Item<'world>> {
+        unsafe { (*world).resources.get::<R>() }.map(Res)
+    }
+}
+
+pub struct ResMut<'world, R: Any>(&'world mut R);
+
+impl<R: Any> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.0
+    }
+}
+
+impl<R: Any> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.0
+    }
+}
+
+impl<R: Any> SystemParam for ResMut<'_, R> {
+    type Item<'world> = ResMut<'world, R>;
+
+    fn access() -> Vec<Access> {
+        vec![Access {
+            type_id: TypeId::of::<R>(),
+            type_name: type_name::<R>(),
+            resource: true,
+            write: true,
+        }]
+    }
+
+    unsafe fn fetch<'world>(world: *mut World) -> Option<Self::Item<'world>> {
+        unsafe { (*world).resources.get_mut::<R>() }.map(ResMut)
+    }
+}
+
+pub trait System: Send {
+    fn name(&self) -> &'static str;
+
+    fn access(&self) -> &[Access];
+
+    fn query_count(&self) -> usize;
+
+    // Returns false when a parameter couldn't be fetched and the system was
+    // skipped.
+    fn run(&mut self, world: &mut World) -> bool;
+}
+
+pub type BoxedSystem = Box<dyn System>;
+
+pub trait IntoSystem<Params> {
+    fn into_system(self) -> BoxedSystem;
+}
+
+// Checked once when the system is added rather than on every run.
+pub fn validate_access(name: &str, access: &[Access], query_count: usize) -> Result<(), String> {
+    if query_count > 1 {
+        return Err(format!(
+            "system {name} takes {query_count} Query parameters, only one is supported"
+        ));
+    }
+    for (i, first) in access.iter().enumerate() {
+        if access[i + 1..]
+            .iter()
+            .any(|second| first.conflicts_with(second))
+        {
+            let kind = if first.resource {
+                "resource"
+            } else {
+                "component"
+            };
+            return Err(format!(
+                "system {name} borrows {kind} {} more than once and at least once mutably",
+                first.type_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+struct FunctionSystem<F, Params> {
+    func: F,
+    access: Vec<Access>,
+    query_count: usize,
+    _marker: PhantomData<fn() -> Params>,
+}
+
+macro_rules! impl_system_function {
+    ($($param:ident),*) => {
+        impl<F, $($param: SystemParam + 'static),*> IntoSystem<($($param,)*)> for F
+        where
+            F: Send + 'static,
+            for<'a> &'a mut F: FnMut($($param),*) + FnMut($($param::Item<'_>),*),
+        {
+            fn into_system(self) -> BoxedSystem {
+                let mut access = Vec::new();
+                $(access.extend($param::access());)*
+                let query_count = [$($param::is_query()),*].into_iter().filter(|&query| query).count();
+                Box::new(FunctionSystem::<F, ($($param,)*)> {
+                    func: self,
+                    access,
+                    query_count,
+                    _marker: PhantomData,
+                })
+            }
+        }
+
+        impl<F, $($param: SystemParam + 'static),*> System for FunctionSystem<F, ($($param,)*)>
+        where
+            F: Send + 'static,
+            for<'a> &'a mut F: FnMut($($param),*) + FnMut($($param::Item<'_>),*),
+        {
+            fn name(&self) -> &'static str {
+                type_name::<F>()
+            }
+
+            fn access(&self) -> &[Access] {
+                &self.access
+            }
+
+            fn query_count(&self) -> usize {
+                self.query_count
+            }
+
+            #[allow(non_snake_case)]
+            fn run(&mut self, world: &mut World) -> bool {
+                // Going through a named function pins the closure's argument
+                // types to the fetched items.
+                fn call<$($param),*>(mut func: impl FnMut($($param),*), $($param: $param),*) {
+                    func($($param),*)
+                }
+
+                let world = world as *mut World;
+                // The parameters were checked against each other when the
+                // system was added, and `world` is exclusively borrowed here.
+                $(let Some($param) = (unsafe { $param::fetch(world) }) else {
+                    return false;
+                };)*
+                call(&mut self.func, $($param),*);
+                true
+            }
+        }
+    };
+}
+
+impl_system_function!(P0);
+impl_system_function!(P0, P1);
+impl_system_function!(P0, P1, P2);
+impl_system_function!(P0, P1, P2, P3);
+impl_system_function!(P0, P1, P2, P3, P4);
+impl_system_function!(P0, P1, P2, P3, P4, P5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Hp(u32);
+
+    struct Damage {
+        amount: u32,
+    }
+
+    fn take_damage(mut bodies: Query<(&mut Hp,)>, damage: Res<Damage>) {
+        for mut hp in bodies.iter() {
+            hp.0 = hp.0.saturating_sub(damage.amount);
+        }
+    }
+
+    #[test]
+    fn query_and_res_are_fetched_together() {
+        let mut world = World::new();
+        let body = world.spawn((Hp(10),));
+        let mut system = take_damage.into_system();
+        assert!(validate_access(system.name(), system.access(), system.query_count()).is_ok());
+
+        // Skipped until the resource exists.
+        assert!(!system.run(&mut world));
+        world.insert_resource(Damage { amount: 3 });
+        assert!(system.run(&mut world));
+        assert_eq!(world.get_component::<Hp>(body), Some(&Hp(7)));
+    }
+
+    #[test]
+    fn systems_with_two_queries_are_rejected() {
+        let mut world = World::new();
+        let err = world
+            .add_system(|_: Query<(&Hp,)>, _: Query<(&Hp,)>| {})
+            .unwrap_err();
+        assert!(err.contains("takes 2 Query parameters"), "{err}");
+    }
+}
@@ -1,47 +1,295 @@
-use glam::Vec3;
+use std::any::TypeId;
 
-use crate::{components::{Camera, FpsCamera, Position}, input::InputState, World};
+use glam::{Mat3, Mat4, Quat};
+
+use crate::{
+    World,
+    components::{
+        Billboard, Bounds, Camera, CollisionEvent, FpsCamera, LodGroup, MeshHandle,
+        ParticleEmitter, Position, Rotation, Scale, Transform, Translation,
+    },
+    input::InputState,
+    spatial::SpatialHash,
+};
 
 pub fn update_fps_camera_system(world: &mut World, input: &InputState, delta_time: f32) {
-        for (camera, pos, _) in world.query::<(&mut FpsCamera, &mut Position, &Camera)>() {
-        let forward = Vec3::new(
-            camera.yaw.cos() * camera.pitch.cos(),
-            camera.pitch.sin(),
-            camera.yaw.sin() * camera.pitch.cos(),
-        )
-        .normalize();
-        let right = forward.cross(Vec3::Y).normalize();
-        let up = right.cross(forward).normalize();
+    for (mut camera, mut pos, _) in world.query::<(&mut FpsCamera, &mut Position, &Camera)>() {
+        camera.update(&mut pos, input, delta_time);
+    }
+}
+
+// Rebuilds Transform from Translation, Rotation and Scale, but only for
+// entities where one of the three was written since the last run, static
+// entities keep their cached matrix. Runs before billboard_system so that
+// can still override the rotation.
+pub fn compose_transform_system(world: &mut World) {
+    let last_run = world.transform_compose_tick;
+    world.transform_compose_tick = world.increment_change_tick();
+
+    let (Some(translation_index), Some(rotation_index), Some(scale_index), Some(transform_index)) = (
+        world.type_registry.get_index(TypeId::of::<Translation>()),
+        world.type_registry.get_index(TypeId::of::<Rotation>()),
+        world.type_registry.get_index(TypeId::of::<Scale>()),
+        world.type_registry.get_index(TypeId::of::<Transform>()),
+    ) else {
+        return;
+    };
 
-        // Movement
-        let mut velocity = Vec3::ZERO;
-        if input.key_w {
-            velocity += forward;
+    let mut composed = Vec::new();
+    for (_, archetype) in &mut world.archetypes {
+        let (
+            Some(translations),
+            Some(rotations),
+            Some(scales),
+            Some(translation_ticks),
+            Some(rotation_ticks),
+            Some(scale_ticks),
+        ) = (
+            archetype.get_column::<Translation>(translation_index),
+            archetype.get_column::<Rotation>(rotation_index),
+            archetype.get_column::<Scale>(scale_index),
+            archetype.change_ticks(translation_index),
+            archetype.change_ticks(rotation_index),
+            archetype.change_ticks(scale_index),
+        )
+        else {
+            continue;
+        };
+        if archetype.change_ticks(transform_index).is_none() {
+            continue;
         }
-        if input.key_s {
-            velocity -= forward;
+
+        composed.clear();
+        for row in 0..translations.len() {
+            if translation_ticks[row] > last_run
+                || rotation_ticks[row] > last_run
+                || scale_ticks[row] > last_run
+            {
+                composed.push((
+                    row,
+                    Mat4::from_scale_rotation_translation(
+                        scales[row].0,
+                        rotations[row].0,
+                        translations[row].0,
+                    ),
+                ));
+            }
         }
-        if input.key_d {
-            velocity += right;
+        for &(row, matrix) in &composed {
+            if let Some(transform) = archetype.get_component_mut::<Transform>(transform_index, row)
+            {
+                transform.0 = matrix;
+            }
         }
-        if input.key_a {
-            velocity -= right;
+    }
+}
+
+// Runs after the camera has moved so billboards face where it is this tick.
+pub fn billboard_system(world: &mut World) {
+    let Some((camera_position, camera_up)) = world
+        .query::<(&FpsCamera, &Position, &Camera)>()
+        .next()
+        .map(|(camera, position, _)| (position.0, camera.up()))
+    else {
+        return;
+    };
+
+    for (_, mut transform) in world.query::<(&Billboard, &mut Transform)>() {
+        let (scale, _, translation) = transform.0.to_scale_rotation_translation();
+        let Some(forward) = (camera_position - translation).try_normalize() else {
+            continue;
+        };
+        // Looking straight along the camera's up, any perpendicular works.
+        let right = camera_up
+            .cross(forward)
+            .try_normalize()
+            .unwrap_or_else(|| forward.any_orthonormal_vector());
+        let up = forward.cross(right);
+
+        let facing = Quat::from_mat3(&Mat3::from_cols(right, up, forward));
+        transform.0 = Mat4::from_scale_rotation_translation(scale, facing, translation);
+    }
+}
+
+// Particles spawn wherever the emitter's Transform is this tick.
+pub fn particle_system(world: &mut World, delta_time: f32) {
+    for (mut emitter, transform) in world.query::<(&mut ParticleEmitter, &Transform)>() {
+        emitter.step(transform.0.w_axis.truncate(), delta_time);
+    }
+}
+
+// Runs after the camera has moved so the snapshot sees this tick's choice.
+pub fn update_lod_system(world: &mut World) {
+    let Some(camera_position) = world
+        .query::<(&FpsCamera, &Position, &Camera)>()
+        .next()
+        .map(|(_, position, _)| position.0)
+    else {
+        return;
+    };
+
+    for (lod_group, transform, mut mesh_handle) in
+        world.query::<(&LodGroup, &Transform, &mut MeshHandle)>()
+    {
+        let distance = transform.0.w_axis.truncate().distance(camera_position);
+        if let Some(selected) = lod_group.select(distance) {
+            *mesh_handle = selected;
         }
-        if input.key_space {
-            velocity += up;
+    }
+}
+
+// Broad phase through a spatial hash sized to the median box, then an exact
+// AABB test per candidate pair. Bodies whose box isn't finite are skipped.
+pub fn collision_system(world: &mut World) {
+    // Events nobody read during the last tick are dropped rather than piling up.
+    world.clear_events::<CollisionEvent>();
+
+    let (Some(transform_index), Some(bounds_index)) = (
+        world.type_registry.get_index(TypeId::of::<Transform>()),
+        world.type_registry.get_index(TypeId::of::<Bounds>()),
+    ) else {
+        return;
+    };
+
+    let mut bodies = Vec::new();
+    for (_, archetype) in &world.archetypes {
+        let (Some(transforms), Some(bounds)) = (
+            archetype.get_column::<Transform>(transform_index),
+            archetype.get_column::<Bounds>(bounds_index),
+        ) else {
+            continue;
+        };
+        for ((entity, transform), bounds) in archetype.entities.iter().zip(transforms).zip(bounds) {
+            let aabb = bounds.0.transform(&transform.0);
+            if aabb.min.is_finite() && aabb.max.is_finite() {
+                bodies.push((*entity, aabb));
+            }
         }
-        if input.key_ctrl {
-            velocity -= up;
+    }
+    if bodies.len() < 2 {
+        return;
+    }
+
+    // The median rather than the mean, so one huge body such as the ground
+    // doesn't make every cell huge.
+    let mut sizes: Vec<f32> = bodies
+        .iter()
+        .map(|(_, aabb)| (aabb.max - aabb.min).max_element())
+        .collect();
+    let middle = sizes.len() / 2;
+    let (_, median_size, _) = sizes.select_nth_unstable_by(middle, f32::total_cmp);
+    let mut spatial_hash = SpatialHash::new(*median_size);
+    for (index, (_, aabb)) in bodies.iter().enumerate() {
+        spatial_hash.insert(index, aabb);
+    }
+
+    let mut events: Vec<CollisionEvent> = spatial_hash
+        .candidate_pairs()
+        .into_iter()
+        .filter(|&(i, j)| bodies[i].1.intersects(&bodies[j].1))
+        .map(|(i, j)| {
+            let (a, b) = (bodies[i].0.min(bodies[j].0), bodies[i].0.max(bodies[j].0));
+            CollisionEvent { a, b }
+        })
+        .collect();
+    events.sort_unstable_by_key(|event| (event.a, event.b));
+
+    for event in events {
+        world.send_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::math::Aabb;
+
+    fn body(world: &mut World, position: Vec3) -> crate::entities::EntityId {
+        world.spawn((
+            Transform(Mat4::from_translation(position)),
+            Bounds(Aabb {
+                min: Vec3::splat(-0.5),
+                max: Vec3::splat(0.5),
+            }),
+        ))
+    }
+
+    #[test]
+    fn overlapping_bodies_collide_once() {
+        let mut world = World::new();
+        let a = body(&mut world, Vec3::ZERO);
+        let b = body(&mut world, Vec3::new(0.5, 0.0, 0.0));
+        body(&mut world, Vec3::new(10.0, 0.0, 0.0));
+
+        collision_system(&mut world);
+        assert_eq!(
+            world.events::<CollisionEvent>(),
+            [CollisionEvent {
+                a: a.min(b),
+                b: a.max(b)
+            }]
+        );
+
+        // The next tick replaces the events rather than adding to them.
+        collision_system(&mut world);
+        assert_eq!(world.events::<CollisionEvent>().len(), 1);
+        assert_eq!(world.take_events::<CollisionEvent>().len(), 1);
+        assert!(world.events::<CollisionEvent>().is_empty());
+    }
+
+    fn matrix_bits(world: &World, entity: crate::entities::EntityId) -> [u32; 16] {
+        world
+            .get_component::<Transform>(entity)
+            .unwrap()
+            .0
+            .to_cols_array()
+            .map(f32::to_bits)
+    }
+
+    #[test]
+    fn only_written_rows_are_recomposed() {
+        let mut world = World::new();
+        let entities: Vec<_> = (0..4)
+            .map(|i| {
+                world.spawn((
+                    Translation(Vec3::X * i as f32),
+                    Rotation(Quat::IDENTITY),
+                    Scale(Vec3::ONE),
+                    Transform(Mat4::IDENTITY),
+                ))
+            })
+            .collect();
+        compose_transform_system(&mut world);
+
+        // Recomposing these would overwrite the sentinel with their TRS.
+        let sentinel = Mat4::from_cols_array(&[7.0; 16]);
+        for &entity in &entities {
+            world.set_component(entity, Transform(sentinel));
         }
+        let before: Vec<_> = entities
+            .iter()
+            .map(|&entity| matrix_bits(&world, entity))
+            .collect();
 
-        if velocity.length_squared() > 0.0 {
-            *pos = Position(pos.0 + velocity.normalize() * camera.speed * delta_time);
+        // Every row is borrowed mutably, only one is written.
+        for mut translation in world.query::<(&mut Translation,)>() {
+            if translation.0.x == 2.0 {
+                translation.0.y = 1.0;
+            }
         }
+        compose_transform_system(&mut world);
 
-        camera.yaw += input.mouse_delta_x * camera.sensitivity;
-        camera.pitch -= input.mouse_delta_y * camera.sensitivity;
-        camera.pitch = camera
-            .pitch
-            .clamp(-89.9_f32.to_radians(), 89.9_f32.to_radians());
+        for (i, &entity) in entities.iter().enumerate() {
+            if i == 2 {
+                assert_eq!(
+                    world.get_component::<Transform>(entity).unwrap().0,
+                    Mat4::from_translation(Vec3::new(2.0, 1.0, 0.0))
+                );
+            } else {
+                assert_eq!(matrix_bits(&world, entity), before[i]);
+            }
+        }
     }
-}
\ No newline at end of file
+}
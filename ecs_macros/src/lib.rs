@@ -1,7 +1,7 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
-use syn::{Index, Path, parse_macro_input};
+use syn::{Data, DeriveInput, Fields, Index, Path, parse_macro_input};
 
 #[proc_macro]
 pub fn impl_query(input: TokenStream) -> TokenStream {
@@ -11,7 +11,7 @@ pub fn impl_query(input: TokenStream) -> TokenStream {
     for n in 1..=16 {
         let idents: Vec<_> = (0..n).map(|i| format_ident!("T{}", i)).collect();
         let vars: Vec<_> = (0..n).map(|i| format_ident!("v{}", i)).collect();
-        let indices: Vec<_> = (0..n).map(Index::from).collect();
+        let _indices: Vec<_> = (0..n).map(Index::from).collect();
 
         // build zipped iterator: columns.0.iter().zip(columns.1.iter()) ...
         let mut zip_chain = quote! { columns.0.iter() };
@@ -54,7 +54,6 @@ pub fn impl_query(input: TokenStream) -> TokenStream {
         });
     }
 
-    eprintln!("{}", tokens.to_string());
     tokens.into()
 }
 
@@ -84,43 +83,137 @@ pub fn impl_query_combinations(input: TokenStream) -> TokenStream {
                 })
                 .collect();
 
-            let item_type = quote! { (#(#ref_types),*) };
+            // Mutable components come wrapped in Mut so writes stamp their row.
+            let item_types: Vec<_> = type_idents
+                .iter()
+                .zip(&mut_refs)
+                .map(|(ty, is_mut)| {
+                    if *is_mut {
+                        quote! { #ecs_path::queries::Mut<'world, #ty> }
+                    } else {
+                        quote! { &'world #ty }
+                    }
+                })
+                .collect();
+            let item_type = quote! { (#(#item_types),*) };
+
+            // Every reference gets its own lifetime so QueryData also covers
+            // tuples written with elided lifetimes in a system's signature.
+            let data_lifetimes: Vec<_> = (0..n)
+                .map(|i| syn::Lifetime::new(&format!("'q{i}"), proc_macro2::Span::call_site()))
+                .collect();
+            let data_types: Vec<_> = type_idents
+                .iter()
+                .zip(&mut_refs)
+                .zip(&data_lifetimes)
+                .map(|((ty, is_mut), lifetime)| {
+                    if *is_mut {
+                        quote! { &#lifetime mut #ty }
+                    } else {
+                        quote! { &#lifetime #ty }
+                    }
+                })
+                .collect();
 
             let get_columns: Vec<_> = type_idents.iter().zip(&mut_refs).enumerate().map(|(i,(ty, is_mut))| {
                 let col_indent = format_ident!("col_{}", i);
                 let index = Index::from(i);
                 if *is_mut {
-                    quote! { let #col_indent: &'world mut Vec<#ty> = unsafe{&mut *ptr}.get_column_mut(indices[#index])?; }
+                    let ticks_ident = format_ident!("ticks_{}", i);
+                    let tick_ident = format_ident!("tick_{}", i);
+                    quote! {
+                        let (#col_indent, #ticks_ident, #tick_ident): (&'world mut Vec<#ty>, &'world mut Vec<u32>, u32) =
+                            unsafe{&mut *ptr}.get_column_mut_tracked(indices[#index])?;
+                    }
                 } else {
                     quote! { let #col_indent: &'world Vec<#ty> = unsafe{&mut *ptr}.get_column(indices[#index])?; }
                 }
             }).collect();
 
-            let mut zip_chain = {
-                if mut_refs[0] {
-                    quote! { col_0.iter_mut() }
-                } else {
-                    quote! { col_0.iter() }
-                }
-            };
+            // A mutable column is zipped with its change ticks, so each item
+            // gets its own row's tick.
+            let column_iters: Vec<_> = mut_refs
+                .iter()
+                .enumerate()
+                .map(|(i, is_mut)| {
+                    let col = format_ident!("col_{}", i);
+                    if *is_mut {
+                        let ticks = format_ident!("ticks_{}", i);
+                        quote! { #col.iter_mut().zip(#ticks.iter_mut()) }
+                    } else {
+                        quote! { #col.iter() }
+                    }
+                })
+                .collect();
+            let var_patterns: Vec<_> = var_idents
+                .iter()
+                .zip(&mut_refs)
+                .enumerate()
+                .map(|(i, (v, is_mut))| {
+                    if *is_mut {
+                        let changed_at = format_ident!("changed_at_{}", i);
+                        quote! { (#v, #changed_at) }
+                    } else {
+                        quote! { #v }
+                    }
+                })
+                .collect();
+            let items: Vec<_> = var_idents
+                .iter()
+                .zip(&mut_refs)
+                .enumerate()
+                .map(|(i, (v, is_mut))| {
+                    if *is_mut {
+                        let changed_at = format_ident!("changed_at_{}", i);
+                        let tick = format_ident!("tick_{}", i);
+                        quote! { #ecs_path::queries::Mut::new(#v, #changed_at, #tick) }
+                    } else {
+                        quote! { #v }
+                    }
+                })
+                .collect();
 
-            for (i, is_mut) in mut_refs.iter().enumerate().skip(1) {
-                let col = format_ident!("col_{}", i);
-                let iter = if *is_mut {
-                    quote! { #col.iter_mut() }
-                } else {
-                    quote! { #col.iter() }
-                };
+            let mut zip_chain = column_iters[0].clone();
+            for iter in &column_iters[1..] {
                 zip_chain = quote! { #zip_chain.zip(#iter) };
             }
 
-            let first_var = &var_idents[0];
-            let mut destructure = quote! { #first_var };
-            for v in &var_idents[1..] {
-                destructure = quote! { (#destructure, #v) };
+            let mut destructure = var_patterns[0].clone();
+            for pattern in &var_patterns[1..] {
+                destructure = quote! { (#destructure, #pattern) };
             }
 
-            let return_tuple = quote! { (#(#var_idents),*) };
+            let return_tuple = quote! { (#(#items),*) };
+
+            // A mutable borrow of a column also borrowed elsewhere in the
+            // query, mutably or not, would alias through the raw pointer
+            // below. Checked in release too, where the archetype is skipped
+            // instead of panicking.
+            let mut_positions: Vec<usize> = mut_refs
+                .iter()
+                .enumerate()
+                .filter(|(_, is_mut)| **is_mut)
+                .map(|(i, _)| i)
+                .collect();
+            let alias_check = if n > 1 && !mut_positions.is_empty() {
+                quote! {
+                    let aliased = [#(#mut_positions),*].iter().any(|&mut_position| {
+                        indices
+                            .iter()
+                            .enumerate()
+                            .any(|(i, index)| i != mut_position && *index == indices[mut_position])
+                    });
+                    debug_assert!(
+                        !aliased,
+                        "query borrows a component mutably and also borrows it again"
+                    );
+                    if aliased {
+                        return None;
+                    }
+                }
+            } else {
+                quote! {}
+            };
 
             output.extend(quote! {
                 impl<'world, #(#type_idents: 'static),*> Query<'world> for (#(#ref_types,)*) {
@@ -136,11 +229,25 @@ pub fn impl_query_combinations(input: TokenStream) -> TokenStream {
                             #(registry.get_index(std::any::TypeId::of::<#type_idents>())?),*
                         ];
 
+                        #alias_check
+
                         let ptr = archetype as *mut Archetype;
 
                         #(#get_columns)*
 
-                        Some(Box::new(#zip_chain.map(|#destructure| #return_tuple)))
+                        Some(Box::new(#zip_chain.map(move |#destructure| #return_tuple)))
+                    }
+                }
+
+                impl<#(#data_lifetimes,)* #(#type_idents: 'static),*> QueryData for (#(#data_types,)*) {
+                    type Fetch<'world> = (#(#ref_types,)*);
+
+                    fn access() -> Vec<(std::any::TypeId, &'static str, bool)> {
+                        vec![#((
+                            std::any::TypeId::of::<#type_idents>(),
+                            std::any::type_name::<#type_idents>(),
+                            #mut_refs
+                        )),*]
                     }
                 }
             });
@@ -150,3 +257,62 @@ pub fn impl_query_combinations(input: TokenStream) -> TokenStream {
     // eprintln!("{}", output.to_string());
     output.into()
 }
+
+// Lets a struct be passed to World::spawn like a tuple, with each field
+// stored as its own component column.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(name, "Bundle can only be derived for structs")
+                .to_compile_error()
+                .into();
+        }
+    };
+    if fields.is_empty() {
+        return syn::Error::new_spanned(name, "a Bundle needs at least one component")
+            .to_compile_error()
+            .into();
+    }
+
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let accessors: Vec<_> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                quote! { #ident }
+            })
+            .collect(),
+        _ => (0..fields.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote! { #index }
+            })
+            .collect(),
+    };
+
+    quote! {
+        impl #impl_generics ::ecs::components::ComponentTuple for #name #type_generics #where_clause {
+            fn component_indices(
+                registry: &mut ::ecs::components::ComponentTypeIndexRegistry,
+            ) -> Vec<usize> {
+                vec![#(registry.get_or_register::<#types>()),*]
+            }
+
+            fn into_components(self) -> Vec<Box<dyn ::ecs::components::ComponentStorage>> {
+                vec![#(
+                    Box::new(vec![self.#accessors])
+                        as Box<dyn ::ecs::components::ComponentStorage>
+                ),*]
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,135 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender, channel},
+};
+
+use log::{error, info};
+
+use ecs::meshes::{MeshId, MeshManager};
+
+use crate::{
+    graphics::{
+        gpu_device::GpuDevice,
+        mesh::{Vertex, mesh_allocator::MeshAllocator, obj::parse_obj},
+    },
+    utils::ThreadPool,
+};
+
+// Mesh data a decode job finished, waiting for the device thread to upload it.
+struct DecodedMesh {
+    id: MeshId,
+    label: String,
+    result: Result<(Vec<Vertex>, Vec<u32>), String>,
+}
+
+// Reads and decodes assets on its own pool, so a large file never holds up
+// the sim or parallel work. Decoding only produces CPU data, the GPU upload
+// happens in `upload_ready` on the thread that owns the device. Until then
+// the returned MeshId is pending in the MeshManager and doesn't resolve.
+pub struct AssetServer {
+    pool: ThreadPool,
+    decoded_sender: Sender<DecodedMesh>,
+    decoded: Receiver<DecodedMesh>,
+    stream: u32,
+    in_flight: usize,
+}
+
+impl AssetServer {
+    // Meshes are uploaded into the allocator's `stream`, which has to hold
+    // `Vertex`.
+    pub fn new(pool: ThreadPool, stream: u32) -> Self {
+        let (decoded_sender, decoded) = channel();
+        Self {
+            pool,
+            decoded_sender,
+            decoded,
+            stream,
+            in_flight: 0,
+        }
+    }
+
+    pub fn load_mesh(&mut self, meshes: &mut MeshManager, path: impl AsRef<Path>) -> MeshId {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        let label = path.to_string_lossy().into_owned();
+        self.decode_mesh(meshes, label, move || {
+            let source = std::fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read {}, {err}", path.display()))?;
+            parse_obj(&source)
+        })
+    }
+
+    // For OBJ text already in memory, e.g. embedded with include_str.
+    pub fn load_mesh_from_source(
+        &mut self,
+        meshes: &mut MeshManager,
+        label: impl Into<String>,
+        source: String,
+    ) -> MeshId {
+        self.decode_mesh(meshes, label.into(), move || parse_obj(&source))
+    }
+
+    fn decode_mesh(
+        &mut self,
+        meshes: &mut MeshManager,
+        label: String,
+        decode: impl FnOnce() -> Result<(Vec<Vertex>, Vec<u32>), String> + Send + 'static,
+    ) -> MeshId {
+        let id = meshes.reserve();
+        let sender = self.decoded_sender.clone();
+        self.in_flight += 1;
+        self.pool.submit(move || {
+            let result = decode();
+            // The server is gone if this fails, nobody is left to upload it.
+            let _ = sender.send(DecodedMesh { id, label, result });
+        });
+        id
+    }
+
+    // Meshes submitted and not yet uploaded.
+    pub fn pending(&self) -> usize {
+        self.in_flight
+    }
+
+    // Uploads every mesh whose decode has finished and resolves its id.
+    // Failed loads are logged and their ids removed. Returns how many meshes
+    // were uploaded.
+    pub fn upload_ready(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        mesh_allocator: &mut MeshAllocator,
+        meshes: &mut MeshManager,
+    ) -> usize {
+        let mut uploaded = 0;
+        while let Ok(decoded) = self.decoded.try_recv() {
+            self.in_flight -= 1;
+            let DecodedMesh { id, label, result } = decoded;
+            if !meshes.is_pending(id) {
+                info!("dropping mesh {label}, it was removed while loading");
+                continue;
+            }
+            let (vertices, indices) = match result {
+                Ok(mesh) => mesh,
+                Err(err) => {
+                    error!("failed to load mesh {label}, {err}");
+                    meshes.remove(id);
+                    continue;
+                }
+            };
+            let Some(handles) =
+                mesh_allocator.upload_static_mesh(gpu, self.stream, &vertices, &indices)
+            else {
+                error!("no room in mesh stream {} for {label}", self.stream);
+                meshes.remove(id);
+                continue;
+            };
+            meshes.resolve(id, handles[0]);
+            uploaded += 1;
+        }
+        uploaded
+    }
+
+    // Queued decodes that haven't started are dropped.
+    pub fn shutdown(self) {
+        self.pool.shutdown();
+    }
+}
@@ -1,18 +1,70 @@
-pub struct FrameIndex {
-    current: usize,
-    count: usize,
+// Which slot of the per-frame rings the sim and the renderer are using.
+// `render` counts rendered frames and `sim` is the frame the running sim job
+// is producing, always the one after the frame it was started in since its
+// snapshot is taken at the start of the next frame. All slot arithmetic
+// lives here so callers never do the modulo themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingIndices {
+    sim: usize,
+    render: usize,
+    depth: usize,
 }
 
-impl FrameIndex {
-    pub fn new(count: usize) -> Self {
-        Self { current: 0, count }
+impl RingIndices {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth > 0, "ring depth must be at least one");
+        Self {
+            sim: 0,
+            render: 0,
+            depth,
+        }
     }
 
-    pub fn index(&self) -> usize {
-        self.current
+    pub fn depth(&self) -> usize {
+        self.depth
     }
 
-    pub fn advance(&mut self) {
-        self.current = (self.current + 1) % self.count;
+    // The slot the current sim job's output lands in.
+    pub fn write_slot(&self) -> usize {
+        self.sim % self.depth
+    }
+
+    // The slot this frame uploads to and draws from.
+    pub fn read_slot(&self) -> usize {
+        self.render % self.depth
+    }
+
+    // A sim job is starting, it feeds the frame after this one.
+    pub fn advance_sim(&mut self) {
+        self.sim = self.render + 1;
+    }
+
+    pub fn advance_render(&mut self) {
+        self.render += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sim_writes_the_slot_the_next_frame_reads() {
+        let mut ring = RingIndices::new(3);
+        for frame in 0..7 {
+            assert_eq!(ring.read_slot(), frame % 3);
+            // No sim job on the fourth frame, the next one still lands in the
+            // slot after the one being drawn.
+            let sim_ran = frame != 3;
+            if sim_ran {
+                ring.advance_sim();
+                assert_eq!(ring.write_slot(), (frame + 1) % 3);
+                assert_ne!(ring.write_slot(), ring.read_slot());
+            }
+            ring.advance_render();
+            if sim_ran {
+                assert_eq!(ring.read_slot(), ring.write_slot());
+            }
+        }
     }
 }
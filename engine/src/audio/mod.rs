@@ -0,0 +1,125 @@
+use std::io::Cursor;
+
+use hound::{SampleFormat, WavReader};
+use log::debug;
+
+use crate::utils::{RegisterKey, Registry};
+
+// Decoded samples, interleaved by channel and normalized to [-1, 1].
+#[derive(Debug, Clone)]
+pub struct Sound {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Sound {
+    pub fn from_wav(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader =
+            WavReader::new(Cursor::new(bytes)).map_err(|err| format!("invalid wav data {err}"))?;
+        let spec = reader.spec();
+
+        let samples = match spec.sample_format {
+            SampleFormat::Float => reader
+                .samples::<f32>()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("failed to decode wav samples {err}"))?,
+            SampleFormat::Int => {
+                let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 / scale))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| format!("failed to decode wav samples {err}"))?
+            }
+        };
+
+        Ok(Self {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            samples,
+        })
+    }
+}
+
+pub type AudioRegistry = Registry<Sound>;
+
+// Where queued sounds end up. A real output device goes behind this so the
+// rest of the engine doesn't depend on a platform audio backend.
+pub trait AudioSink: Send {
+    fn play(&mut self, sound: &Sound);
+}
+
+// Drops everything, used when no output device is available.
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&mut self, _sound: &Sound) {}
+}
+
+pub struct AudioEngine {
+    sounds: AudioRegistry,
+    queue: Vec<RegisterKey>,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Default for AudioEngine {
+    fn default() -> Self {
+        Self::new(Box::new(NullSink))
+    }
+}
+
+impl AudioEngine {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            sounds: AudioRegistry::default(),
+            queue: Vec::new(),
+            sink,
+        }
+    }
+
+    pub fn load_wav(&mut self, label: &'static str, bytes: &[u8]) -> Result<RegisterKey, String> {
+        let sound = Sound::from_wav(bytes).map_err(|err| format!("{label}: {err}"))?;
+        let key = RegisterKey::from_label::<Sound>(label);
+        self.sounds.register_key(key.clone(), sound);
+        Ok(key)
+    }
+
+    pub fn sounds(&self) -> &AudioRegistry {
+        &self.sounds
+    }
+
+    pub fn queued(&self) -> &[RegisterKey] {
+        &self.queue
+    }
+
+    // Queues the sound for the next flush rather than playing it right away,
+    // so gameplay code never waits on the sink.
+    pub fn play(&mut self, sound: &RegisterKey) -> Result<(), String> {
+        if self.sounds.get(sound).is_none() {
+            return Err(format!("sound {} is not loaded", sound.label));
+        }
+        self.queue.push(sound.clone());
+        Ok(())
+    }
+
+    pub fn play_label(&mut self, label: &str) -> Result<(), String> {
+        let key = self
+            .sounds
+            .keys()
+            .find(|key| key.label == label)
+            .cloned()
+            .ok_or_else(|| format!("sound {label} is not loaded"))?;
+        self.play(&key)
+    }
+
+    pub fn flush(&mut self) {
+        for key in self.queue.drain(..) {
+            if let Some(sound) = self.sounds.get(&key) {
+                debug!("playing sound {}", key.label);
+                self.sink.play(sound);
+            }
+        }
+    }
+}
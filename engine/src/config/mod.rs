@@ -0,0 +1,209 @@
+use std::{path::PathBuf, time::Duration};
+
+use wgpu::{Backends, Color};
+
+use crate::{
+    graphics::{buffers::RING_BUFFER_DEPTH, textures::MAX_ANISOTROPY},
+    windowing::CursorGrab,
+};
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub threads: usize,
+    pub worker_threads: usize,
+    pub sim_hz: f64,
+    pub ring_depth: usize,
+    pub frame_latency: Option<u32>,
+    pub shader_dir: PathBuf,
+    pub clear_color: Color,
+    pub window_title: String,
+    pub anisotropy: u16,
+    pub seed: u64,
+    pub cursor_grab: CursorGrab,
+    pub frame_limit: Option<u32>,
+    pub threaded_sim: bool,
+    pub gpu_driven_draws: bool,
+    pub backends: Backends,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            threads: 4,
+            worker_threads: 4,
+            sim_hz: 240.0,
+            ring_depth: RING_BUFFER_DEPTH,
+            frame_latency: None,
+            shader_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/graphics/shaders")),
+            clear_color: Color::BLACK,
+            window_title: String::from("potato engine"),
+            anisotropy: MAX_ANISOTROPY,
+            seed: 0,
+            cursor_grab: CursorGrab::default(),
+            frame_limit: None,
+            threaded_sim: true,
+            gpu_driven_draws: false,
+            backends: Backends::all(),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // Sized separately from `threads` so fine-grained parallel work never
+    // queues behind a long sim job.
+    pub fn worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = worker_threads;
+        self
+    }
+
+    pub fn sim_hz(mut self, sim_hz: f64) -> Self {
+        self.sim_hz = sim_hz;
+        self
+    }
+
+    pub fn ring_depth(mut self, ring_depth: usize) -> Self {
+        self.ring_depth = ring_depth;
+        self
+    }
+
+    // How many frames the surface may queue, the ring depth when unset. Each
+    // reads its own ring slot, so it can't be more than the ring depth.
+    pub fn frame_latency(mut self, frame_latency: u32) -> Self {
+        self.frame_latency = Some(frame_latency);
+        self
+    }
+
+    pub fn shader_dir(mut self, shader_dir: impl Into<PathBuf>) -> Self {
+        self.shader_dir = shader_dir.into();
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    pub fn window_title(mut self, window_title: impl Into<String>) -> Self {
+        self.window_title = window_title.into();
+        self
+    }
+
+    // Clamped to the adapter's maximum when a sampler is created.
+    pub fn anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    // Seeds the world's Rng resource, the same seed replays the same sequence.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // The grab mode tried first, the other is the fallback.
+    pub fn cursor_grab(mut self, cursor_grab: CursorGrab) -> Self {
+        self.cursor_grab = cursor_grab;
+        self
+    }
+
+    // Renders this many frames, reports the average frame times and exits.
+    pub fn frame_limit(mut self, frame_limit: Option<u32>) -> Self {
+        self.frame_limit = frame_limit;
+        self
+    }
+
+    // Runs sim ticks on the sim pool, overlapping the main thread's event
+    // handling, or inline on the main thread when false.
+    pub fn threaded_sim(mut self, threaded_sim: bool) -> Self {
+        self.threaded_sim = threaded_sim;
+        self
+    }
+
+    // Also binds the indirect draw buffers writable for compute passes, so
+    // draw commands can be generated on the GPU.
+    pub fn gpu_driven_draws(mut self, gpu_driven_draws: bool) -> Self {
+        self.gpu_driven_draws = gpu_driven_draws;
+        self
+    }
+
+    // The backends the instance is created with. WGPU_BACKEND overrides it,
+    // and the config is updated to the backends actually used.
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    // The GPU ring buffers are sized at compile time, so the configured depth
+    // can only use fewer frames in flight, never more.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threads == 0 {
+            return Err(String::from("engine needs at least one sim thread"));
+        }
+        if self.worker_threads == 0 {
+            return Err(String::from("engine needs at least one worker thread"));
+        }
+        if !(self.sim_hz.is_finite() && self.sim_hz > 0.0) {
+            return Err(format!(
+                "simulation rate {} must be a positive number",
+                self.sim_hz
+            ));
+        }
+        if self.ring_depth == 0 || self.ring_depth > RING_BUFFER_DEPTH {
+            return Err(format!(
+                "ring depth {} must be between 1 and {RING_BUFFER_DEPTH}",
+                self.ring_depth
+            ));
+        }
+        if let Some(frame_latency) = self.frame_latency
+            && (frame_latency == 0 || frame_latency as usize > self.ring_depth)
+        {
+            return Err(format!(
+                "frame latency {frame_latency} must be between 1 and the ring depth {}",
+                self.ring_depth
+            ));
+        }
+        if self.frame_limit == Some(0) {
+            return Err(String::from("frame limit must be at least one frame"));
+        }
+        Ok(())
+    }
+
+    pub fn surface_frame_latency(&self) -> u32 {
+        self.frame_latency.unwrap_or(self.ring_depth as u32)
+    }
+
+    pub fn sim_delta_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.sim_hz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_latency_is_bounded_by_the_ring_depth() {
+        assert!(EngineConfig::new().validate().is_ok());
+        assert!(EngineConfig::new().frame_latency(0).validate().is_err());
+
+        let shallow = EngineConfig::new().ring_depth(2);
+        assert!(shallow.validate().is_ok());
+        assert_eq!(shallow.surface_frame_latency(), 2);
+        assert!(shallow.clone().frame_latency(1).validate().is_ok());
+        assert_eq!(
+            shallow.frame_latency(3).validate(),
+            Err(String::from(
+                "frame latency 3 must be between 1 and the ring depth 2"
+            ))
+        );
+    }
+}
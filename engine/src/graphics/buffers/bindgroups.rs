@@ -1,8 +1,6 @@
 use std::any::Any;
 
-use wgpu::{
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Device,
-};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Device};
 pub trait BindGroupInterface: Any + Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn as_mut_any(&mut self) -> &mut dyn Any;
@@ -17,6 +15,6 @@ pub fn create_bind_group(
     device.create_bind_group(&BindGroupDescriptor {
         label: Some(label),
         layout: model_bind_group_layout,
-        entries: &entry,
+        entries: entry,
     })
 }
@@ -7,6 +7,6 @@ pub fn create_bind_group_layout(
 ) -> BindGroupLayout {
     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some(label),
-        entries: &entry,
+        entries: entry,
     })
 }
@@ -1,25 +1,27 @@
-use std::{
-    any::Any,
-    marker::PhantomData,
-    sync::{Arc, Mutex},
-};
+use std::{any::Any, marker::PhantomData};
 
 use bytemuck::Pod;
-use log::debug;
 use wgpu::{
-    BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue,
-    util::{BufferInitDescriptor, DeviceExt, StagingBelt},
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+    Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages,
+    COPY_BUFFER_ALIGNMENT, CommandEncoder, Device, Queue, RenderPass, ShaderStages,
+    util::{BufferInitDescriptor, DeviceExt},
 };
 
 pub mod bindgroups;
 pub mod layouts;
+pub mod staging;
 pub mod submissions;
 
-use crate::{
-    graphics::buffers::submissions::{CameraUniform, IndirectDraw, ModelUniform},
-    utils::{RegisterKey, Registry},
+use crate::graphics::{
+    buffers::{
+        staging::MappedStaging,
+        submissions::{BoneMatrix, CameraUniform, IndirectDraw, LightUniform, ModelUniform},
+    },
+    gpu_device::GpuDevice,
 };
-use ecs::commands::IndirectDrawCommand;
+
+pub const RING_BUFFER_DEPTH: usize = 3;
 
 pub trait BufferInterface: Any + Send + Sync {
     fn as_any(&self) -> &dyn Any;
@@ -34,7 +36,9 @@ pub struct BufferEntry {
 }
 
 pub struct GpuRingBuffer<T> {
-    entries: [BufferEntry; 3],
+    entries: [BufferEntry; RING_BUFFER_DEPTH],
+    // One per entry once enable_mapped_staging is called, empty otherwise.
+    staging: Vec<MappedStaging>,
     _phantom: PhantomData<T>,
 }
 
@@ -58,6 +62,26 @@ impl BufferInterface for GpuRingBuffer<ModelUniform> {
     }
 }
 
+impl BufferInterface for GpuRingBuffer<BoneMatrix> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl BufferInterface for GpuRingBuffer<LightUniform> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
 impl BufferInterface for GpuRingBuffer<IndirectDraw> {
     fn as_any(&self) -> &dyn Any {
         self
@@ -69,27 +93,87 @@ impl BufferInterface for GpuRingBuffer<IndirectDraw> {
 
 impl<T> GpuRingBuffer<T> {
     pub fn new(entries: Vec<BufferEntry>) -> Self {
+        let entry_count = entries.len();
         Self {
-            entries: [
-                entries.get(0).unwrap().clone(),
-                entries.get(1).unwrap().clone(),
-                entries.get(2).unwrap().clone(),
-            ],
+            entries: entries.try_into().unwrap_or_else(|_| {
+                panic!("ring buffer needs {RING_BUFFER_DEPTH} entries, got {entry_count}")
+            }),
+            staging: Vec::new(),
             _phantom: PhantomData,
         }
     }
 
     pub fn get_read(&self, frame_index: usize) -> &BufferEntry {
-        &self.entries[frame_index % 3]
+        &self.entries[frame_index % RING_BUFFER_DEPTH]
     }
 
     pub fn get_write(&mut self, frame_index: usize) -> &mut BufferEntry {
-        &mut self.entries[frame_index % 3]
+        &mut self.entries[frame_index % RING_BUFFER_DEPTH]
+    }
+
+    pub fn write(&mut self, gpu: &dyn GpuDevice, data: &[u8], frame_index: usize) {
+        self.write_range(gpu, 0, data, frame_index);
     }
 
-    pub fn write(&mut self, queue: &Queue, data: &[u8], frame_index: usize) {
+    pub fn write_range(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        offset: u64,
+        data: &[u8],
+        frame_index: usize,
+    ) {
+        let Some(size) = BufferSize::new(data.len() as u64) else {
+            return;
+        };
+        debug_assert!(
+            offset.is_multiple_of(COPY_BUFFER_ALIGNMENT)
+                && size.get().is_multiple_of(COPY_BUFFER_ALIGNMENT),
+            "buffer writes must be {COPY_BUFFER_ALIGNMENT} byte aligned, got offset {offset} size {size}"
+        );
+
         let entry = self.get_write(frame_index);
-        queue.write_buffer(&entry.buffer, 0, data);
+        gpu.write_buffer_with(&entry.buffer, offset, size, &mut |view| {
+            view.copy_from_slice(data)
+        });
+    }
+
+    // Gives every entry a staging buffer of its own size for mapped_write.
+    pub fn enable_mapped_staging(&mut self, gpu: &dyn GpuDevice) {
+        self.staging = self
+            .entries
+            .iter()
+            .map(|entry| MappedStaging::new(gpu, "ring buffer staging", entry.buffer.size()))
+            .collect();
+    }
+
+    // For large uploads, writes straight into the slot's mapped staging
+    // buffer and records a copy into the entry on `encoder`, skipping the
+    // queue's own staging copy. Call remap_staging once the encoder has been
+    // submitted. Fails if staging isn't enabled or the slot's staging buffer
+    // hasn't finished mapping again, write still works then.
+    pub fn mapped_write(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        data: &[u8],
+        frame_index: usize,
+    ) -> Result<(), String> {
+        debug_assert!(
+            (data.len() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT),
+            "buffer writes must be {COPY_BUFFER_ALIGNMENT} byte aligned, got size {}",
+            data.len()
+        );
+        let slot = frame_index % RING_BUFFER_DEPTH;
+        let staging = self
+            .staging
+            .get_mut(slot)
+            .ok_or("mapped staging is not enabled for this ring buffer")?;
+        staging.write(encoder, &self.entries[slot].buffer, 0, data)
+    }
+
+    pub fn remap_staging(&mut self) {
+        for staging in &mut self.staging {
+            staging.remap();
+        }
     }
 }
 
@@ -128,3 +212,165 @@ pub fn _create_buffer_with_data(
         usage: combined_buffer_uses,
     })
 }
+
+// One uniform buffer per ring slot holding `capacity` values, each at an
+// offset aligned to min_uniform_buffer_offset_alignment. The bind group only
+// covers a single value and is bound with a dynamic offset per object, so
+// many objects share one buffer and one bind group.
+pub struct DynamicUniformBuffer<T> {
+    ring: GpuRingBuffer<T>,
+    bind_group_layout: BindGroupLayout,
+    stride: u64,
+    capacity: u32,
+}
+
+impl<T: Pod> DynamicUniformBuffer<T> {
+    pub fn new(device: &Device, label: &str, capacity: u32, visibility: ShaderStages) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = (size_of::<T>() as u64).div_ceil(alignment) * alignment;
+        let element_size = BufferSize::new(size_of::<T>() as u64);
+
+        let bind_group_layout = layouts::create_bind_group_layout(
+            label,
+            device,
+            &vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: element_size,
+                },
+                count: None,
+            }],
+        );
+
+        let entries = (0..RING_BUFFER_DEPTH)
+            .map(|_| {
+                let buffer = create_buffer(
+                    device,
+                    label,
+                    stride * capacity.max(1) as u64,
+                    vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST],
+                    false,
+                );
+                let bind_group = bindgroups::create_bind_group(
+                    label,
+                    device,
+                    &bind_group_layout,
+                    &vec![BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &buffer,
+                            offset: 0,
+                            size: element_size,
+                        }),
+                    }],
+                );
+                BufferEntry {
+                    buffer,
+                    bind_group: Some(bind_group),
+                    element_count: 0,
+                }
+            })
+            .collect();
+
+        Self {
+            ring: GpuRingBuffer::new(entries),
+            bind_group_layout,
+            stride,
+            capacity,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub fn offset(&self, index: u32) -> Result<u32, String> {
+        if index >= self.capacity {
+            return Err(format!(
+                "dynamic uniform index {index} is out of range for capacity {}",
+                self.capacity
+            ));
+        }
+        Ok((index as u64 * self.stride) as u32)
+    }
+
+    pub fn write(
+        &mut self,
+        queue: &Queue,
+        frame_index: usize,
+        index: u32,
+        value: &T,
+    ) -> Result<(), String> {
+        let offset = self.offset(index)?;
+        let entry = self.ring.get_write(frame_index);
+        entry.element_count = entry.element_count.max(index + 1);
+        queue.write_buffer(&entry.buffer, offset as u64, bytemuck::bytes_of(value));
+        Ok(())
+    }
+
+    pub fn bind(
+        &self,
+        render_pass: &mut RenderPass,
+        group: u32,
+        frame_index: usize,
+        index: u32,
+    ) -> Result<(), String> {
+        let offset = self.offset(index)?;
+        let bind_group = self
+            .ring
+            .get_read(frame_index)
+            .bind_group
+            .as_ref()
+            .expect("dynamic uniform buffer entries always have a bind group");
+        render_pass.set_bind_group(group, Some(bind_group), &[offset]);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "test-mock-gpu"))]
+mod mock_gpu_tests {
+    use super::*;
+    use crate::graphics::gpu_device::MockGpuDevice;
+
+    #[test]
+    fn write_range_only_touches_its_range_in_the_current_slot() {
+        let gpu = MockGpuDevice::new();
+        let entries = (0..RING_BUFFER_DEPTH)
+            .map(|_| BufferEntry {
+                buffer: gpu.create_buffer(&BufferDescriptor {
+                    label: Some("ring slot"),
+                    size: 256,
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                bind_group: None,
+                element_count: 0,
+            })
+            .collect();
+        let mut ring = GpuRingBuffer::<ModelUniform>::new(entries);
+
+        let frame_index = RING_BUFFER_DEPTH + 1;
+        ring.write_range(&gpu, 16, &[1; 16], frame_index);
+        ring.write_range(&gpu, 128, &[2; 32], frame_index);
+
+        let slot = ring.get_read(frame_index).buffer.clone();
+        let writes: Vec<(u64, Vec<u8>)> = gpu
+            .writes_to(&slot)
+            .into_iter()
+            .map(|write| (write.offset, write.data))
+            .collect();
+        assert_eq!(writes, [(16, vec![1; 16]), (128, vec![2; 32])]);
+        assert_eq!(gpu.writes().len(), 2);
+    }
+}
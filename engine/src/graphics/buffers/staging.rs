@@ -0,0 +1,95 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder, MapMode};
+
+use crate::graphics::gpu_device::GpuDevice;
+
+// A MAP_WRITE staging buffer the CPU writes into directly, then copies to a
+// device-local buffer on the GPU timeline. wgpu can't keep a buffer mapped
+// while the GPU uses it, so after the copy is submitted it has to be mapped
+// again with `remap`, and it can only be written once that has completed.
+pub struct MappedStaging {
+    buffer: Buffer,
+    mapped: Arc<AtomicBool>,
+    // Unmapped and copied from, but not yet asked to map again.
+    needs_remap: bool,
+}
+
+impl MappedStaging {
+    pub fn new(gpu: &dyn GpuDevice, label: &str, size: u64) -> Self {
+        let buffer = gpu.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        Self {
+            buffer,
+            mapped: Arc::new(AtomicBool::new(true)),
+            needs_remap: false,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.load(Ordering::Acquire)
+    }
+
+    // Writes `data` into the mapped range and records a copy of it to
+    // `destination` at `offset`. Fails without touching anything if the
+    // buffer is still being mapped again, callers can fall back to a queue
+    // write.
+    pub fn write(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        destination: &Buffer,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), String> {
+        if !self.is_mapped() {
+            return Err("staging buffer is still being mapped".to_string());
+        }
+        if data.len() as u64 > self.size() {
+            return Err(format!(
+                "{} bytes don't fit the {} byte staging buffer",
+                data.len(),
+                self.size()
+            ));
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.buffer
+            .slice(..data.len() as u64)
+            .get_mapped_range_mut()
+            .copy_from_slice(data);
+        self.buffer.unmap();
+        self.mapped.store(false, Ordering::Release);
+        self.needs_remap = true;
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, destination, offset, data.len() as u64);
+        Ok(())
+    }
+
+    // Call after the encoder holding the copy has been submitted. The buffer
+    // is writable again once the device has been polled past the copy.
+    pub fn remap(&mut self) {
+        if !self.needs_remap {
+            return;
+        }
+        self.needs_remap = false;
+        let mapped = self.mapped.clone();
+        self.buffer
+            .slice(..)
+            .map_async(MapMode::Write, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+    }
+}
@@ -1,20 +1,33 @@
 use crate::{
-    graphics::buffers::{
-        BufferEntry, BufferInterface, GpuRingBuffer, bindgroups::create_bind_group, create_buffer,
+    graphics::{
+        buffers::{
+            BufferEntry, BufferInterface, DynamicUniformBuffer, GpuRingBuffer, RING_BUFFER_DEPTH,
+            bindgroups::create_bind_group, create_buffer,
+        },
+        gpu_device::GpuDevice,
+        shadows::ShadowMap,
     },
     utils::{RegisterKey, Registry},
 };
 use bytemuck::{Pod, Zeroable};
+use ecs::components::DirectionalLight;
 use glam::{Mat4, Vec3};
 use wgpu::{
-    BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BufferSize,
-    BufferUsages, Device, Queue, ShaderStages,
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindingResource, Buffer, BufferBinding, BufferSize, BufferUsages, Device, ShaderStages,
 };
 
-const MAX_INDIRECT_DRAWS: u64 = 65536;
+pub const MAX_INDIRECT_DRAWS: u64 = 65536;
+// Models each ring slot's storage buffer starts with, it grows with the scene.
+pub const INITIAL_MODEL_CAPACITY: u64 = 1024;
+
+// Leaves room for the scene to grow a while before the next reallocation.
+pub fn model_capacity_for(count: u64) -> u64 {
+    count.next_power_of_two().max(INITIAL_MODEL_CAPACITY)
+}
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
 pub struct IndirectDraw {
     pub index_count: u32,
     pub instance_count: u32,
@@ -23,8 +36,8 @@ pub struct IndirectDraw {
     pub first_instance: u32,
 }
 
-impl Default for IndirectDraw {
-    fn default() -> Self {
+impl IndirectDraw {
+    pub fn _new() -> Self {
         Self {
             index_count: 0,
             instance_count: 0,
@@ -33,39 +46,71 @@ impl Default for IndirectDraw {
             first_instance: 0,
         }
     }
-}
 
-impl IndirectDraw {
-    pub fn _new() -> Self {
-        Self {
-            index_count: 0,
-            instance_count: 0,
-            first_index: 0,
-            base_vertex: 0,
-            first_instance: 0,
+    // Read only for the vertex stage. The writable variant is for compute
+    // passes generating draws on the GPU, vertex shaders can't bind storage
+    // buffers writable without an extra feature, so it gets its own layout.
+    pub fn layout_entry(writable: bool) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: if writable {
+                wgpu::ShaderStages::COMPUTE
+            } else {
+                wgpu::ShaderStages::VERTEX
+            },
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !writable,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
         }
     }
 
     pub fn create_bind_group_layout(self, device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
+            entries: &[Self::layout_entry(false)],
             label: Some("indirect_draw_bind_group_layout"),
         })
     }
 
+    pub fn create_writable_bind_group_layout(self, device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[Self::layout_entry(true)],
+            label: Some("indirect_draw_writable_bind_group_layout"),
+        })
+    }
+
+    // One per ring slot, binding the same buffers the vertex stage reads.
+    pub fn create_writable_bind_groups(
+        device: &Device,
+        writable_layout: &BindGroupLayout,
+        indirect_draws: &GpuRingBuffer<IndirectDraw>,
+    ) -> Vec<BindGroup> {
+        (0..RING_BUFFER_DEPTH)
+            .map(|frame_index| {
+                create_bind_group(
+                    "indirect_draw_writable_bind_group",
+                    device,
+                    writable_layout,
+                    &vec![BindGroupEntry {
+                        binding: 0,
+                        resource: indirect_draws
+                            .get_read(frame_index)
+                            .buffer
+                            .as_entire_binding(),
+                    }],
+                )
+            })
+            .collect()
+    }
+
     pub fn create_and_store_buffers(
         self,
         device: &Device,
-        queue: &Queue,
+        gpu: &dyn GpuDevice,
         bind_group_layout: &BindGroupLayout,
         gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
         frame_index: usize,
@@ -77,7 +122,7 @@ impl IndirectDraw {
         ];
 
         let mut buffer_entries: Vec<BufferEntry> = Vec::new();
-        for _ in 0..3 {
+        for _ in 0..RING_BUFFER_DEPTH {
             let buffer = create_buffer(
                 device,
                 "indirect_draw_gpu",
@@ -97,14 +142,14 @@ impl IndirectDraw {
             );
 
             buffer_entries.push(BufferEntry {
-                buffer: buffer,
+                buffer,
                 bind_group: Some(bind_group),
                 element_count: 0,
             });
         }
 
         let mut triple_buffered_indirect_draw = GpuRingBuffer::<IndirectDraw>::new(buffer_entries);
-        triple_buffered_indirect_draw.write(queue, bytemuck::cast_slice(&vec![self]), frame_index);
+        triple_buffered_indirect_draw.write(gpu, bytemuck::cast_slice(&[self]), frame_index);
         gpu_buffer_registry.register_key(
             RegisterKey::from_label::<GpuRingBuffer<IndirectDraw>>("indirect_draw_buffer"),
             Box::new(triple_buffered_indirect_draw),
@@ -113,6 +158,16 @@ impl IndirectDraw {
     }
 }
 
+// Cameras one frame can upload, e.g. split-screen players or shadow cascades.
+pub const MAX_CAMERA_VIEWS: usize = 4;
+// Dynamic uniform offsets have to be multiples of 256 on every backend.
+const CAMERA_VIEW_INDEX_STRIDE: u64 = 256;
+
+// The dynamic offset that selects camera `view` in the camera bind group.
+pub fn camera_view_offset(view: u32) -> u32 {
+    view * CAMERA_VIEW_INDEX_STRIDE as u32
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct CameraUniform {
@@ -122,8 +177,8 @@ pub struct CameraUniform {
 
 impl Default for CameraUniform {
     fn default() -> Self {
-        Self {
-            view: Mat4::look_at_rh(
+        Self::from_matrices(
+            Mat4::look_at_rh(
                 Vec3 {
                     x: 0.0,
                     y: 0.0,
@@ -131,53 +186,90 @@ impl Default for CameraUniform {
                 },
                 Vec3::ZERO,
                 Vec3::Y,
-            )
-            .to_cols_array_2d(),
-            projection: Mat4::perspective_rh(0.785, 16.0 / 9.0, 0.1, 10.0).to_cols_array_2d(),
-        }
+            ),
+            Mat4::perspective_rh(0.785, 16.0 / 9.0, 0.1, 10.0),
+        )
     }
 }
 
 impl CameraUniform {
-    pub fn _new(view: [[f32; 4]; 4], projection: [[f32; 4]; 4]) -> Self {
+    // The shader reads column-major matrices, same layout as glam.
+    pub fn from_matrices(view: Mat4, projection: Mat4) -> Self {
         Self {
-            view: view,
-            projection: projection,
+            view: view.to_cols_array_2d(),
+            projection: projection.to_cols_array_2d(),
         }
     }
 
+    pub fn _new(view: [[f32; 4]; 4], projection: [[f32; 4]; 4]) -> Self {
+        Self { view, projection }
+    }
+
     pub fn create_bind_group_layout(self, device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("camera_bind_group_layout"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                count: None,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(size_of::<CameraUniform>() as u64),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<CameraUniform>() as u64),
+                    },
+                    visibility: ShaderStages::VERTEX,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(size_of::<[u32; 4]>() as u64),
+                    },
+                    visibility: ShaderStages::VERTEX,
                 },
-                visibility: ShaderStages::VERTEX,
-            }],
+            ],
         })
     }
 
     pub fn create_and_store_buffers(
         self,
         device: &Device,
-        queue: &Queue,
+        gpu: &dyn GpuDevice,
         bind_group_layout: &BindGroupLayout,
         gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
         frame_index: usize,
     ) -> Result<(), String> {
-        let buffer_uses = vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST];
+        let buffer_uses = vec![
+            BufferUsages::STORAGE,
+            BufferUsages::COPY_DST,
+            BufferUsages::COPY_SRC,
+        ];
+
+        // Slot n holds view index n, a pass picks its camera by binding the
+        // slot at camera_view_offset(n).
+        let view_index = create_buffer(
+            device,
+            "camera_view_index",
+            MAX_CAMERA_VIEWS as u64 * CAMERA_VIEW_INDEX_STRIDE,
+            vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST],
+            false,
+        );
+        for view in 0..MAX_CAMERA_VIEWS as u32 {
+            gpu.write_buffer(
+                &view_index,
+                camera_view_offset(view) as u64,
+                bytemuck::bytes_of(&[view, 0, 0, 0]),
+            );
+        }
 
         let mut buffer_entries: Vec<BufferEntry> = Vec::new();
-        for _ in 0..3 {
+        for _ in 0..RING_BUFFER_DEPTH {
             let buffer = create_buffer(
                 device,
-                "camera_gpu_uniform",
-                size_of::<CameraUniform>() as u64,
+                "camera_gpu_views",
+                (MAX_CAMERA_VIEWS * size_of::<CameraUniform>()) as u64,
                 buffer_uses.clone(),
                 false,
             );
@@ -186,14 +278,24 @@ impl CameraUniform {
                 "camera_gpu_uniform_bind_group",
                 device,
                 bind_group_layout,
-                &vec![BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
+                &vec![
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &view_index,
+                            offset: 0,
+                            size: BufferSize::new(size_of::<[u32; 4]>() as u64),
+                        }),
+                    },
+                ],
             );
 
             buffer_entries.push(BufferEntry {
-                buffer: buffer,
+                buffer,
                 bind_group: Some(bind_group),
                 element_count: 0,
             });
@@ -201,7 +303,7 @@ impl CameraUniform {
 
         let mut triple_buffered_camera_uniform =
             GpuRingBuffer::<CameraUniform>::new(buffer_entries);
-        triple_buffered_camera_uniform.write(queue, bytemuck::bytes_of(&self), frame_index);
+        triple_buffered_camera_uniform.write(gpu, bytemuck::bytes_of(&self), frame_index);
         gpu_buffer_registry.register_key(
             RegisterKey::from_label::<GpuRingBuffer<CameraUniform>>("camera_gpu_uniform_triple"),
             Box::new(triple_buffered_camera_uniform),
@@ -214,92 +316,384 @@ impl CameraUniform {
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct ModelUniform {
     pub model: [[f32; 4]; 4],
+    pub bone_offset: u32,
+    pub bone_count: u32,
+    pub _padding: [u32; 2],
 }
 
 impl Default for ModelUniform {
     fn default() -> Self {
-        Self {
-            model: Mat4::IDENTITY.to_cols_array_2d(),
-        }
+        Self::from_matrix(Mat4::IDENTITY)
     }
 }
 
 impl ModelUniform {
+    pub fn from_matrix(model: Mat4) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            bone_offset: 0,
+            bone_count: 0,
+            _padding: [0; 2],
+        }
+    }
+
     pub fn _new(model: [[f32; 4]; 4]) -> Self {
-        Self { model }
+        Self {
+            model,
+            ..Default::default()
+        }
+    }
+
+    // For pipelines that bind one model per draw instead of indexing the
+    // instanced storage buffer.
+    pub fn create_dynamic_buffer(
+        device: &Device,
+        capacity: u32,
+    ) -> DynamicUniformBuffer<ModelUniform> {
+        DynamicUniformBuffer::new(
+            device,
+            "model_dynamic_uniform",
+            capacity,
+            ShaderStages::VERTEX,
+        )
     }
 
     pub fn create_bind_group_layout(self, device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("model_bind_group_layout"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                count: None,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(
-                        MAX_INDIRECT_DRAWS * size_of::<ModelUniform>() as u64,
-                    ),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<ModelUniform>() as u64),
+                    },
+                    visibility: ShaderStages::VERTEX,
+                },
+                // Bone palette, shares the group since every bind group slot
+                // is already taken on downlevel limits.
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            MAX_BONES * size_of::<BoneMatrix>() as u64,
+                        ),
+                    },
+                    visibility: ShaderStages::VERTEX,
                 },
-                visibility: ShaderStages::VERTEX,
-            }],
+            ],
         })
     }
 
     pub fn create_and_store_buffers(
         self,
         device: &Device,
-        queue: &Queue,
+        gpu: &dyn GpuDevice,
         bind_group_layout: &BindGroupLayout,
         gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
         frame_index: usize,
     ) -> Result<(), String> {
-        let buffer_uses = vec![BufferUsages::STORAGE, BufferUsages::COPY_DST];
+        let buffer_uses = vec![
+            BufferUsages::STORAGE,
+            BufferUsages::COPY_DST,
+            BufferUsages::COPY_SRC,
+        ];
 
         let mut buffer_entires: Vec<BufferEntry> = Vec::new();
-        for _ in 0..3 {
-            let buffer = create_buffer(
+        let mut bone_buffer_entries: Vec<BufferEntry> = Vec::new();
+        for _ in 0..RING_BUFFER_DEPTH {
+            let buffer = Self::create_model_buffer(device, INITIAL_MODEL_CAPACITY);
+
+            let bone_buffer = create_buffer(
                 device,
-                "model_gpu_uniform",
-                MAX_INDIRECT_DRAWS * size_of::<ModelUniform>() as u64,
+                "bone_palette_gpu",
+                MAX_BONES * size_of::<BoneMatrix>() as u64,
                 buffer_uses.clone(),
                 false,
             );
 
-            let bind_group = create_bind_group(
-                "model_gpu_uniform_bind_group",
-                device,
-                bind_group_layout,
-                &vec![BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
-            );
+            let bind_group =
+                Self::create_model_bind_group(device, bind_group_layout, &buffer, &bone_buffer);
             buffer_entires.push(BufferEntry {
                 buffer,
                 bind_group: Some(bind_group),
                 element_count: 0,
             });
+            bone_buffer_entries.push(BufferEntry {
+                buffer: bone_buffer,
+                bind_group: None,
+                element_count: 0,
+            });
         }
 
-        let empty_models = vec![
-            ModelUniform {
-                model: Mat4::IDENTITY.to_cols_array_2d(),
-            };
-            MAX_INDIRECT_DRAWS as usize
-        ];
+        let empty_models = vec![ModelUniform::default(); INITIAL_MODEL_CAPACITY as usize];
 
         let mut triple_buffered_model_uniform = GpuRingBuffer::<ModelUniform>::new(buffer_entires);
-        triple_buffered_model_uniform.write(
-            queue,
-            bytemuck::cast_slice(&empty_models),
-            frame_index,
-        );
+        triple_buffered_model_uniform.write(gpu, bytemuck::cast_slice(&empty_models), frame_index);
         gpu_buffer_registry.register_key(
             RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>("model_gpu_uniform_triple"),
             Box::new(triple_buffered_model_uniform),
         );
+        gpu_buffer_registry.register_key(
+            RegisterKey::from_label::<GpuRingBuffer<BoneMatrix>>("bone_palette_gpu_triple"),
+            Box::new(GpuRingBuffer::<BoneMatrix>::new(bone_buffer_entries)),
+        );
+        Ok(())
+    }
+
+    fn create_model_buffer(device: &Device, capacity: u64) -> Buffer {
+        create_buffer(
+            device,
+            "model_gpu_uniform",
+            capacity * size_of::<ModelUniform>() as u64,
+            vec![
+                BufferUsages::STORAGE,
+                BufferUsages::COPY_DST,
+                BufferUsages::COPY_SRC,
+            ],
+            false,
+        )
+    }
+
+    fn create_model_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        model_buffer: &Buffer,
+        bone_buffer: &Buffer,
+    ) -> BindGroup {
+        create_bind_group(
+            "model_gpu_uniform_bind_group",
+            device,
+            bind_group_layout,
+            &vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: model_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: bone_buffer.as_entire_binding(),
+                },
+            ],
+        )
+    }
+
+    // Makes sure `frame_index`'s model buffer holds `count` models, replacing
+    // it and its bind group with a bigger one if not. Only that ring slot is
+    // touched, the others may still be in flight and grow when they're next
+    // written. Returns true if the buffer was replaced.
+    pub fn reserve_models(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
+        frame_index: usize,
+        count: u64,
+    ) -> Result<bool, String> {
+        let bone_buffer = gpu_buffer_registry
+            .get(&RegisterKey::from_label::<GpuRingBuffer<BoneMatrix>>(
+                "bone_palette_gpu_triple",
+            ))
+            .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<BoneMatrix>>())
+            .ok_or("bone palette buffer is missing")?
+            .get_read(frame_index)
+            .buffer
+            .clone();
+        let model_entry = gpu_buffer_registry
+            .get_mut(&RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>(
+                "model_gpu_uniform_triple",
+            ))
+            .and_then(|entry| {
+                entry
+                    .as_mut_any()
+                    .downcast_mut::<GpuRingBuffer<ModelUniform>>()
+            })
+            .ok_or("model buffer is missing")?
+            .get_write(frame_index);
+
+        if count * size_of::<ModelUniform>() as u64 <= model_entry.buffer.size() {
+            return Ok(false);
+        }
+        let buffer = Self::create_model_buffer(device, model_capacity_for(count));
+        model_entry.bind_group = Some(Self::create_model_bind_group(
+            device,
+            bind_group_layout,
+            &buffer,
+            &bone_buffer,
+        ));
+        model_entry.buffer = buffer;
+        Ok(true)
+    }
+}
+
+pub const MAX_BONES: u64 = 16384;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct BoneMatrix {
+    pub matrix: [[f32; 4]; 4],
+}
+
+impl BoneMatrix {
+    pub fn from_palette(palette: &[Mat4]) -> Vec<BoneMatrix> {
+        palette
+            .iter()
+            .map(|matrix| BoneMatrix {
+                matrix: matrix.to_cols_array_2d(),
+            })
+            .collect()
+    }
+}
+
+pub const MAX_LIGHTS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+pub struct DirectionalLightUniform {
+    pub direction: [f32; 3],
+    pub intensity: f32,
+    pub color: [f32; 3],
+    pub _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct LightUniform {
+    pub light_count: u32,
+    // Nonzero when the first light casts shadows through light_view_proj.
+    pub shadows_enabled: u32,
+    pub _padding: [u32; 2],
+    pub light_view_proj: [[f32; 4]; 4],
+    pub lights: [DirectionalLightUniform; MAX_LIGHTS],
+}
+
+impl Default for LightUniform {
+    fn default() -> Self {
+        Self {
+            light_count: 0,
+            shadows_enabled: 0,
+            _padding: [0; 2],
+            light_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            lights: [DirectionalLightUniform::default(); MAX_LIGHTS],
+        }
+    }
+}
+
+impl LightUniform {
+    pub fn from_lights(lights: &[DirectionalLight]) -> Self {
+        let mut uniform = Self::default();
+        for (slot, light) in uniform.lights.iter_mut().zip(lights) {
+            *slot = DirectionalLightUniform {
+                direction: light.direction.normalize_or_zero().to_array(),
+                intensity: light.intensity,
+                color: light.color.to_array(),
+                _padding: 0.0,
+            };
+        }
+        uniform.light_count = lights.len().min(MAX_LIGHTS) as u32;
+        uniform
+    }
+
+    // Shadows the first light with what the shadow pass rendered through
+    // `light_view_proj`.
+    pub fn with_shadow(mut self, light_view_proj: Mat4) -> Self {
+        self.shadows_enabled = 1;
+        self.light_view_proj = light_view_proj.to_cols_array_2d();
+        self
+    }
+
+    pub fn create_bind_group_layout(self, device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("light_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    count: None,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<LightUniform>() as u64),
+                    },
+                    visibility: ShaderStages::FRAGMENT,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    visibility: ShaderStages::FRAGMENT,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    count: None,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    visibility: ShaderStages::FRAGMENT,
+                },
+            ],
+        })
+    }
+
+    pub fn create_and_store_buffers(
+        self,
+        device: &Device,
+        gpu: &dyn GpuDevice,
+        bind_group_layout: &BindGroupLayout,
+        shadow_map: &ShadowMap,
+        gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
+        frame_index: usize,
+    ) -> Result<(), String> {
+        let buffer_uses = vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST];
+
+        let mut buffer_entries: Vec<BufferEntry> = Vec::new();
+        for _ in 0..RING_BUFFER_DEPTH {
+            let buffer = create_buffer(
+                device,
+                "light_gpu_uniform",
+                size_of::<LightUniform>() as u64,
+                buffer_uses.clone(),
+                false,
+            );
+
+            let bind_group = create_bind_group(
+                "light_gpu_uniform_bind_group",
+                device,
+                bind_group_layout,
+                &vec![
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&shadow_map.view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&shadow_map.sampler),
+                    },
+                ],
+            );
+
+            buffer_entries.push(BufferEntry {
+                buffer,
+                bind_group: Some(bind_group),
+                element_count: 0,
+            });
+        }
+
+        let mut triple_buffered_light_uniform = GpuRingBuffer::<LightUniform>::new(buffer_entries);
+        triple_buffered_light_uniform.write(gpu, bytemuck::bytes_of(&self), frame_index);
+        gpu_buffer_registry.register_key(
+            RegisterKey::from_label::<GpuRingBuffer<LightUniform>>("light_gpu_uniform_triple"),
+            Box::new(triple_buffered_light_uniform),
+        );
         Ok(())
     }
 }
@@ -0,0 +1,381 @@
+use bytemuck::{Pod, Zeroable};
+use ecs::math::{Aabb, Frustum};
+use wgpu::{
+    BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType,
+    BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, Features, PipelineLayoutDescriptor, RenderPass,
+    ShaderModule, ShaderStages,
+};
+
+use crate::graphics::{
+    buffers::{
+        bindgroups::create_bind_group, create_buffer, layouts::create_bind_group_layout,
+        submissions::IndirectDraw,
+    },
+    gpu_device::GpuDevice,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+pub struct CullParams {
+    pub planes: [[f32; 4]; 6],
+    pub draw_count: u32,
+    _padding: [u32; 3],
+}
+
+impl CullParams {
+    pub fn new(frustum: &Frustum, draw_count: u32) -> Self {
+        Self {
+            planes: frustum
+                .planes
+                .map(|plane| plane.normal.extend(plane.distance).to_array()),
+            draw_count,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// One draw to test. The draw should cover a single instance, its model matrix
+// is read from the model buffer at `first_instance`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+pub struct CullInstance {
+    pub bounds_min: [f32; 3],
+    pub cullable: u32,
+    pub bounds_max: [f32; 3],
+    _padding0: u32,
+    pub draw: IndirectDraw,
+    _padding1: [u32; 3],
+}
+
+impl CullInstance {
+    // Draws without bounds are always kept.
+    pub fn new(draw: IndirectDraw, bounds: Option<&Aabb>) -> Self {
+        let (bounds_min, bounds_max) = bounds
+            .map(|bounds| (bounds.min.to_array(), bounds.max.to_array()))
+            .unwrap_or_default();
+        Self {
+            bounds_min,
+            cullable: bounds.is_some() as u32,
+            bounds_max,
+            _padding0: 0,
+            draw,
+            _padding1: [0; 3],
+        }
+    }
+}
+
+// Frustum culls draws on the GPU. A compute pass tests each instance's bounds
+// against the camera planes and compacts the survivors into `visible_draws`,
+// counting them in `visible_count` so the render pass can draw them with
+// multi_draw_indexed_indirect_count without reading anything back.
+pub struct GpuCuller {
+    pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    params: Buffer,
+    instances: Buffer,
+    visible_draws: Buffer,
+    visible_count: Buffer,
+    capacity: u32,
+    draw_count: u32,
+}
+
+impl GpuCuller {
+    // The compute pass runs without them, drawing its output needs both.
+    pub const REQUIRED_FEATURES: Features =
+        Features::MULTI_DRAW_INDIRECT_COUNT.union(Features::INDIRECT_FIRST_INSTANCE);
+
+    pub fn supported(device: &Device) -> bool {
+        device.features().contains(Self::REQUIRED_FEATURES)
+    }
+
+    // `shader` is cull.wgsl, `capacity` is the most draws one upload can hold.
+    pub fn new(device: &Device, shader: &ShaderModule, capacity: u32) -> Self {
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = create_bind_group_layout(
+            "gpu culling",
+            device,
+            &vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("gpu culling"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("gpu culling"),
+            layout: Some(&pipeline_layout),
+            module: shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let capacity = capacity.max(1);
+        Self {
+            pipeline,
+            bind_group_layout,
+            params: create_buffer(
+                device,
+                "cull params",
+                size_of::<CullParams>() as u64,
+                vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST],
+                false,
+            ),
+            instances: create_buffer(
+                device,
+                "cull instances",
+                capacity as u64 * size_of::<CullInstance>() as u64,
+                vec![BufferUsages::STORAGE, BufferUsages::COPY_DST],
+                false,
+            ),
+            visible_draws: create_buffer(
+                device,
+                "visible draws",
+                capacity as u64 * size_of::<IndirectDraw>() as u64,
+                vec![
+                    BufferUsages::STORAGE,
+                    BufferUsages::INDIRECT,
+                    BufferUsages::COPY_SRC,
+                ],
+                false,
+            ),
+            visible_count: create_buffer(
+                device,
+                "visible draw count",
+                size_of::<u32>() as u64,
+                vec![
+                    BufferUsages::STORAGE,
+                    BufferUsages::INDIRECT,
+                    BufferUsages::COPY_DST,
+                    BufferUsages::COPY_SRC,
+                ],
+                false,
+            ),
+            capacity,
+            draw_count: 0,
+        }
+    }
+
+    pub fn upload(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        frustum: &Frustum,
+        instances: &[CullInstance],
+    ) -> Result<(), String> {
+        self.draw_count = 0;
+        if instances.len() > self.capacity as usize {
+            return Err(format!(
+                "{} draws to cull, the culler holds {}",
+                instances.len(),
+                self.capacity
+            ));
+        }
+        self.draw_count = instances.len() as u32;
+        gpu.write_buffer(
+            &self.params,
+            0,
+            bytemuck::bytes_of(&CullParams::new(frustum, self.draw_count)),
+        );
+        if !instances.is_empty() {
+            gpu.write_buffer(&self.instances, 0, bytemuck::cast_slice(instances));
+        }
+        Ok(())
+    }
+
+    // `models` is the model storage buffer the draws' first_instance indexes,
+    // the same one the render pass reads.
+    pub fn encode(&self, device: &Device, encoder: &mut CommandEncoder, models: &Buffer) {
+        encoder.clear_buffer(&self.visible_count, 0, None);
+        if self.draw_count == 0 {
+            return;
+        }
+
+        let bind_group = create_bind_group(
+            "gpu culling",
+            device,
+            &self.bind_group_layout,
+            &vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: models.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: self.instances.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: self.visible_draws.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: self.visible_count.as_entire_binding(),
+                },
+            ],
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("gpu culling"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.draw_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    // Expects the vertex and index buffers of the culled draws to be bound.
+    pub fn draw(&self, render_pass: &mut RenderPass) {
+        if self.draw_count == 0 {
+            return;
+        }
+        render_pass.multi_draw_indexed_indirect_count(
+            &self.visible_draws,
+            0,
+            &self.visible_count,
+            0,
+            self.draw_count,
+        );
+    }
+
+    pub fn draw_count(&self) -> u32 {
+        self.draw_count
+    }
+
+    pub fn visible_draws(&self) -> &Buffer {
+        &self.visible_draws
+    }
+
+    pub fn visible_count(&self) -> &Buffer {
+        &self.visible_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, sync::mpsc};
+
+    use glam::{Mat4, Vec3};
+    use wgpu::{
+        Backends, CommandEncoderDescriptor, Instance, MapMode, PollType, ShaderModuleDescriptor,
+        ShaderSource,
+    };
+
+    use super::*;
+    use crate::graphics::{GPUContext, buffers::submissions::ModelUniform};
+
+    fn draw(first_instance: u32) -> IndirectDraw {
+        IndirectDraw {
+            index_count: 36,
+            instance_count: 1,
+            first_index: 0,
+            base_vertex: 0,
+            first_instance,
+        }
+    }
+
+    // Runs on whatever adapter the machine has and passes without one.
+    #[test]
+    fn dispatch_counts_the_visible_draws() {
+        let instance = Instance::default();
+        let Ok(gpu) = GPUContext::init(&instance, Backends::all(), None) else {
+            return;
+        };
+        let device = &gpu.device;
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("cull"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/cull.wgsl"))),
+        });
+        let mut culler = GpuCuller::new(device, &shader, 8);
+
+        // In front of, behind and far beside a camera at the origin looking
+        // down -z, the last without bounds so it is never culled.
+        let models = [
+            ModelUniform::from_matrix(Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0))),
+            ModelUniform::from_matrix(Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0))),
+            ModelUniform::from_matrix(Mat4::from_translation(Vec3::new(500.0, 0.0, -5.0))),
+        ];
+        let model_buffer = create_buffer(
+            device,
+            "cull test models",
+            size_of_val(&models) as u64,
+            vec![BufferUsages::STORAGE, BufferUsages::COPY_DST],
+            false,
+        );
+        gpu.write_buffer(&model_buffer, 0, bytemuck::cast_slice(&models));
+
+        let bounds = Aabb {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        };
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let projection = Mat4::perspective_rh(60_f32.to_radians(), 1.0, 0.1, 100.0);
+        culler
+            .upload(
+                &gpu,
+                &Frustum::from_view_proj(projection * view),
+                &[
+                    CullInstance::new(draw(0), Some(&bounds)),
+                    CullInstance::new(draw(1), Some(&bounds)),
+                    CullInstance::new(draw(2), None),
+                ],
+            )
+            .unwrap();
+
+        let readback = create_buffer(
+            device,
+            "cull test readback",
+            size_of::<u32>() as u64,
+            vec![BufferUsages::COPY_DST, BufferUsages::MAP_READ],
+            false,
+        );
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("cull test"),
+        });
+        culler.encode(device, &mut encoder, &model_buffer);
+        encoder.copy_buffer_to_buffer(culler.visible_count(), 0, &readback, 0, 4);
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(PollType::Wait).unwrap();
+        receiver.recv().unwrap().unwrap();
+        let visible_count: u32 = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        assert_eq!(visible_count, 2);
+    }
+}
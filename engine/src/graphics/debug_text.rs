@@ -0,0 +1,432 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupEntry, BindGroupLayoutEntry, BindingResource, BindingType,
+    BlendState, Buffer, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoder, Extent3d, FilterMode, FragmentState, IndexFormat, MultisampleState, Origin3d,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDimension, VertexBufferLayout, VertexState, VertexStepMode, vertex_attr_array,
+};
+
+use crate::graphics::{
+    GPUContext,
+    buffers::{bindgroups::create_bind_group, create_buffer, layouts::create_bind_group_layout},
+    gpu_device::GpuDevice,
+    mesh::immediate::ImmediateGeometry,
+    passes::{DepthConfig, PassConfig},
+    stats::FrameStats,
+};
+
+// Room the overlay starts with, it grows past this if a frame needs more.
+pub const DEBUG_GLYPH_CAPACITY: u32 = 512;
+
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+// Atlas cells are a pixel wider than the glyphs so neighbours never bleed in.
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+
+// Everything the atlas has a glyph for. Lowercase letters are drawn as
+// uppercase and anything else missing as '?'.
+const GLYPH_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ.:-/%()_,=+?";
+
+// One row per byte, top to bottom, the glyph's leftmost column is bit 4.
+#[rustfmt::skip]
+const GLYPH_ROWS: [[u8; GLYPH_HEIGHT as usize]; 48] = [
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+];
+
+fn glyph_index(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    GLYPH_CHARS
+        .chars()
+        .position(|glyph| glyph == c)
+        .unwrap_or(GLYPH_CHARS.len() - 1)
+}
+
+pub fn atlas_size() -> (u32, u32) {
+    (GLYPH_CHARS.len() as u32 * CELL_WIDTH, GLYPH_HEIGHT)
+}
+
+// R8 coverage, one byte per pixel, row major.
+pub fn atlas_pixels() -> Vec<u8> {
+    let (width, height) = atlas_size();
+    let mut pixels = vec![0; (width * height) as usize];
+    for (glyph, rows) in GLYPH_ROWS.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    let pixel_x = glyph as u32 * CELL_WIDTH + x;
+                    pixels[y * width as usize + pixel_x as usize] = 255;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct TextVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl TextVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x4
+    ];
+
+    pub fn create_buffer_layout<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Appends a quad per visible character, starting at `origin` in pixels from
+// the top left. Spaces only advance the pen and '\n' starts a new line.
+pub fn build_glyph_quads(
+    text: &str,
+    origin: Vec2,
+    scale: f32,
+    color: [f32; 4],
+    vertices: &mut Vec<TextVertex>,
+    indices: &mut Vec<u32>,
+) {
+    let (atlas_width, _) = atlas_size();
+    let glyph_size = Vec2::new(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32) * scale;
+    let mut pen = origin;
+
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                pen = Vec2::new(origin.x, pen.y + LINE_HEIGHT as f32 * scale);
+                continue;
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let u0 = (glyph_index(c) as u32 * CELL_WIDTH) as f32 / atlas_width as f32;
+                let u1 = u0 + GLYPH_WIDTH as f32 / atlas_width as f32;
+                let first = vertices.len() as u32;
+                let corners = [
+                    (Vec2::ZERO, [u0, 0.0]),
+                    (Vec2::new(glyph_size.x, 0.0), [u1, 0.0]),
+                    (glyph_size, [u1, 1.0]),
+                    (Vec2::new(0.0, glyph_size.y), [u0, 1.0]),
+                ];
+                vertices.extend(corners.iter().map(|(offset, uv)| TextVertex {
+                    position: (pen + *offset).to_array(),
+                    uv: *uv,
+                    color,
+                }));
+                indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+            }
+        }
+        pen.x += CELL_WIDTH as f32 * scale;
+    }
+}
+
+pub fn debug_overlay_text(
+    fps: f64,
+    gpu_frame_time_ms: Option<f32>,
+    entity_count: usize,
+    stats: &FrameStats,
+) -> String {
+    let gpu = gpu_frame_time_ms
+        .map(|gpu_ms| format!(" GPU {gpu_ms:.2} MS"))
+        .unwrap_or_default();
+    format!(
+        "FPS {fps:.0}{gpu}\nENTITIES {entity_count}\nDRAWS {} INSTANCES {} TRIS {}",
+        stats.draw_calls, stats.instances, stats.triangles
+    )
+}
+
+// Draws text over the finished frame, for FPS and frame stats. The glyphs
+// come from a small built-in 5x7 pixel font.
+pub struct DebugOverlay {
+    pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    projection: Buffer,
+    geometry: ImmediateGeometry<TextVertex>,
+    frame_index: usize,
+    index_count: u32,
+    vertices: Vec<TextVertex>,
+    indices: Vec<u32>,
+}
+
+impl DebugOverlay {
+    // `shader` is debug_text.wgsl. The glyph buffers start with room for
+    // `initial_glyphs` characters and grow when a frame needs more.
+    pub fn new(
+        gpu_context: &GPUContext,
+        shader: &ShaderModule,
+        target_format: TextureFormat,
+        initial_glyphs: u32,
+    ) -> Self {
+        let device = &gpu_context.device;
+        let queue = &gpu_context.queue;
+        let (atlas_width, atlas_height) = atlas_size();
+        let atlas_extent = Extent3d {
+            width: atlas_width,
+            height: atlas_height,
+            depth_or_array_layers: 1,
+        };
+        let atlas = device.create_texture(&TextureDescriptor {
+            label: Some("debug font atlas"),
+            size: atlas_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &atlas,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &atlas_pixels(),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(atlas_width),
+                rows_per_image: Some(atlas_height),
+            },
+            atlas_extent,
+        );
+        let atlas_view = atlas.create_view(&Default::default());
+        // Nearest keeps the pixel font crisp at integer scales.
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("debug font sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let projection = create_buffer(
+            device,
+            "debug text projection",
+            size_of::<Mat4>() as u64,
+            vec![BufferUsages::UNIFORM, BufferUsages::COPY_DST],
+            false,
+        );
+
+        let bind_group_layout = create_bind_group_layout(
+            "debug text",
+            device,
+            &vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+        let bind_group = create_bind_group(
+            "debug text",
+            device,
+            &bind_group_layout,
+            &vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: projection.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&atlas_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("debug text"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("debug text"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[TextVertex::create_buffer_layout()],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: target_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            projection,
+            geometry: ImmediateGeometry::new(
+                gpu_context,
+                "debug text",
+                initial_glyphs as u64 * 4,
+                initial_glyphs as u64 * 6,
+            ),
+            frame_index: 0,
+            index_count: 0,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    // Lays out `text` for a target of `width` by `height` pixels.
+    pub fn prepare(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        text: &str,
+        width: u32,
+        height: u32,
+        frame_index: usize,
+    ) {
+        let projection = Mat4::orthographic_rh(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+        gpu.write_buffer(
+            &self.projection,
+            0,
+            bytemuck::bytes_of(&projection.to_cols_array_2d()),
+        );
+
+        self.vertices.clear();
+        self.indices.clear();
+        build_glyph_quads(
+            text,
+            Vec2::splat(8.0),
+            2.0,
+            [1.0, 1.0, 1.0, 1.0],
+            &mut self.vertices,
+            &mut self.indices,
+        );
+        let draw = self.geometry.push(&self.vertices, &self.indices);
+        self.geometry.flush(gpu, frame_index);
+        self.index_count = draw.index_count;
+        self.frame_index = frame_index;
+    }
+
+    // Draws on top of whatever `view` already holds.
+    pub fn encode(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        if self.index_count == 0 {
+            return;
+        }
+        let pass_config = PassConfig::overlay(DepthConfig::None);
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("debug text"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: pass_config.color_ops(),
+            })],
+            depth_stencil_attachment: pass_config.depth_attachment(None),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.geometry.vertex_buffer(self.frame_index).slice(..));
+        render_pass.set_index_buffer(
+            self.geometry.index_buffer(self.frame_index).slice(..),
+            IndexFormat::Uint32,
+        );
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
@@ -0,0 +1,148 @@
+use ecs::components::PipelineKey;
+
+use crate::graphics::buffers::submissions::IndirectDraw;
+
+// One indirect draw plus the state it needs bound: which pipeline draws it
+// and which mesh stream its vertices and indices live in.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawCommand {
+    pub pipeline: PipelineKey,
+    pub stream: u32,
+    pub draw: IndirectDraw,
+}
+
+// How a pipeline's instances are ordered by distance to the camera. Front
+// to back lets early depth testing skip hidden fragments, blended geometry
+// has to be drawn back to front to composite correctly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrawOrder {
+    #[default]
+    FrontToBack,
+    BackToFront,
+}
+
+// The frame's draws in the order they are recorded.
+#[derive(Debug, Clone, Default)]
+pub struct DrawList {
+    commands: Vec<DrawCommand>,
+    // The snapshot renderable behind each model matrix, in buffer order.
+    instance_order: Vec<usize>,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    // Groups draws by pipeline, then stream, so each is bound once. The sort
+    // is stable, draws sharing both keep the order they were pushed in.
+    pub fn sort(&mut self) {
+        self.commands
+            .sort_by_key(|command| (command.pipeline, command.stream));
+    }
+
+    pub fn set_instance_order(&mut self, instance_order: Vec<usize>) {
+        self.instance_order = instance_order;
+    }
+
+    pub fn instance_order(&self) -> &[usize] {
+        &self.instance_order
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // In command order, what gets written to the indirect buffer.
+    pub fn indirect_draws(&self) -> Vec<IndirectDraw> {
+        self.commands.iter().map(|command| command.draw).collect()
+    }
+
+    // The pipeline and stream every draw shares, None when the list is empty
+    // or binds more than one of either.
+    pub fn single_batch(&self) -> Option<(PipelineKey, u32)> {
+        let first = self.commands.first()?;
+        self.commands
+            .iter()
+            .all(|command| command.pipeline == first.pipeline && command.stream == first.stream)
+            .then_some((first.pipeline, first.stream))
+    }
+
+    // Every pipeline bound while recording the list, in order.
+    pub fn pipeline_switches(&self) -> Vec<PipelineKey> {
+        let mut switches: Vec<PipelineKey> = Vec::new();
+        for command in &self.commands {
+            if switches.last() != Some(&command.pipeline) {
+                switches.push(command.pipeline);
+            }
+        }
+        switches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(pipeline: u32, stream: u32) -> DrawCommand {
+        DrawCommand {
+            pipeline: PipelineKey(pipeline),
+            stream,
+            draw: IndirectDraw::default(),
+        }
+    }
+
+    #[test]
+    fn sorting_binds_each_pipeline_once() {
+        let mut draw_list = DrawList::new();
+        for pipeline in [1, 0, 1, 0, 1] {
+            draw_list.push(command(pipeline, 0));
+        }
+        assert_eq!(
+            draw_list.pipeline_switches(),
+            [
+                PipelineKey(1),
+                PipelineKey(0),
+                PipelineKey(1),
+                PipelineKey(0),
+                PipelineKey(1)
+            ]
+        );
+
+        draw_list.sort();
+        assert_eq!(
+            draw_list.pipeline_switches(),
+            [PipelineKey(0), PipelineKey(1)]
+        );
+    }
+
+    #[test]
+    fn single_batch_needs_one_pipeline_and_stream() {
+        let mut draw_list = DrawList::new();
+        assert_eq!(draw_list.single_batch(), None);
+
+        draw_list.push(command(2, 1));
+        draw_list.push(command(2, 1));
+        assert_eq!(draw_list.single_batch(), Some((PipelineKey(2), 1)));
+
+        draw_list.push(command(2, 3));
+        assert_eq!(draw_list.single_batch(), None);
+
+        let mut draw_list = DrawList::new();
+        draw_list.push(command(0, 1));
+        draw_list.push(command(2, 1));
+        assert_eq!(draw_list.single_batch(), None);
+    }
+}
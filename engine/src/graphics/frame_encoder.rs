@@ -0,0 +1,59 @@
+use wgpu::{CommandBuffer, CommandEncoder, Queue, SubmissionIndex};
+
+use crate::graphics::GPUContext;
+
+// Collects a frame's command buffers so they reach the queue in a single
+// submit, in the order they were recorded, however many encoders the frame
+// used. Nothing is submitted until `submit`.
+#[derive(Debug, Default)]
+pub struct FrameEncoder {
+    command_buffers: Vec<CommandBuffer>,
+    labels: Vec<String>,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records into a new encoder and keeps the finished command buffer.
+    pub fn record<R>(
+        &mut self,
+        gpu_context: &GPUContext,
+        label: &str,
+        record: impl FnOnce(&mut CommandEncoder) -> R,
+    ) -> R {
+        let mut encoder = gpu_context.begin_encoder(label);
+        let result = record(&mut encoder);
+        self.push(label, encoder.finish());
+        result
+    }
+
+    // For encoders that had to outlive a `record` call, e.g. one borrowed by
+    // several upload helpers before it was finished.
+    pub fn push(&mut self, label: impl Into<String>, command_buffer: CommandBuffer) {
+        self.labels.push(label.into());
+        self.command_buffers.push(command_buffer);
+    }
+
+    // In submission order.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.labels.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.command_buffers.is_empty()
+    }
+
+    // None if nothing was recorded.
+    pub fn submit(self, queue: &Queue) -> Option<SubmissionIndex> {
+        if self.command_buffers.is_empty() {
+            return None;
+        }
+        Some(queue.submit(self.command_buffers))
+    }
+}
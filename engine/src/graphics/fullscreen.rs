@@ -0,0 +1,64 @@
+use std::{borrow::Cow, ops::Range};
+
+use wgpu::{
+    Device, FragmentState, MultisampleState, PipelineLayout, PrimitiveState, RenderPass,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+    TextureFormat, VertexState,
+};
+
+// Embedded rather than loaded from the shader directory, every fullscreen
+// pass needs the same vertex stage. Fragment shaders take its VertexOutput,
+// a clip position and a uv at location 0.
+pub const FULLSCREEN_VERTEX_WGSL: &str = include_str!("shaders/fullscreen.wgsl");
+
+// The vertices the stub generates a single screen covering triangle from.
+pub const FULLSCREEN_VERTICES: Range<u32> = 0..3;
+
+pub fn create_fullscreen_vertex_shader(device: &Device) -> ShaderModule {
+    device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("fullscreen vertex"),
+        source: ShaderSource::Wgsl(Cow::Borrowed(FULLSCREEN_VERTEX_WGSL)),
+    })
+}
+
+// No vertex buffers, positions come from the vertex index.
+pub fn fullscreen_vertex_state(module: &ShaderModule) -> VertexState<'_> {
+    VertexState {
+        module,
+        entry_point: Some("vs_main"),
+        compilation_options: Default::default(),
+        buffers: &[],
+    }
+}
+
+// A pipeline drawing the stub with `fragment`'s fs_main into one color
+// target, without depth or blending.
+pub fn create_fullscreen_pipeline(
+    device: &Device,
+    label: &str,
+    layout: &PipelineLayout,
+    fragment: &ShaderModule,
+    target_format: TextureFormat,
+) -> RenderPipeline {
+    let vertex = create_fullscreen_vertex_shader(device);
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: fullscreen_vertex_state(&vertex),
+        fragment: Some(FragmentState {
+            module: fragment,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(target_format.into())],
+        }),
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+pub fn draw_fullscreen(render_pass: &mut RenderPass<'_>) {
+    render_pass.draw(FULLSCREEN_VERTICES, 0..1);
+}
@@ -0,0 +1,148 @@
+use wgpu::{Buffer, BufferDescriptor, BufferSize};
+
+use crate::graphics::GPUContext;
+
+// The device and queue calls the buffer code makes. Going through this lets
+// the offset, capacity and ring logic run against MockGpuDevice without a GPU.
+pub trait GpuDevice {
+    fn create_buffer(&self, descriptor: &BufferDescriptor) -> Buffer;
+    fn write_buffer(&self, buffer: &Buffer, offset: u64, data: &[u8]);
+    // Fills `size` bytes at `offset` in place in the queue's staging memory,
+    // see Queue::write_buffer_with.
+    fn write_buffer_with(
+        &self,
+        buffer: &Buffer,
+        offset: u64,
+        size: BufferSize,
+        write: &mut dyn FnMut(&mut [u8]),
+    );
+}
+
+impl GpuDevice for GPUContext {
+    fn create_buffer(&self, descriptor: &BufferDescriptor) -> Buffer {
+        self.device.create_buffer(descriptor)
+    }
+
+    fn write_buffer(&self, buffer: &Buffer, offset: u64, data: &[u8]) {
+        self.queue.write_buffer(buffer, offset, data);
+    }
+
+    fn write_buffer_with(
+        &self,
+        buffer: &Buffer,
+        offset: u64,
+        size: BufferSize,
+        write: &mut dyn FnMut(&mut [u8]),
+    ) {
+        if let Some(mut view) = self.queue.write_buffer_with(buffer, offset, size) {
+            write(&mut view);
+        }
+    }
+}
+
+#[cfg(feature = "test-mock-gpu")]
+pub use mock::{MockGpuDevice, RecordedWrite};
+
+#[cfg(feature = "test-mock-gpu")]
+mod mock {
+    use std::sync::Mutex;
+
+    use wgpu::{Buffer, BufferDescriptor, BufferSize, Device, Queue};
+
+    use super::GpuDevice;
+
+    #[derive(Debug, Clone)]
+    pub struct RecordedWrite {
+        pub buffer: Buffer,
+        pub offset: u64,
+        pub data: Vec<u8>,
+    }
+
+    // Buffers come from wgpu's noop backend, which hands out real handles but
+    // never executes anything, so writes are recorded here instead.
+    pub struct MockGpuDevice {
+        device: Device,
+        _queue: Queue,
+        buffers: Mutex<Vec<Buffer>>,
+        writes: Mutex<Vec<RecordedWrite>>,
+    }
+
+    impl Default for MockGpuDevice {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl MockGpuDevice {
+        pub fn new() -> Self {
+            let (device, queue) = Device::noop(&Default::default());
+            Self {
+                device,
+                _queue: queue,
+                buffers: Mutex::new(Vec::new()),
+                writes: Mutex::new(Vec::new()),
+            }
+        }
+
+        // For code that takes a Device directly, such as layout creation.
+        pub fn device(&self) -> &Device {
+            &self.device
+        }
+
+        pub fn buffers(&self) -> Vec<Buffer> {
+            self.buffers.lock().unwrap().clone()
+        }
+
+        pub fn writes(&self) -> Vec<RecordedWrite> {
+            self.writes.lock().unwrap().clone()
+        }
+
+        pub fn writes_to(&self, buffer: &Buffer) -> Vec<RecordedWrite> {
+            self.writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|write| &write.buffer == buffer)
+                .cloned()
+                .collect()
+        }
+
+        pub fn clear_writes(&self) {
+            self.writes.lock().unwrap().clear();
+        }
+    }
+
+    impl GpuDevice for MockGpuDevice {
+        fn create_buffer(&self, descriptor: &BufferDescriptor) -> Buffer {
+            let buffer = self.device.create_buffer(descriptor);
+            self.buffers.lock().unwrap().push(buffer.clone());
+            buffer
+        }
+
+        fn write_buffer(&self, buffer: &Buffer, offset: u64, data: &[u8]) {
+            assert!(
+                offset + data.len() as u64 <= buffer.size(),
+                "write of {} bytes at {offset} overflows a {} byte buffer",
+                data.len(),
+                buffer.size()
+            );
+            self.writes.lock().unwrap().push(RecordedWrite {
+                buffer: buffer.clone(),
+                offset,
+                data: data.to_vec(),
+            });
+        }
+
+        fn write_buffer_with(
+            &self,
+            buffer: &Buffer,
+            offset: u64,
+            size: BufferSize,
+            write: &mut dyn FnMut(&mut [u8]),
+        ) {
+            let mut data = vec![0; size.get() as usize];
+            write(&mut data);
+            self.write_buffer(buffer, offset, &data);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use bytemuck::Pod;
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, COPY_BUFFER_ALIGNMENT};
+
+use crate::graphics::{buffers::RING_BUFFER_DEPTH, gpu_device::GpuDevice};
+
+// Where one push landed in the frame's buffers, ready for draw_indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImmediateDraw {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub base_vertex: i32,
+}
+
+struct ImmediateBuffers {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    vertex_capacity: u64,
+    index_capacity: u64,
+}
+
+// Geometry rebuilt from scratch every frame, e.g. debug lines or UI quads.
+// Pushes collect on the CPU and flush writes them in one go, first growing
+// the frame slot's buffers if the frame needs more than they hold. Unlike
+// the MeshAllocator's dynamic region nothing is ever rejected for size.
+pub struct ImmediateGeometry<V> {
+    label: &'static str,
+    slots: Vec<ImmediateBuffers>,
+    vertices: Vec<V>,
+    indices: Vec<u32>,
+}
+
+impl<V: Pod> ImmediateGeometry<V> {
+    // Capacities are in vertices and indices, every ring slot starts at them.
+    pub fn new(
+        gpu: &dyn GpuDevice,
+        label: &'static str,
+        vertex_capacity: u64,
+        index_capacity: u64,
+    ) -> Self {
+        debug_assert!(
+            (size_of::<V>() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT),
+            "immediate vertices must be a multiple of 4 bytes"
+        );
+        Self {
+            label,
+            slots: (0..RING_BUFFER_DEPTH)
+                .map(|_| Self::create_buffers(gpu, label, vertex_capacity, index_capacity))
+                .collect(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn create_buffers(
+        gpu: &dyn GpuDevice,
+        label: &'static str,
+        vertex_capacity: u64,
+        index_capacity: u64,
+    ) -> ImmediateBuffers {
+        let vertex_capacity = vertex_capacity.max(1);
+        let index_capacity = index_capacity.max(1);
+        ImmediateBuffers {
+            vertex_buffer: gpu.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: vertex_capacity * size_of::<V>() as u64,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            index_buffer: gpu.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: index_capacity * size_of::<u32>() as u64,
+                usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            vertex_capacity,
+            index_capacity,
+        }
+    }
+
+    // Indices are relative to `vertices`, the returned base_vertex offsets
+    // them into the frame's buffer.
+    pub fn push(&mut self, vertices: &[V], indices: &[u32]) -> ImmediateDraw {
+        let draw = ImmediateDraw {
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            base_vertex: self.vertices.len() as i32,
+        };
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+        draw
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    // Uploads everything pushed since the last flush into `frame_index`'s
+    // buffers and starts the next frame empty. Returns true if the buffers
+    // had to grow, in which case anything bound from them before is stale.
+    pub fn flush(&mut self, gpu: &dyn GpuDevice, frame_index: usize) -> bool {
+        let slot = frame_index % self.slots.len();
+        let vertex_count = self.vertices.len() as u64;
+        let index_count = self.indices.len() as u64;
+
+        let buffers = &self.slots[slot];
+        let grew = vertex_count > buffers.vertex_capacity || index_count > buffers.index_capacity;
+        if grew {
+            // Doubling keeps a frame that creeps up in size from reallocating
+            // every time.
+            let vertex_capacity = buffers
+                .vertex_capacity
+                .max(vertex_count.next_power_of_two());
+            let index_capacity = buffers.index_capacity.max(index_count.next_power_of_two());
+            self.slots[slot] =
+                Self::create_buffers(gpu, self.label, vertex_capacity, index_capacity);
+        }
+
+        let buffers = &self.slots[slot];
+        if !self.vertices.is_empty() {
+            gpu.write_buffer(
+                &buffers.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.vertices),
+            );
+        }
+        if !self.indices.is_empty() {
+            gpu.write_buffer(
+                &buffers.index_buffer,
+                0,
+                bytemuck::cast_slice(&self.indices),
+            );
+        }
+        self.vertices.clear();
+        self.indices.clear();
+        grew
+    }
+
+    pub fn vertex_buffer(&self, frame_index: usize) -> &Buffer {
+        &self.slots[frame_index % self.slots.len()].vertex_buffer
+    }
+
+    pub fn index_buffer(&self, frame_index: usize) -> &Buffer {
+        &self.slots[frame_index % self.slots.len()].index_buffer
+    }
+
+    // In vertices and indices.
+    pub fn capacity(&self, frame_index: usize) -> (u64, u64) {
+        let buffers = &self.slots[frame_index % self.slots.len()];
+        (buffers.vertex_capacity, buffers.index_capacity)
+    }
+}
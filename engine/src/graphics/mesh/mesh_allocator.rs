@@ -1,187 +1,540 @@
 use std::fmt::Debug;
 
-use log::info;
-use wgpu::{Buffer, BufferUsages, Device, Queue};
+use log::{error, info};
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, COPY_BUFFER_ALIGNMENT};
 
-use crate::graphics::buffers::{self, BufferEntry, GpuRingBuffer};
+use crate::graphics::{
+    buffers::{BufferEntry, GpuRingBuffer, RING_BUFFER_DEPTH},
+    gpu_device::GpuDevice,
+    mesh::Vertex,
+};
 use ecs::components::MeshHandle;
 
+// The stream every allocator starts with, sized for `Vertex`.
+pub const DEFAULT_MESH_STREAM: u32 = 0;
+
+// Meshes with different vertex formats live in separate streams, each with its
+// own vertex and index buffers. Each buffer starts with a static region for
+// meshes uploaded once and kept for the allocator's lifetime, followed by a
+// dynamic region that is refilled every frame. Clearing a frame only rewinds
+// the dynamic regions.
 pub struct MeshAllocator {
+    streams: Vec<MeshStream>,
+}
+
+struct MeshStream {
+    label: &'static str,
+    vertex_stride: u64,
+
     vertex_buffers: GpuRingBuffer<Buffer>,
     index_buffers: GpuRingBuffer<Buffer>,
 
-    vertex_offset: [u64; 3],
-    index_offset: [u64; 3],
+    static_vertex_offset: u64,
+    static_index_offset: u64,
+    dynamic_vertex_offset: [u64; RING_BUFFER_DEPTH],
+    dynamic_index_offset: [u64; RING_BUFFER_DEPTH],
 
+    static_vertex_capacity: u64,
+    static_index_capacity: u64,
     vertex_capacity: u64,
     index_capacity: u64,
 }
 
 impl MeshAllocator {
-    pub fn new(device: &Device, vertex_capacity: u64, index_capacity: u64) -> Self {
-        Self {
-            vertex_buffers: GpuRingBuffer::new(vec![
-                Self::create_vertex_buffer_entry(device, vertex_capacity),
-                Self::create_vertex_buffer_entry(device, vertex_capacity),
-                Self::create_vertex_buffer_entry(device, vertex_capacity),
-            ]),
-            index_buffers: GpuRingBuffer::new(vec![
-                Self::create_index_buffer_entry(device, vertex_capacity),
-                Self::create_index_buffer_entry(device, vertex_capacity),
-                Self::create_index_buffer_entry(device, vertex_capacity),
-            ]),
-            vertex_offset: [0; 3],
-            index_offset: [0; 3],
-            vertex_capacity: vertex_capacity,
-            index_capacity: index_capacity,
-        }
+    pub fn new(
+        gpu: &dyn GpuDevice,
+        static_vertex_capacity: u64,
+        static_index_capacity: u64,
+        dynamic_vertex_capacity: u64,
+        dynamic_index_capacity: u64,
+    ) -> Self {
+        let mut allocator = Self {
+            streams: Vec::new(),
+        };
+        allocator.add_stream(
+            gpu,
+            "default",
+            size_of::<Vertex>() as u64,
+            static_vertex_capacity,
+            static_index_capacity,
+            dynamic_vertex_capacity,
+            dynamic_index_capacity,
+        );
+        allocator
     }
 
-    fn create_vertex_buffer_entry(device: &Device, vertex_capacity: u64) -> BufferEntry {
-        let buffer = buffers::create_buffer(
-            device,
-            "Shared Vertex Buffer",
+    // Returns the id to upload to and to store in the stream's MeshHandles.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_stream(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        label: &'static str,
+        vertex_stride: u64,
+        static_vertex_capacity: u64,
+        static_index_capacity: u64,
+        dynamic_vertex_capacity: u64,
+        dynamic_index_capacity: u64,
+    ) -> u32 {
+        let vertex_capacity = static_vertex_capacity + dynamic_vertex_capacity;
+        let index_capacity = static_index_capacity + dynamic_index_capacity;
+        self.streams.push(MeshStream {
+            label,
+            vertex_stride,
+            vertex_buffers: GpuRingBuffer::new(
+                (0..RING_BUFFER_DEPTH)
+                    .map(|_| Self::create_vertex_buffer_entry(gpu, vertex_capacity))
+                    .collect(),
+            ),
+            index_buffers: GpuRingBuffer::new(
+                (0..RING_BUFFER_DEPTH)
+                    .map(|_| Self::create_index_buffer_entry(gpu, index_capacity))
+                    .collect(),
+            ),
+            static_vertex_offset: 0,
+            static_index_offset: 0,
+            dynamic_vertex_offset: [static_vertex_capacity; RING_BUFFER_DEPTH],
+            dynamic_index_offset: [static_index_capacity; RING_BUFFER_DEPTH],
+            static_vertex_capacity,
+            static_index_capacity,
             vertex_capacity,
-            vec![BufferUsages::VERTEX, BufferUsages::COPY_DST],
-            false,
-        );
+            index_capacity,
+        });
+        (self.streams.len() - 1) as u32
+    }
+
+    pub fn stream_id(&self, label: &str) -> Option<u32> {
+        self.streams
+            .iter()
+            .position(|stream| stream.label == label)
+            .map(|id| id as u32)
+    }
+
+    pub fn vertex_stride(&self, stream: u32) -> Option<u64> {
+        self.streams
+            .get(stream as usize)
+            .map(|stream| stream.vertex_stride)
+    }
+
+    fn create_vertex_buffer_entry(gpu: &dyn GpuDevice, vertex_capacity: u64) -> BufferEntry {
+        let buffer = gpu.create_buffer(&BufferDescriptor {
+            label: Some("Shared Vertex Buffer"),
+            size: vertex_capacity,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         BufferEntry {
-            buffer: buffer,
+            buffer,
             bind_group: None,
             element_count: 0,
         }
     }
 
-    fn create_index_buffer_entry(device: &Device, index_capacity: u64) -> BufferEntry {
-        let buffer = buffers::create_buffer(
-            device,
-            "Shared Index Buffer",
-            index_capacity,
-            vec![BufferUsages::INDEX, BufferUsages::COPY_DST],
-            false,
-        );
+    fn create_index_buffer_entry(gpu: &dyn GpuDevice, index_capacity: u64) -> BufferEntry {
+        let buffer = gpu.create_buffer(&BufferDescriptor {
+            label: Some("Shared Index Buffer"),
+            size: index_capacity,
+            usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         BufferEntry {
-            buffer: buffer,
+            buffer,
             bind_group: None,
             element_count: 0,
         }
     }
 
-    pub fn upload_static_mesh<V: bytemuck::Pod + Debug, I: bytemuck::Pod + Debug>(
+    // Writes the mesh into every ring slot at the same offsets, so the
+    // returned handles are identical and valid for any frame. Indices are u32
+    // only, draws bind the index buffers as Uint32 and turn index offsets
+    // into first_index with that size.
+    pub fn upload_static_mesh<V: bytemuck::Pod + Debug>(
         &mut self,
-        queue: &Queue,
+        gpu: &dyn GpuDevice,
+        stream: u32,
         vertices: &[V],
-        indices: &[I],
+        indices: &[u32],
     ) -> Option<Vec<MeshHandle>> {
+        let mesh_stream = self.stream_for::<V>(stream)?;
+        let (vertex_start, index_start) = fit(
+            (
+                mesh_stream.static_vertex_offset,
+                mesh_stream.static_vertex_capacity,
+            ),
+            (
+                mesh_stream.static_index_offset,
+                mesh_stream.static_index_capacity,
+            ),
+            vertices,
+            indices,
+        )?;
+
         let mut handles = Vec::new();
-        for i in 0..3 {
-            let vertex_size = size_of::<V>() as u64;
-            let index_size = size_of::<I>() as u64;
-
-            let vertex_data_len = vertex_size * vertices.len() as u64;
-            let index_data_len = index_size * indices.len() as u64;
-
-            if self.vertex_offset[i] + vertex_data_len > self.vertex_capacity
-                || self.index_offset[i] + index_data_len > self.index_capacity
-            {
-                return None;
-            } else {
-                info!(
-                    "writing vertices {:?} to buffer {} at {}",
-                    vertices, i, self.vertex_offset[i]
-                );
-                queue.write_buffer(
-                    &self.vertex_buffers.get_write(i).buffer,
-                    self.vertex_offset[i],
-                    bytemuck::cast_slice(vertices),
-                );
-                info!(
-                    "writing indices {:?} to buffer {} at {}",
-                    indices, i, self.index_offset[i]
-                );
-                queue.write_buffer(
-                    &self.index_buffers.get_write(i).buffer,
-                    self.index_offset[i],
-                    bytemuck::cast_slice(indices),
-                );
-
-                let handle = MeshHandle {
-                    vertex_offset: self.vertex_offset[i],
-                    index_offset: self.index_offset[i],
-                    vertex_count: vertices.len() as u32,
-                    index_count: indices.len() as u32,
-                };
-
-                self.vertex_offset[i] += vertex_data_len;
-                self.index_offset[i] += index_data_len;
-
-                handles.push(handle);
-            }
+        for i in 0..RING_BUFFER_DEPTH {
+            handles.push(mesh_stream.write_mesh(
+                gpu,
+                stream,
+                i,
+                vertex_start,
+                index_start,
+                vertices,
+                indices,
+            ));
         }
+
+        mesh_stream.static_vertex_offset = vertex_start + size_of_val(vertices) as u64;
+        mesh_stream.static_index_offset = index_start + size_of_val(indices) as u64;
         Some(handles)
     }
 
-    pub fn upload_mesh<V: bytemuck::Pod + Debug, I: bytemuck::Pod + Debug>(
+    // Only valid for the frame it was uploaded for, until clear_current_frame.
+    pub fn upload_mesh<V: bytemuck::Pod + Debug>(
         &mut self,
-        queue: &Queue,
+        gpu: &dyn GpuDevice,
+        stream: u32,
         frame_index: usize,
         vertices: &[V],
-        indices: &[I],
+        indices: &[u32],
     ) -> Option<MeshHandle> {
-        let vertex_size = size_of::<V>() as u64;
-        let index_size = size_of::<I>() as u64;
+        let slot = frame_index % RING_BUFFER_DEPTH;
+        let mesh_stream = self.stream_for::<V>(stream)?;
+        let (vertex_start, index_start) = fit(
+            (
+                mesh_stream.dynamic_vertex_offset[slot],
+                mesh_stream.vertex_capacity,
+            ),
+            (
+                mesh_stream.dynamic_index_offset[slot],
+                mesh_stream.index_capacity,
+            ),
+            vertices,
+            indices,
+        )?;
 
-        let vertex_data_len = vertex_size * vertices.len() as u64;
-        let index_data_len = index_size * indices.len() as u64;
+        let handle = mesh_stream.write_mesh(
+            gpu,
+            stream,
+            slot,
+            vertex_start,
+            index_start,
+            vertices,
+            indices,
+        );
+
+        mesh_stream.dynamic_vertex_offset[slot] = vertex_start + size_of_val(vertices) as u64;
+        mesh_stream.dynamic_index_offset[slot] = index_start + size_of_val(indices) as u64;
+        Some(handle)
+    }
 
-        if self.vertex_offset[frame_index] + vertex_data_len > self.vertex_capacity
-            || self.index_offset[frame_index] + index_data_len > self.index_capacity
-        {
+    // Base vertices are computed from the stream's stride, so a vertex type of
+    // a different size would put every draw at the wrong offset.
+    fn stream_for<V>(&mut self, stream: u32) -> Option<&mut MeshStream> {
+        let Some(mesh_stream) = self.streams.get_mut(stream as usize) else {
+            error!("mesh stream {stream} does not exist");
             return None;
-        } else {
-            info!(
-                "writing vertices {:?} to buffer {} at {}",
-                vertices, frame_index, self.vertex_offset[frame_index]
-            );
-            queue.write_buffer(
-                &self.vertex_buffers.get_write(frame_index).buffer,
-                self.vertex_offset[frame_index],
-                bytemuck::cast_slice(vertices),
-            );
-            info!(
-                "writing indices {:?} to buffer {} at {}",
-                indices, frame_index, self.index_offset[frame_index]
-            );
-            queue.write_buffer(
-                &self.index_buffers.get_write(frame_index).buffer,
-                self.index_offset[frame_index],
-                bytemuck::cast_slice(indices),
+        };
+        if size_of::<V>() as u64 != mesh_stream.vertex_stride {
+            error!(
+                "mesh stream {} expects {} byte vertices, got {}",
+                mesh_stream.label,
+                mesh_stream.vertex_stride,
+                size_of::<V>()
             );
+            return None;
+        }
+        Some(mesh_stream)
+    }
 
-            let handle = MeshHandle {
-                vertex_offset: self.vertex_offset[frame_index],
-                index_offset: self.index_offset[frame_index],
-                vertex_count: vertices.len() as u32,
-                index_count: indices.len() as u32,
-            };
+    pub fn get_current_vertex_buffer(&self, stream: u32, frame_index: usize) -> Option<&Buffer> {
+        self.streams
+            .get(stream as usize)
+            .map(|stream| &stream.vertex_buffers.get_read(frame_index).buffer)
+    }
 
-            self.vertex_offset[frame_index] += vertex_data_len;
-            self.index_offset[frame_index] += index_data_len;
+    pub fn get_current_index_buffer(&self, stream: u32, frame_index: usize) -> Option<&Buffer> {
+        self.streams
+            .get(stream as usize)
+            .map(|stream| &stream.index_buffers.get_read(frame_index).buffer)
+    }
 
-            Some(handle)
+    // Drops the frame's dynamic meshes in every stream, static meshes are
+    // untouched.
+    pub fn clear_current_frame(&mut self, frame_index: usize) {
+        let slot = frame_index % RING_BUFFER_DEPTH;
+        for stream in &mut self.streams {
+            stream.dynamic_vertex_offset[slot] = stream.static_vertex_capacity;
+            stream.dynamic_index_offset[slot] = stream.static_index_capacity;
         }
     }
+}
+
+impl MeshStream {
+    #[allow(clippy::too_many_arguments)]
+    fn write_mesh<V: bytemuck::Pod + Debug>(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        stream: u32,
+        slot: usize,
+        vertex_start: u64,
+        index_start: u64,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> MeshHandle {
+        info!(
+            "writing vertices {:?} to {} buffer {} at {}",
+            vertices, self.label, slot, vertex_start
+        );
+        write_padded(
+            gpu,
+            &self.vertex_buffers.get_write(slot).buffer,
+            vertex_start,
+            bytemuck::cast_slice(vertices),
+        );
+        info!(
+            "writing indices {:?} to {} buffer {} at {}",
+            indices, self.label, slot, index_start
+        );
+        write_padded(
+            gpu,
+            &self.index_buffers.get_write(slot).buffer,
+            index_start,
+            bytemuck::cast_slice(indices),
+        );
 
-    pub fn get_current_vertex_buffer(&self, frame_index: usize) -> &Buffer {
-        &self.vertex_buffers.get_read(frame_index).buffer
+        MeshHandle {
+            vertex_offset: vertex_start,
+            index_offset: index_start,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+            stream,
+        }
     }
+}
+
+// Returns the aligned start offsets if both the vertices and the indices fit
+// between their region's current offset and its end.
+fn fit<V>(
+    (vertex_offset, vertex_end): (u64, u64),
+    (index_offset, index_end): (u64, u64),
+    vertices: &[V],
+    indices: &[u32],
+) -> Option<(u64, u64)> {
+    let vertex_start = align_to(vertex_offset, buffer_alignment(size_of::<V>() as u64));
+    let index_start = align_to(index_offset, buffer_alignment(size_of::<u32>() as u64));
 
-    pub fn get_current_index_buffer(&self, frame_index: usize) -> &Buffer {
-        &self.index_buffers.get_read(frame_index).buffer
+    if vertex_start + size_of_val(vertices) as u64 > vertex_end
+        || index_start + size_of_val(indices) as u64 > index_end
+    {
+        return None;
     }
+    Some((vertex_start, index_start))
+}
 
-    pub fn clear_current_frame(&mut self, frame_index: usize) {
-        self.vertex_offset[frame_index] = 0;
-        self.index_offset[frame_index] = 0;
+// Offsets must satisfy COPY_BUFFER_ALIGNMENT for the write and be a whole
+// number of elements so the draw can turn them back into vertex/index indices.
+fn buffer_alignment(element_size: u64) -> u64 {
+    let mut a = element_size.max(1);
+    let mut b = COPY_BUFFER_ALIGNMENT;
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    element_size.max(1) / a * COPY_BUFFER_ALIGNMENT
+}
+
+fn align_to(offset: u64, alignment: u64) -> u64 {
+    offset.div_ceil(alignment) * alignment
+}
+
+// write_buffer requires the data length to be a multiple of
+// COPY_BUFFER_ALIGNMENT, which e.g. an odd number of 6 byte vertices isn't.
+fn write_padded(gpu: &dyn GpuDevice, buffer: &Buffer, offset: u64, data: &[u8]) {
+    let padded_len = align_to(data.len() as u64, COPY_BUFFER_ALIGNMENT) as usize;
+    if padded_len == data.len() {
+        gpu.write_buffer(buffer, offset, data);
+    } else {
+        let mut padded = data.to_vec();
+        padded.resize(padded_len, 0);
+        gpu.write_buffer(buffer, offset, &padded);
+    }
+}
+
+#[cfg(all(test, feature = "test-mock-gpu"))]
+mod mock_gpu_tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+    use crate::graphics::gpu_device::MockGpuDevice;
+
+    const STRIDE: u64 = size_of::<Vertex>() as u64;
+    // Four vertices and sixteen indices in each region.
+    const STATIC_VERTEX_CAPACITY: u64 = 4 * STRIDE;
+    const STATIC_INDEX_CAPACITY: u64 = 64;
+
+    fn allocator(gpu: &MockGpuDevice) -> MeshAllocator {
+        MeshAllocator::new(
+            gpu,
+            STATIC_VERTEX_CAPACITY,
+            STATIC_INDEX_CAPACITY,
+            4 * STRIDE,
+            64,
+        )
+    }
+
+    fn vertices(count: usize) -> Vec<Vertex> {
+        vec![Vertex::zeroed(); count]
+    }
+
+    // (offset, size) of every write to the slot's vertex and index buffers.
+    fn writes(gpu: &MockGpuDevice, allocator: &MeshAllocator, slot: usize) -> [Vec<(u64, u64)>; 2] {
+        let sizes = |buffer: &Buffer| {
+            gpu.writes_to(buffer)
+                .iter()
+                .map(|write| (write.offset, write.data.len() as u64))
+                .collect()
+        };
+        [
+            sizes(
+                allocator
+                    .get_current_vertex_buffer(DEFAULT_MESH_STREAM, slot)
+                    .unwrap(),
+            ),
+            sizes(
+                allocator
+                    .get_current_index_buffer(DEFAULT_MESH_STREAM, slot)
+                    .unwrap(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn uploads_write_at_their_region_offsets() {
+        let gpu = MockGpuDevice::new();
+        let mut allocator = allocator(&gpu);
+
+        // Static meshes go to every slot at the same offsets.
+        let first = allocator
+            .upload_static_mesh(&gpu, DEFAULT_MESH_STREAM, &vertices(3), &[0, 1, 2])
+            .unwrap();
+        assert_eq!(first.len(), RING_BUFFER_DEPTH);
+        assert!(first.iter().all(|handle| *handle == first[0]));
+        for slot in 0..RING_BUFFER_DEPTH {
+            assert_eq!(
+                writes(&gpu, &allocator, slot),
+                [vec![(0, 3 * STRIDE)], vec![(0, 12)]]
+            );
+        }
+
+        gpu.clear_writes();
+        let second = allocator
+            .upload_static_mesh(&gpu, DEFAULT_MESH_STREAM, &vertices(1), &[0, 0, 0])
+            .unwrap();
+        assert_eq!(
+            (second[0].vertex_offset, second[0].index_offset),
+            (3 * STRIDE, 12)
+        );
+        for slot in 0..RING_BUFFER_DEPTH {
+            assert_eq!(
+                writes(&gpu, &allocator, slot),
+                [vec![(3 * STRIDE, STRIDE)], vec![(12, 12)]]
+            );
+        }
+
+        // Dynamic meshes start after the static region, in their slot only.
+        gpu.clear_writes();
+        let dynamic = allocator
+            .upload_mesh(&gpu, DEFAULT_MESH_STREAM, 1, &vertices(2), &[0, 1, 1])
+            .unwrap();
+        assert_eq!(
+            (dynamic.vertex_offset, dynamic.index_offset),
+            (STATIC_VERTEX_CAPACITY, STATIC_INDEX_CAPACITY)
+        );
+        assert_eq!(
+            writes(&gpu, &allocator, 1),
+            [
+                vec![(STATIC_VERTEX_CAPACITY, 2 * STRIDE)],
+                vec![(STATIC_INDEX_CAPACITY, 12)]
+            ]
+        );
+        assert_eq!(writes(&gpu, &allocator, 0), [vec![], vec![]]);
+        assert_eq!(writes(&gpu, &allocator, 2), [vec![], vec![]]);
+
+        // Clearing rewinds the slot, so the same upload lands in the same place.
+        gpu.clear_writes();
+        allocator.clear_current_frame(1);
+        let again = allocator
+            .upload_mesh(&gpu, DEFAULT_MESH_STREAM, 1, &vertices(2), &[0, 1, 1])
+            .unwrap();
+        assert_eq!(again, dynamic);
+        assert_eq!(
+            writes(&gpu, &allocator, 1),
+            [
+                vec![(STATIC_VERTEX_CAPACITY, 2 * STRIDE)],
+                vec![(STATIC_INDEX_CAPACITY, 12)]
+            ]
+        );
+
+        // Three more vertices don't fit in the two left, nothing is written.
+        gpu.clear_writes();
+        assert!(
+            allocator
+                .upload_mesh(&gpu, DEFAULT_MESH_STREAM, 1, &vertices(3), &[0, 1, 2])
+                .is_none()
+        );
+        assert!(gpu.writes().is_empty());
+    }
+
+    #[test]
+    fn clearing_a_frame_keeps_static_meshes() {
+        let gpu = MockGpuDevice::new();
+        let mut allocator = allocator(&gpu);
+        let upload_static = |allocator: &mut MeshAllocator, count| {
+            allocator
+                .upload_static_mesh(&gpu, DEFAULT_MESH_STREAM, &vertices(count), &[0, 0, 0])
+                .unwrap()[0]
+        };
+        let upload_dynamic = |allocator: &mut MeshAllocator, count| {
+            allocator
+                .upload_mesh(&gpu, DEFAULT_MESH_STREAM, 0, &vertices(count), &[0, 0, 0])
+                .unwrap()
+        };
+
+        let first_static = upload_static(&mut allocator, 1);
+        let first_dynamic = upload_dynamic(&mut allocator, 1);
+        let second_static = upload_static(&mut allocator, 2);
+        let second_dynamic = upload_dynamic(&mut allocator, 1);
+
+        // Each region only moves its own offsets.
+        assert_eq!(
+            (first_static.vertex_offset, second_static.vertex_offset),
+            (0, STRIDE)
+        );
+        assert_eq!(
+            (first_dynamic.vertex_offset, second_dynamic.vertex_offset),
+            (STATIC_VERTEX_CAPACITY, STATIC_VERTEX_CAPACITY + STRIDE)
+        );
+        assert_eq!(
+            (first_dynamic.index_offset, second_dynamic.index_offset),
+            (STATIC_INDEX_CAPACITY, STATIC_INDEX_CAPACITY + 12)
+        );
+
+        allocator.clear_current_frame(0);
+
+        // The static region carries on after the second static mesh, the
+        // dynamic one starts over at the static capacity.
+        let third_static = upload_static(&mut allocator, 1);
+        assert_eq!(
+            (third_static.vertex_offset, third_static.index_offset),
+            (3 * STRIDE, 24)
+        );
+        gpu.clear_writes();
+        assert_eq!(upload_dynamic(&mut allocator, 1), first_dynamic);
+
+        // Nothing written after the clear reaches the static meshes' bytes.
+        let [vertex_writes, index_writes] = writes(&gpu, &allocator, 0);
+        assert!(
+            vertex_writes
+                .iter()
+                .all(|&(offset, _)| offset >= 3 * STRIDE)
+        );
+        assert!(index_writes.iter().all(|&(offset, _)| offset >= 24));
     }
 }
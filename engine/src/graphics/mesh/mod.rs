@@ -1,8 +1,16 @@
 use std::sync::Arc;
 
-use wgpu::{Buffer, PrimitiveTopology, VertexBufferLayout, vertex_attr_array};
+use wgpu::{
+    Buffer, PrimitiveTopology, VertexAttribute, VertexBufferLayout, VertexStepMode,
+    vertex_attr_array,
+};
 
+use crate::utils::Registry;
+
+pub mod immediate;
 pub mod mesh_allocator;
+pub mod obj;
+pub mod optimize;
 pub struct Mesh {
     pub vertex_offset: u64,
     pub index_offset: u64,
@@ -21,14 +29,63 @@ pub struct Mesh {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
+    pub normal: [f32; 3],
+    // Unskinned meshes leave these zeroed, the shader only reads them when
+    // the instance has a bone range.
+    pub joint_indices: [u32; 4],
+    pub joint_weights: [f32; 4],
+    // Top-left origin, as wgpu samples textures.
+    pub uv: [f32; 2],
 }
 
 impl Vertex {
+    const ATTRIBUTES: [VertexAttribute; 5] = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Uint32x4,
+        3 => Float32x4,
+        4 => Float32x2
+    ];
+
     pub fn create_buffer_layout<'a>() -> VertexBufferLayout<'a> {
         VertexBufferLayout {
             array_stride: size_of::<Self>() as u64,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &vertex_attr_array![0 => Float32x3],
+            attributes: &Self::ATTRIBUTES,
         }
     }
 }
+
+// The layout the main render pipeline draws with, registered for `Vertex`.
+pub const DEFAULT_VERTEX_LAYOUT: &str = "vertex";
+
+// An owned copy of a VertexBufferLayout, so layouts can be registered by name
+// and looked up when a pipeline is built.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexLayout {
+    pub array_stride: u64,
+    pub step_mode: VertexStepMode,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn buffer_layout(&self) -> VertexBufferLayout<'_> {
+        VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+impl From<VertexBufferLayout<'_>> for VertexLayout {
+    fn from(layout: VertexBufferLayout<'_>) -> Self {
+        Self {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: layout.attributes.to_vec(),
+        }
+    }
+}
+
+pub type VertexLayoutRegistry = Registry<VertexLayout>;
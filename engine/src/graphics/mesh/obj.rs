@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec3};
+
+use crate::graphics::mesh::Vertex;
+
+// Index of an `f` corner into the position, texture coordinate and normal
+// lists.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// Parses the `v`, `vt`, `vn` and `f` statements of a Wavefront OBJ,
+// everything else is ignored. Faces with more than three corners are
+// triangulated as a fan, and each distinct v/vt/vn combination becomes one
+// vertex. Corners without a normal get the area-weighted average of the
+// faces around them, corners without a texture coordinate get zero.
+pub fn parse_obj(source: &str) -> Result<(Vec<Vertex>, Vec<u32>), String> {
+    let mut positions = Vec::new();
+    let mut texture_coordinates = Vec::new();
+    let mut normals = Vec::new();
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut corner_vertices: HashMap<Corner, u32> = HashMap::new();
+    // Vertices without a normal in the file, filled in from their faces.
+    let mut smoothed = Vec::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line_number = line_number + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => positions.push(parse_vec3(tokens, line_number)?),
+            Some("vt") => texture_coordinates.push(parse_vec2(tokens, line_number)?),
+            Some("vn") => normals.push(parse_vec3(tokens, line_number)?),
+            Some("f") => {
+                let corners = tokens
+                    .map(|token| {
+                        parse_corner(
+                            token,
+                            [positions.len(), texture_coordinates.len(), normals.len()],
+                            line_number,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(format!("line {line_number}: face needs at least 3 corners"));
+                }
+                let face: Vec<u32> = corners
+                    .iter()
+                    .map(|&corner| {
+                        *corner_vertices.entry(corner).or_insert_with(|| {
+                            let (position, texture_coordinate, normal) = corner;
+                            let index = vertices.len() as u32;
+                            if normal.is_none() {
+                                smoothed.push(index);
+                            }
+                            vertices.push(Vertex {
+                                position: positions[position].to_array(),
+                                normal: normal
+                                    .map_or([0.0; 3], |normal| normals[normal].to_array()),
+                                joint_indices: [0; 4],
+                                joint_weights: [0.0; 4],
+                                // OBJ puts v = 0 at the bottom of the image.
+                                uv: texture_coordinate.map_or([0.0; 2], |texture_coordinate| {
+                                    let uv = texture_coordinates[texture_coordinate];
+                                    [uv.x, 1.0 - uv.y]
+                                }),
+                            });
+                            index
+                        })
+                    })
+                    .collect();
+                for i in 1..face.len() - 1 {
+                    indices.extend([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !smoothed.is_empty() {
+        let mut accumulated = vec![Vec3::ZERO; vertices.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(vertices[triangle[i] as usize].position));
+            // Left unnormalized so larger faces weigh more.
+            let face_normal = (b - a).cross(c - a);
+            for &index in triangle {
+                accumulated[index as usize] += face_normal;
+            }
+        }
+        for index in smoothed {
+            vertices[index as usize].normal =
+                accumulated[index as usize].normalize_or_zero().to_array();
+        }
+    }
+
+    Ok((vertices, indices))
+}
+
+// A third `vt` component, for 3D textures, is ignored.
+fn parse_vec2<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    line_number: usize,
+) -> Result<Vec2, String> {
+    let components = parse_components::<2>(tokens, line_number)?;
+    Ok(Vec2::from(components))
+}
+
+fn parse_vec3<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    line_number: usize,
+) -> Result<Vec3, String> {
+    let components = parse_components::<3>(tokens, line_number)?;
+    Ok(Vec3::from(components))
+}
+
+fn parse_components<'a, const N: usize>(
+    mut tokens: impl Iterator<Item = &'a str>,
+    line_number: usize,
+) -> Result<[f32; N], String> {
+    let mut components = [0.0; N];
+    for component in &mut components {
+        let token = tokens
+            .next()
+            .ok_or_else(|| format!("line {line_number}: expected {N} components"))?;
+        *component = token
+            .parse()
+            .map_err(|err| format!("line {line_number}: invalid number {token}, {err}"))?;
+    }
+    Ok(components)
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`, 1-based, negative indices count back
+// from the end of the list so far.
+fn parse_corner(token: &str, counts: [usize; 3], line_number: usize) -> Result<Corner, String> {
+    let mut parts = token.split('/');
+    let position = parts
+        .next()
+        .ok_or_else(|| format!("line {line_number}: empty face corner"))
+        .and_then(|part| resolve_index(part, counts[0], line_number))?;
+    let mut optional = |count: usize| match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, count, line_number).map(Some),
+        _ => Ok(None),
+    };
+    let texture_coordinate = optional(counts[1])?;
+    let normal = optional(counts[2])?;
+    Ok((position, texture_coordinate, normal))
+}
+
+fn resolve_index(part: &str, count: usize, line_number: usize) -> Result<usize, String> {
+    let index: i64 = part
+        .parse()
+        .map_err(|err| format!("line {line_number}: invalid index {part}, {err}"))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "line {line_number}: index {index} out of range of {count}"
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "\
+# a unit quad facing +z
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    #[test]
+    fn quad_is_fanned_into_two_triangles_over_four_vertices() {
+        let (vertices, indices) = parse_obj(QUAD).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(
+            positions,
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0]
+            ]
+        );
+        assert!(
+            vertices
+                .iter()
+                .all(|vertex| vertex.normal == [0.0, 0.0, 1.0])
+        );
+        // Flipped to wgpu's top-left origin.
+        assert_eq!(vertices[0].uv, [0.0, 1.0]);
+        assert_eq!(vertices[2].uv, [1.0, 0.0]);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::graphics::mesh::Vertex;
+
+// Reorders triangles for the post-transform vertex cache with Tom Forsyth's
+// linear-speed algorithm, so consecutive triangles reuse recently shaded
+// vertices. Only the order of the triangles changes, each triangle keeps its
+// indices and winding.
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+#[derive(Debug, Clone, Default)]
+struct VertexData {
+    // Triangles using this vertex that haven't been emitted yet.
+    triangles: Vec<usize>,
+    cache_position: Option<usize>,
+    score: f32,
+}
+
+impl VertexData {
+    fn update_score(&mut self) {
+        let remaining = self.triangles.len();
+        if remaining == 0 {
+            self.score = -1.0;
+            return;
+        }
+        let cache_score = match self.cache_position {
+            None => 0.0,
+            // The last triangle's vertices get a fixed score, so the next
+            // triangle doesn't simply reuse the same edge.
+            Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+            Some(position) => {
+                let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+                (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+            }
+        };
+        // Favors finishing off vertices with few triangles left.
+        let valence_score = VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER);
+        self.score = cache_score + valence_score;
+    }
+}
+
+// `vertices` only bounds the indices. Index lists that aren't whole
+// triangles or reference missing vertices are left as they are.
+pub fn optimize_mesh<V>(vertices: &[V], indices: &mut [u32]) {
+    let vertex_count = vertices.len();
+    if !indices.len().is_multiple_of(3)
+        || indices.iter().any(|&index| index as usize >= vertex_count)
+    {
+        return;
+    }
+    let triangle_count = indices.len() / 3;
+    if triangle_count < 2 {
+        return;
+    }
+
+    let mut vertex_data = vec![VertexData::default(); vertex_count];
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            vertex_data[index as usize].triangles.push(triangle);
+        }
+    }
+    for vertex in &mut vertex_data {
+        vertex.update_score();
+    }
+
+    let triangle_score = |vertex_data: &[VertexData], triangle: usize| -> f32 {
+        indices[triangle * 3..triangle * 3 + 3]
+            .iter()
+            .map(|&index| vertex_data[index as usize].score)
+            .sum()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|triangle| triangle_score(&vertex_data, triangle))
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut order = Vec::with_capacity(triangle_count);
+    // Holds a few entries past CACHE_SIZE while the new triangle is pushed in.
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut best = None;
+    // Triangles before this have all been emitted, so the fallback search
+    // when the cache holds nothing useful never rescans them.
+    let mut next_unemitted = 0;
+
+    while order.len() < triangle_count {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                while emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                (next_unemitted..triangle_count)
+                    .filter(|&triangle| !emitted[triangle])
+                    .max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]))
+                    .expect("an unemitted triangle is left")
+            }
+        };
+        emitted[triangle] = true;
+        order.push(triangle);
+
+        let corners = [
+            indices[triangle * 3],
+            indices[triangle * 3 + 1],
+            indices[triangle * 3 + 2],
+        ];
+        for &index in &corners {
+            vertex_data[index as usize]
+                .triangles
+                .retain(|&other| other != triangle);
+        }
+
+        // Most recently used first, the triangle's vertices move to the front.
+        let previous = std::mem::take(&mut cache);
+        for &index in corners.iter().chain(&previous) {
+            if !cache.contains(&index) {
+                cache.push(index);
+            }
+        }
+        let evicted: Vec<u32> = cache.drain(CACHE_SIZE.min(cache.len())..).collect();
+        for &index in &evicted {
+            vertex_data[index as usize].cache_position = None;
+            vertex_data[index as usize].update_score();
+        }
+        for (position, &index) in cache.iter().enumerate() {
+            vertex_data[index as usize].cache_position = Some(position);
+            vertex_data[index as usize].update_score();
+        }
+
+        // Only triangles touching the cache or an evicted vertex changed score.
+        best = None;
+        let mut best_score = f32::MIN;
+        for &index in cache.iter().chain(&evicted) {
+            for &other in &vertex_data[index as usize].triangles {
+                let score = triangle_score(&vertex_data, other);
+                triangle_scores[other] = score;
+                if score > best_score {
+                    best_score = score;
+                    best = Some(other);
+                }
+            }
+        }
+    }
+
+    let original = indices.to_vec();
+    for (slot, triangle) in order.into_iter().enumerate() {
+        indices[slot * 3..slot * 3 + 3].copy_from_slice(&original[triangle * 3..triangle * 3 + 3]);
+    }
+}
+
+// Average post-transform cache misses per triangle for a FIFO cache of
+// `cache_size` vertices, 3.0 means no reuse at all.
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return 0.0;
+    }
+    let mut cache = VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+    misses as f32 / triangle_count as f32
+}
+
+// Merges vertices whose attributes all lie within `epsilon` of an earlier
+// vertex, keeping the first of each group, and remaps `indices` onto the
+// welded list. Joint indices have to match exactly. Every index must be in
+// range of `vertices`.
+pub fn weld_vertices(
+    vertices: &[Vertex],
+    indices: &[u32],
+    epsilon: f32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let epsilon = epsilon.max(0.0);
+    // Cells at least epsilon wide, so a match is always in a neighboring cell.
+    let cell_size = epsilon.max(f32::EPSILON);
+    let cell_of = |position: [f32; 3]| position.map(|axis| (axis / cell_size).floor() as i64);
+
+    let mut welded: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let remap: Vec<u32> = vertices
+        .iter()
+        .map(|vertex| {
+            let cell = cell_of(vertex.position);
+            let existing = neighbor_cells(cell)
+                .filter_map(|neighbor| cells.get(&neighbor))
+                .flatten()
+                .copied()
+                .find(|&candidate| vertices_match(&welded[candidate as usize], vertex, epsilon));
+            existing.unwrap_or_else(|| {
+                let index = welded.len() as u32;
+                welded.push(*vertex);
+                cells.entry(cell).or_default().push(index);
+                index
+            })
+        })
+        .collect();
+
+    let indices = indices.iter().map(|&index| remap[index as usize]).collect();
+    (welded, indices)
+}
+
+fn neighbor_cells(cell: [i64; 3]) -> impl Iterator<Item = [i64; 3]> {
+    (-1..=1).flat_map(move |x| {
+        (-1..=1).flat_map(move |y| (-1..=1).map(move |z| [cell[0] + x, cell[1] + y, cell[2] + z]))
+    })
+}
+
+fn vertices_match(a: &Vertex, b: &Vertex, epsilon: f32) -> bool {
+    let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon);
+    a.joint_indices == b.joint_indices
+        && close(&a.position, &b.position)
+        && close(&a.normal, &b.normal)
+        && close(&a.joint_weights, &b.joint_weights)
+        && close(&a.uv, &b.uv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|corners| [corners[0], corners[1], corners[2]])
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn forsyth_reorders_triangles_without_changing_them() {
+        // An 8x8 grid of quads with its triangles scattered through the list.
+        const SIDE: u32 = 8;
+        let vertex = |x: u32, y: u32| y * (SIDE + 1) + x;
+        let mut grid = Vec::new();
+        for y in 0..SIDE {
+            for x in 0..SIDE {
+                grid.push([vertex(x, y), vertex(x + 1, y), vertex(x + 1, y + 1)]);
+                grid.push([vertex(x + 1, y + 1), vertex(x, y + 1), vertex(x, y)]);
+            }
+        }
+        let triangle_count = grid.len();
+        let scattered: Vec<u32> = (0..triangle_count)
+            .flat_map(|triangle| grid[triangle * 37 % triangle_count])
+            .collect();
+        let vertices = vec![[0.0f32; 3]; ((SIDE + 1) * (SIDE + 1)) as usize];
+
+        let mut indices = scattered.clone();
+        optimize_mesh(&vertices, &mut indices);
+
+        assert_ne!(indices, scattered);
+        assert_eq!(sorted_triangles(&indices), sorted_triangles(&scattered));
+        assert!(
+            average_cache_miss_ratio(&indices, CACHE_SIZE)
+                < average_cache_miss_ratio(&scattered, CACHE_SIZE)
+        );
+    }
+
+    #[test]
+    fn unindexed_quad_welds_to_four_vertices() {
+        let corner = |position: [f32; 3], uv: [f32; 2]| Vertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            joint_indices: [0; 4],
+            joint_weights: [0.0; 4],
+            uv,
+        };
+        let corners = [
+            corner([0.0, 0.0, 0.0], [0.0, 1.0]),
+            corner([1.0, 0.0, 0.0], [1.0, 1.0]),
+            corner([1.0, 1.0, 0.0], [1.0, 0.0]),
+            corner([0.0, 1.0, 0.0], [0.0, 0.0]),
+        ];
+        let vertices = [0, 1, 2, 2, 3, 0].map(|corner: usize| corners[corner]);
+
+        let (welded, indices) = weld_vertices(&vertices, &[0, 1, 2, 3, 4, 5], 1e-5);
+        assert_eq!(welded.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 2, 3, 0]);
+        for (welded, corner) in welded.iter().zip(&corners) {
+            assert_eq!(welded.position, corner.position);
+            assert_eq!(welded.uv, corner.uv);
+        }
+    }
+}
@@ -1,33 +1,60 @@
-use std::{process, sync::Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use ecs::{
-    World,
-    components::{Camera, FpsCamera, MeshHandle, Position, Transform},
+    components::{FpsCamera, MeshHandle, PipelineKey, Position},
+    entities::EntityId,
+    math::Frustum,
+    snapshot::{CameraSnapshot, RenderSnapshot},
 };
-use glam::{Mat4, Vec3};
+use glam::Mat4;
 use log::{error, info};
 use pollster::FutureExt;
 use wgpu::{
-    Adapter, BufferSize, CommandEncoder, Device, DeviceDescriptor, Features, Instance, Limits,
-    Operations, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor, RenderPipeline, RequestAdapterOptions, Surface, TextureView, Trace,
+    Adapter, Backend, Backends, BindGroupLayout, BufferSize, CommandEncoder,
+    CommandEncoderDescriptor, Device, DeviceDescriptor, DeviceType, DownlevelFlags, Features,
+    Instance, Limits, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPassTimestampWrites, RenderPipeline, RequestAdapterOptions, Surface, TextureView, Trace,
     util::StagingBelt,
 };
 
 use crate::{
-    r#async::FrameIndex,
     graphics::buffers::{
         BufferInterface, GpuRingBuffer,
-        submissions::{CameraUniform, IndirectDraw, ModelUniform},
+        submissions::{
+            BoneMatrix, CameraUniform, IndirectDraw, LightUniform, MAX_BONES, MAX_CAMERA_VIEWS,
+            ModelUniform, camera_view_offset,
+        },
     },
-    graphics::mesh::mesh_allocator::MeshAllocator,
+    graphics::culling::{CullInstance, GpuCuller},
+    graphics::draw_list::{DrawCommand, DrawList, DrawOrder},
+    graphics::gpu_device::GpuDevice,
+    graphics::mesh::{Vertex, mesh_allocator::MeshAllocator},
+    graphics::passes::PassConfig,
+    graphics::shadows::{SHADOW_RADIUS, light_view_projection},
+    graphics::stats::FrameStats,
     graphics::viewports::ViewportDescription,
     utils::{RegisterKey, Registry},
 };
 
 pub mod buffers;
+pub mod culling;
+pub mod debug_text;
+pub mod draw_list;
+pub mod frame_encoder;
+pub mod fullscreen;
+pub mod gpu_device;
 pub mod mesh;
+pub mod particles;
+pub mod passes;
+pub mod post_process;
+pub mod render_graph;
+pub mod render_mode;
+pub mod render_target;
 pub mod shaders;
+pub mod shadows;
+pub mod stats;
+pub mod textures;
+pub mod timing;
 pub mod viewports;
 
 #[derive(Debug)]
@@ -35,110 +62,264 @@ pub struct GPUContext {
     pub adapter: Adapter,
     pub device: Device,
     pub queue: Queue,
+    encoders_created: AtomicU64,
 }
 
 impl GPUContext {
-    pub fn init(instance: &Instance, surface: &Surface) -> Self {
-        info!("requesting adpater");
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                compatible_surface: Some(surface),
+    pub fn init(
+        instance: &Instance,
+        backends: Backends,
+        surface: Option<&Surface>,
+    ) -> Result<Self, String> {
+        Self::init_with_options(
+            instance,
+            backends,
+            &RequestAdapterOptions {
+                compatible_surface: surface,
                 ..Default::default()
-            })
-            .block_on()
-            .unwrap_or_else(|err| {
-                error!("failed to request an adapter {}", err);
-                process::exit(1);
-            });
+            },
+        )
+    }
+
+    // `backends` should be what the instance was created with, it is only used
+    // to explain a failed adapter request.
+    pub fn init_with_options(
+        instance: &Instance,
+        backends: Backends,
+        options: &RequestAdapterOptions,
+    ) -> Result<Self, String> {
+        info!("requesting adpater");
+        let adapter = instance.request_adapter(options).block_on().map_err(|err| {
+            let available: Vec<String> = instance
+                .enumerate_adapters(backends)
+                .iter()
+                .map(|adapter| {
+                    let info = adapter.get_info();
+                    format!("{} ({:?})", info.name, info.backend)
+                })
+                .collect();
+            format!(
+                "no suitable adapter, {err}. tried backends {backends:?} with power preference {:?}, force fallback adapter {}, compatible surface {}. adapters on those backends: {available:?}",
+                options.power_preference,
+                options.force_fallback_adapter,
+                options.compatible_surface.is_some(),
+            )
+        })?;
 
         info!("requesting device and queue");
         let (device, queue) = adapter
             .request_device(&DeviceDescriptor {
                 label: None,
-                required_features: Features::empty(),
+                // Optional, GpuTimer and GpuCuller stay disabled without them
+                // and the wireframe render mode draws filled.
+                required_features: adapter.features()
+                    & (Features::TIMESTAMP_QUERY
+                        | Features::POLYGON_MODE_LINE
+                        | GpuCuller::REQUIRED_FEATURES),
                 required_limits: Limits::downlevel_defaults(),
                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                 trace: Trace::Off,
             })
             .block_on()
-            .unwrap_or_else(|err| {
-                error!("failed to retrieve device and queue {}", err);
-                process::exit(1);
-            });
-
-        Self {
-            adapter: adapter,
-            device: device,
-            queue: queue,
+            .map_err(|err| {
+                format!(
+                    "failed to retrieve device and queue from {} {err}",
+                    adapter.get_info().name
+                )
+            })?;
+
+        let gpu_context = Self {
+            adapter,
+            device,
+            queue,
+            encoders_created: AtomicU64::new(0),
+        };
+        gpu_context.capabilities().log();
+        Ok(gpu_context)
+    }
+
+    // Limits and features come from the device rather than the adapter, they
+    // are what we actually requested and can rely on.
+    // wgpu encoders can't be reset and reused once finished, so this only
+    // centralizes creation, every encoder gets a label for debugging tools
+    // and is counted.
+    pub fn begin_encoder(&self, label: &str) -> CommandEncoder {
+        self.encoders_created.fetch_add(1, Ordering::Relaxed);
+        self.device
+            .create_command_encoder(&CommandEncoderDescriptor { label: Some(label) })
+    }
+
+    pub fn encoders_created(&self) -> u64 {
+        self.encoders_created.load(Ordering::Relaxed)
+    }
+
+    pub fn capabilities(&self) -> CapabilityReport {
+        let info = self.adapter.get_info();
+        let features = self.device.features();
+        let limits = self.device.limits();
+        CapabilityReport {
+            backend: info.backend,
+            device_name: info.name,
+            device_type: info.device_type,
+            driver: format!("{} {}", info.driver, info.driver_info),
+            features,
+            adapter_features: self.adapter.features(),
+            max_buffer_size: limits.max_buffer_size,
+            max_bind_groups: limits.max_bind_groups,
+            max_uniform_buffer_binding_size: limits.max_uniform_buffer_binding_size,
+            max_storage_buffer_binding_size: limits.max_storage_buffer_binding_size,
+            max_texture_dimension_2d: limits.max_texture_dimension_2d,
+            max_push_constant_size: limits.max_push_constant_size,
+            multi_draw_indirect: features.contains(Features::MULTI_DRAW_INDIRECT),
+            multi_draw_indirect_count: features.contains(Features::MULTI_DRAW_INDIRECT_COUNT),
+            polygon_mode_line: features.contains(Features::POLYGON_MODE_LINE),
+            push_constants: features.contains(Features::PUSH_CONSTANTS),
+            max_anisotropy: self.max_anisotropy(),
         }
     }
+
+    // Anisotropic filtering is all or nothing in wgpu, without the downlevel
+    // flag any anisotropy_clamp above 1 is ignored.
+    pub fn max_anisotropy(&self) -> u16 {
+        if self
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::ANISOTROPIC_FILTERING)
+        {
+            textures::MAX_ANISOTROPY
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapabilityReport {
+    pub backend: Backend,
+    pub device_name: String,
+    pub device_type: DeviceType,
+    pub driver: String,
+    pub features: Features,
+    pub adapter_features: Features,
+    pub max_buffer_size: u64,
+    pub max_bind_groups: u32,
+    pub max_uniform_buffer_binding_size: u32,
+    pub max_storage_buffer_binding_size: u32,
+    pub max_texture_dimension_2d: u32,
+    pub max_push_constant_size: u32,
+    pub multi_draw_indirect: bool,
+    pub multi_draw_indirect_count: bool,
+    pub polygon_mode_line: bool,
+    pub push_constants: bool,
+    pub max_anisotropy: u16,
+}
+
+impl CapabilityReport {
+    pub fn log(&self) {
+        info!(
+            "adapter: {} ({:?}) on {:?}, driver {}",
+            self.device_name, self.device_type, self.backend, self.driver
+        );
+        info!("device features: {:?}", self.features);
+        info!("adapter features: {:?}", self.adapter_features);
+        info!(
+            "limits: max buffer size {}, max bind groups {}, max uniform binding {}, max storage binding {}, max texture 2d {}, max push constants {}",
+            self.max_buffer_size,
+            self.max_bind_groups,
+            self.max_uniform_buffer_binding_size,
+            self.max_storage_buffer_binding_size,
+            self.max_texture_dimension_2d,
+            self.max_push_constant_size
+        );
+        info!(
+            "multi draw indirect: {}, multi draw indirect count: {}, polygon mode line: {}, push constants: {}, max anisotropy: {}",
+            self.multi_draw_indirect,
+            self.multi_draw_indirect_count,
+            self.polygon_mode_line,
+            self.push_constants,
+            self.max_anisotropy
+        );
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn init_render_pass(
     encoder: &mut CommandEncoder,
     view: &TextureView,
     descriptor: &ViewportDescription,
-    render_pipeline: &RenderPipeline,
-    gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
-    frame_index: &mut FrameIndex,
-    mesh_allocator: &mut MeshAllocator,
+    pipelines: &[RenderPipeline],
+    gpu_buffer_registry: &Registry<Box<dyn BufferInterface>>,
+    frame_index: usize,
+    mesh_allocator: &MeshAllocator,
+    draw_list: &DrawList,
+    culler: Option<&GpuCuller>,
+    camera_view: u32,
+    pass_config: PassConfig,
+    timestamp_writes: Option<RenderPassTimestampWrites>,
+    // Draws everything with this instead of each command's pipeline, for
+    // the debug render modes.
+    pipeline_override: Option<&RenderPipeline>,
 ) {
     let render_pass_descriptor = &RenderPassDescriptor {
         label: Some("Example render pass"),
         color_attachments: &[Some(RenderPassColorAttachment {
-            view: view,
+            view,
             resolve_target: None,
-            ops: Operations {
-                load: wgpu::LoadOp::Clear(descriptor.background),
-                store: wgpu::StoreOp::Store,
-            },
+            ops: pass_config.color_ops(),
         })],
-        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-            view: &descriptor.depth.as_ref().unwrap().view,
-            depth_ops: Some(Operations {
-                load: wgpu::LoadOp::Clear(1.0),
-                store: wgpu::StoreOp::Store,
-            }),
-            stencil_ops: None,
-        }),
-        timestamp_writes: None,
+        depth_stencil_attachment: pass_config
+            .depth_attachment(descriptor.depth.as_ref().map(|depth| &depth.view)),
+        timestamp_writes,
         occlusion_query_set: None,
     };
     let mut render_pass = encoder.begin_render_pass(render_pass_descriptor);
 
-    render_pass.set_pipeline(render_pipeline);
+    let Some(default_pipeline) = pipeline_override.or(pipelines.first()) else {
+        error!("no render pipeline to draw with");
+        return;
+    };
+    render_pass.set_pipeline(default_pipeline);
 
     let main_gpu_camera_key =
         RegisterKey::from_label::<GpuRingBuffer<CameraUniform>>("camera_gpu_uniform_triple");
-    if let Some(camera_uniform_buffer_entry) = gpu_buffer_registry.get(&main_gpu_camera_key) {
-        if let Some(gpu_ring_buffer) = camera_uniform_buffer_entry
-            .as_any()
-            .downcast_ref::<GpuRingBuffer<CameraUniform>>()
-        {
-            let camera_bind_group = gpu_ring_buffer
-                .get_read(frame_index.index())
-                .bind_group
-                .as_ref()
-                .unwrap();
-            render_pass.set_bind_group(0, Some(camera_bind_group), &[]);
-        }
+    let Some(cameras) = gpu_buffer_registry
+        .get(&main_gpu_camera_key)
+        .and_then(|entry| {
+            entry
+                .as_any()
+                .downcast_ref::<GpuRingBuffer<CameraUniform>>()
+        })
+    else {
+        error!("no camera buffer to draw with");
+        return;
+    };
+    let cameras = cameras.get_read(frame_index);
+    // upload_camera_data empties the slot when no camera is active, there is
+    // no view to draw from so the pass only clears.
+    if cameras.element_count == 0 {
+        return;
     }
+    render_pass.set_bind_group(
+        0,
+        cameras.bind_group.as_ref(),
+        &[camera_view_offset(camera_view)],
+    );
 
     let main_gpu_model_key =
         RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>("model_gpu_uniform_triple");
 
-    if let Some(model_uniform_buffer_entry) = gpu_buffer_registry.get(&main_gpu_model_key) {
-        if let Some(gpu_ring_buffer) = model_uniform_buffer_entry
+    if let Some(model_uniform_buffer_entry) = gpu_buffer_registry.get(&main_gpu_model_key)
+        && let Some(gpu_ring_buffer) = model_uniform_buffer_entry
             .as_any()
             .downcast_ref::<GpuRingBuffer<ModelUniform>>()
-        {
-            let model_bind_group = gpu_ring_buffer
-                .get_read(frame_index.index())
-                .bind_group
-                .as_ref()
-                .unwrap();
-            render_pass.set_bind_group(1, Some(model_bind_group), &[]);
-        }
+    {
+        let model_bind_group = gpu_ring_buffer
+            .get_read(frame_index)
+            .bind_group
+            .as_ref()
+            .unwrap();
+        render_pass.set_bind_group(1, Some(model_bind_group), &[]);
     }
 
     let indirect_draw_gpu_key =
@@ -150,39 +331,103 @@ pub fn init_render_pass(
         .downcast_ref::<GpuRingBuffer<IndirectDraw>>()
         .unwrap();
     let indirect_draw_bind_group = indirect_draw_gpu_entry
-        .get_read(frame_index.index())
+        .get_read(frame_index)
         .bind_group
         .as_ref()
         .unwrap();
     render_pass.set_bind_group(2, Some(indirect_draw_bind_group), &[]);
-    let indirect_draw_buffer = &indirect_draw_gpu_entry.get_read(frame_index.index()).buffer;
-
-    render_pass.set_vertex_buffer(
-        0,
-        mesh_allocator
-            .get_current_vertex_buffer(frame_index.index())
-            .slice(..),
-    );
-    render_pass.set_index_buffer(
-        mesh_allocator
-            .get_current_index_buffer(frame_index.index())
-            .slice(..),
-        wgpu::IndexFormat::Uint32,
-    );
 
-    let draw_count = indirect_draw_gpu_entry.get_read(frame_index.index()).element_count;
+    let main_gpu_light_key =
+        RegisterKey::from_label::<GpuRingBuffer<LightUniform>>("light_gpu_uniform_triple");
+    if let Some(light_uniform_buffer_entry) = gpu_buffer_registry.get(&main_gpu_light_key)
+        && let Some(gpu_ring_buffer) = light_uniform_buffer_entry
+            .as_any()
+            .downcast_ref::<GpuRingBuffer<LightUniform>>()
+    {
+        let light_bind_group = gpu_ring_buffer
+            .get_read(frame_index)
+            .bind_group
+            .as_ref()
+            .unwrap();
+        render_pass.set_bind_group(3, Some(light_bind_group), &[]);
+    }
+    let indirect_draw_buffer = &indirect_draw_gpu_entry.get_read(frame_index).buffer;
+
+    let draw_count = indirect_draw_gpu_entry.get_read(frame_index).element_count;
+
+    // The culler is only handed a list that is a single batch, see
+    // Engine::render, anything else is drawn from the list below.
+    if let Some(culler) = culler
+        && let Some((pipeline_key, stream)) = draw_list.single_batch()
+        && let Some(pipeline) = pipeline_override.or(pipelines.get(pipeline_key.0 as usize))
+        && let (Some(vertex_buffer), Some(index_buffer)) = (
+            mesh_allocator.get_current_vertex_buffer(stream, frame_index),
+            mesh_allocator.get_current_index_buffer(stream, frame_index),
+        )
+    {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        culler.draw(&mut render_pass);
+        return;
+    }
 
-    for i in 0..draw_count {
+    // The draw list is sorted by pipeline then stream, so each is only bound
+    // when it changes.
+    let mut bound_pipeline = Some(PipelineKey::DEFAULT);
+    let mut bound_stream = None;
+    for (i, command) in draw_list
+        .commands()
+        .iter()
+        .enumerate()
+        .take(draw_count as usize)
+    {
+        if pipeline_override.is_none() && bound_pipeline != Some(command.pipeline) {
+            let Some(pipeline) = pipelines.get(command.pipeline.0 as usize) else {
+                error!("draw {i} uses missing pipeline {}", command.pipeline.0);
+                continue;
+            };
+            render_pass.set_pipeline(pipeline);
+            bound_pipeline = Some(command.pipeline);
+        }
+        let stream = command.stream;
+        if bound_stream != Some(stream) {
+            let (Some(vertex_buffer), Some(index_buffer)) = (
+                mesh_allocator.get_current_vertex_buffer(stream, frame_index),
+                mesh_allocator.get_current_index_buffer(stream, frame_index),
+            ) else {
+                error!("draw {i} uses missing mesh stream {stream}");
+                continue;
+            };
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            bound_stream = Some(stream);
+        }
         render_pass.draw_indexed_indirect(
             indirect_draw_buffer,
             i as u64 * std::mem::size_of::<IndirectDraw>() as u64,
         );
-        // info!("gpu frame_index drawn: {}, drawcount: {}, i: {}", frame_index.index(), draw_count, i);
     }
 }
 
+pub fn camera_matrices(camera: &FpsCamera, position: &Position) -> (Mat4, Mat4) {
+    let view = Mat4::look_to_rh(position.0, camera.forward(), camera.up());
+    let projection = Mat4::perspective_rh(0.785, 16.0 / 9.0, 0.1, 1000.0);
+    (view, projection)
+}
+
+// The view slot a viewport's pass binds for `camera`. Viewports without a
+// camera, or whose camera is no longer active or was dropped past
+// MAX_CAMERA_VIEWS, use the first view.
+pub fn viewport_camera_view(snapshot: &RenderSnapshot, camera: Option<EntityId>) -> u32 {
+    camera
+        .and_then(|camera| snapshot.view_index(camera))
+        .filter(|&view| view < MAX_CAMERA_VIEWS)
+        .unwrap_or(0) as u32
+}
+
 pub fn upload_camera_data(
-    world: &mut World,
+    snapshot: &RenderSnapshot,
     frame_index: usize,
     staging_belt: &mut StagingBelt,
     device: &Device,
@@ -197,74 +442,270 @@ pub fn upload_camera_data(
         .as_mut_any()
         .downcast_mut::<GpuRingBuffer<CameraUniform>>()
         .unwrap();
-    for (camera, pos, _) in world.query::<(&mut FpsCamera, &mut Position, &Camera)>() {
-        let forward = Vec3::new(
-            camera.yaw.cos() * camera.pitch.cos(),
-            camera.pitch.sin(),
-            camera.yaw.sin() * camera.pitch.cos(),
-        )
-        .normalize();
+    // Views past MAX_CAMERA_VIEWS are dropped, the pass indexing them would
+    // read out of bounds.
+    let camera_uniforms: Vec<CameraUniform> = snapshot
+        .views
+        .iter()
+        .take(MAX_CAMERA_VIEWS)
+        .map(|camera| {
+            let (view, projection) = camera_matrices(&camera.camera, &camera.position);
+            CameraUniform::from_matrices(view, projection)
+        })
+        .collect();
+
+    // Without a camera nothing is written and the slot is marked empty,
+    // rather than left holding the views of the last frame that used it.
+    let camera_entry = camera_ring_buffer.get_write(frame_index);
+    camera_entry.element_count = 0;
+    if camera_uniforms.is_empty() {
+        return;
+    }
+    camera_entry.element_count = camera_uniforms.len() as u32;
 
-        let camera_uniform = CameraUniform {
-            view: Mat4::look_to_rh(pos.0, forward, Vec3::Y).to_cols_array_2d(),
-            projection: Mat4::perspective_rh(0.785, 16.0 / 9.0, 0.1, 1000.0).to_cols_array_2d(),
-        };
+    let mut view_mut = staging_belt.write_buffer(
+        encoder,
+        &camera_entry.buffer,
+        0,
+        BufferSize::new(size_of_val(camera_uniforms.as_slice()) as u64).unwrap(),
+        device,
+    );
 
-        let camera_entry = camera_ring_buffer.get_write(frame_index);
-        camera_entry.element_count = 1;
+    view_mut.copy_from_slice(bytemuck::cast_slice(&camera_uniforms));
+}
 
-        let mut view_mut = staging_belt.write_buffer(
-            encoder,
-            &camera_entry.buffer,
-            0,
-            BufferSize::new(size_of::<CameraUniform>() as u64).unwrap(),
-            device,
-        );
+pub fn upload_light_data(
+    snapshot: &RenderSnapshot,
+    frame_index: usize,
+    staging_belt: &mut StagingBelt,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
+) {
+    let light_buffer_key =
+        RegisterKey::from_label::<GpuRingBuffer<LightUniform>>("light_gpu_uniform_triple");
+    let light_ring_buffer = gpu_buffer_registry
+        .get_mut(&light_buffer_key)
+        .unwrap()
+        .as_mut_any()
+        .downcast_mut::<GpuRingBuffer<LightUniform>>()
+        .unwrap();
 
-        view_mut.copy_from_slice(bytemuck::bytes_of(&camera_uniform));
+    let mut light_uniform = LightUniform::from_lights(&snapshot.lights);
+    if let Some(light) = snapshot.lights.first() {
+        let focus = snapshot
+            .views
+            .first()
+            .map(|view| view.position.0)
+            .unwrap_or_default();
+        light_uniform =
+            light_uniform.with_shadow(light_view_projection(light.direction, focus, SHADOW_RADIUS));
     }
+
+    let light_entry = light_ring_buffer.get_write(frame_index);
+    light_entry.element_count = light_uniform.light_count;
+
+    let mut view_mut = staging_belt.write_buffer(
+        encoder,
+        &light_entry.buffer,
+        0,
+        BufferSize::new(size_of::<LightUniform>() as u64).unwrap(),
+        device,
+    );
+    view_mut.copy_from_slice(bytemuck::bytes_of(&light_uniform));
 }
 
-pub fn upload_indirect_draw_commands(
-    world: &mut World,
+pub fn upload_bone_palette(
+    snapshot: &RenderSnapshot,
     frame_index: usize,
     staging_belt: &mut StagingBelt,
     device: &Device,
     encoder: &mut CommandEncoder,
     gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
 ) {
-    let first_instance_counter = 0;
-
-    let mut batch: Vec<Transform> = Vec::new();
-    let mut mesh_handle = MeshHandle {
-        vertex_offset: 0,
-        index_offset: 0,
-        vertex_count: 0,
-        index_count: 0,
+    let bone_count = snapshot.bone_palette.len().min(MAX_BONES as usize);
+    if bone_count < snapshot.bone_palette.len() {
+        error!(
+            "bone palette has {} matrices, only the first {MAX_BONES} are uploaded",
+            snapshot.bone_palette.len()
+        );
+    }
+    let bones = BoneMatrix::from_palette(&snapshot.bone_palette[..bone_count]);
+
+    let bone_buffer_key =
+        RegisterKey::from_label::<GpuRingBuffer<BoneMatrix>>("bone_palette_gpu_triple");
+    let bone_ring_buffer = gpu_buffer_registry
+        .get_mut(&bone_buffer_key)
+        .unwrap()
+        .as_mut_any()
+        .downcast_mut::<GpuRingBuffer<BoneMatrix>>()
+        .unwrap();
+
+    let bone_entry = bone_ring_buffer.get_write(frame_index);
+    bone_entry.element_count = bones.len() as u32;
+
+    let bone_bytes: &[u8] = bytemuck::cast_slice(&bones);
+    let Some(bone_bytes_size) = BufferSize::new(bone_bytes.len() as u64) else {
+        return;
     };
+    let mut view_mut =
+        staging_belt.write_buffer(encoder, &bone_entry.buffer, 0, bone_bytes_size, device);
+    view_mut.copy_from_slice(bone_bytes);
+}
 
-    for (_i, (transform, mesh)) in world.query::<(&Transform, &MeshHandle)>().enumerate() {
-        batch.push(transform.clone());
-        mesh_handle = mesh.clone();
+// Logs the entity of a renderable whose transform has a NaN or infinite
+// component.
+pub fn has_finite_transform(snapshot: &RenderSnapshot, index: usize) -> bool {
+    let Some((transform, _)) = snapshot.renderables.get(index) else {
+        return false;
+    };
+    if transform.0.is_finite() {
+        return true;
+    }
+    match snapshot.entities.get(index) {
+        Some(entity) => error!("skipping entity {entity:?}, its transform isn't finite"),
+        None => error!("skipping renderable {index}, its transform isn't finite"),
     }
+    false
+}
 
-    let indirect_draw = IndirectDraw {
-        index_count: mesh_handle.index_count,
-        instance_count: batch.len() as u32,
-        first_index: mesh_handle.index_offset as u32,
-        base_vertex: mesh_handle.vertex_offset as i32,
-        first_instance: first_instance_counter,
-        ..Default::default()
+// The order renderables go into the model buffer in: grouped by pipeline,
+// then sorted by distance to the camera as each pipeline's DrawOrder says.
+// Front to back draws are grouped by mesh stream first so each stream is
+// bound once, back to front ones can't be without breaking the order.
+// `draw_orders` is indexed by PipelineKey, missing pipelines draw front to
+// back. Without a camera renderables stay in snapshot order per pipeline.
+pub fn instance_order(
+    snapshot: &RenderSnapshot,
+    camera: Option<&CameraSnapshot>,
+    draw_orders: &[DrawOrder],
+) -> Vec<usize> {
+    let camera_position = camera.map(|camera| camera.position.0);
+    let pipeline = |index: usize| snapshot.pipelines.get(index).copied().unwrap_or_default();
+    let draw_order = |pipeline: PipelineKey| {
+        draw_orders
+            .get(pipeline.0 as usize)
+            .copied()
+            .unwrap_or_default()
+    };
+    let distance = |index: usize| {
+        camera_position.map_or(0.0, |camera_position| {
+            let (transform, _) = &snapshot.renderables[index];
+            transform
+                .0
+                .w_axis
+                .truncate()
+                .distance_squared(camera_position)
+        })
     };
 
-    // indirect_draws.iter().for_each(|x| info!("{:?}", x));
+    // One NaN in the model buffer can corrupt the whole frame, so debug
+    // builds leave out renderables whose transform isn't finite.
+    let mut order: Vec<usize> = (0..snapshot.renderables.len())
+        .filter(|&index| !cfg!(debug_assertions) || has_finite_transform(snapshot, index))
+        .collect();
+    order.sort_by(|&a, &b| {
+        let (pipeline_a, pipeline_b) = (pipeline(a), pipeline(b));
+        pipeline_a
+            .cmp(&pipeline_b)
+            .then_with(|| match draw_order(pipeline_a) {
+                DrawOrder::FrontToBack => {
+                    let (stream_a, stream_b) = (
+                        snapshot.renderables[a].1.stream,
+                        snapshot.renderables[b].1.stream,
+                    );
+                    stream_a
+                        .cmp(&stream_b)
+                        .then_with(|| distance(a).total_cmp(&distance(b)))
+                }
+                DrawOrder::BackToFront => distance(b).total_cmp(&distance(a)),
+            })
+    });
+    order
+}
 
-    let mut model_matrices: Vec<ModelUniform> = Vec::new();
-    batch.iter().for_each(|x| {
+// The frame's draw commands and their model matrices, in instance_order.
+// Each run of renderables sharing a mesh and pipeline becomes one instanced
+// command whose instances index the matrices through first_instance, so
+// neighbours with the same mesh still batch. A snapshot without renderables
+// builds no commands at all, rather than a zero-instance draw of a default
+// mesh.
+pub fn build_indirect_draws(
+    snapshot: &RenderSnapshot,
+    camera: Option<&CameraSnapshot>,
+    mesh_allocator: &MeshAllocator,
+    draw_orders: &[DrawOrder],
+) -> (DrawList, Vec<ModelUniform>) {
+    let order = instance_order(snapshot, camera, draw_orders);
+    let mut draw_list = DrawList::new();
+    let mut model_matrices: Vec<ModelUniform> = Vec::with_capacity(order.len());
+    let mut current: Option<(MeshHandle, PipelineKey)> = None;
+    let mut run: Option<DrawCommand> = None;
+
+    for &index in &order {
+        let (Some((transform, mesh)), Some(bone_range)) = (
+            snapshot.renderables.get(index),
+            snapshot.bone_ranges.get(index),
+        ) else {
+            continue;
+        };
+        let instance = model_matrices.len() as u32;
         model_matrices.push(ModelUniform {
-            model: x.0.to_cols_array_2d(),
+            bone_offset: bone_range.offset,
+            bone_count: bone_range.count,
+            ..ModelUniform::from_matrix(transform.0)
         });
-    });
+
+        let pipeline = snapshot.pipelines.get(index).copied().unwrap_or_default();
+        if current == Some((*mesh, pipeline))
+            && let Some(command) = run.as_mut()
+        {
+            command.draw.instance_count += 1;
+            continue;
+        }
+        if let Some(command) = run.take() {
+            draw_list.push(command);
+        }
+        run = Some(DrawCommand {
+            pipeline,
+            stream: mesh.stream,
+            draw: IndirectDraw {
+                index_count: mesh.index_count,
+                instance_count: 1,
+                first_index: (mesh.index_offset / size_of::<u32>() as u64) as u32,
+                base_vertex: (mesh.vertex_offset
+                    / mesh_allocator
+                        .vertex_stride(mesh.stream)
+                        .unwrap_or(size_of::<Vertex>() as u64)) as i32,
+                first_instance: instance,
+            },
+        });
+        current = Some((*mesh, pipeline));
+    }
+    if let Some(command) = run {
+        draw_list.push(command);
+    }
+
+    draw_list.set_instance_order(order);
+    (draw_list, model_matrices)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn upload_indirect_draw_commands(
+    snapshot: &RenderSnapshot,
+    camera: Option<&CameraSnapshot>,
+    frame_index: usize,
+    staging_belt: &mut StagingBelt,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    gpu_buffer_registry: &mut Registry<Box<dyn BufferInterface>>,
+    model_bind_group_layout: &BindGroupLayout,
+    mesh_allocator: &MeshAllocator,
+    draw_orders: &[DrawOrder],
+) -> (FrameStats, DrawList) {
+    let (draw_list, model_matrices) =
+        build_indirect_draws(snapshot, camera, mesh_allocator, draw_orders);
+    let indirect_draws = draw_list.indirect_draws();
 
     let indirect_draw_buffer_key =
         RegisterKey::from_label::<GpuRingBuffer<IndirectDraw>>("indirect_draw_buffer");
@@ -275,18 +716,33 @@ pub fn upload_indirect_draw_commands(
         .downcast_mut::<GpuRingBuffer<IndirectDraw>>()
         .unwrap();
 
+    // The slot still holds the count from the last frame that used it, which
+    // the render pass would otherwise draw stale commands up to.
     let indirect_entry = indirect_draw_buffer.get_write(frame_index);
-    indirect_entry.element_count = 1;
+    indirect_entry.element_count = 0;
 
-    let mut indirect_draw_view_mut = staging_belt.write_buffer(
-        encoder,
-        &indirect_entry.buffer,
-        0,
-        BufferSize::new(size_of::<IndirectDraw>() as u64).unwrap(),
+    let indirect_draw_bytes: &[u8] = bytemuck::cast_slice(&indirect_draws);
+    if let Some(indirect_draw_size) = BufferSize::new(indirect_draw_bytes.len() as u64) {
+        let mut indirect_draw_view_mut = staging_belt.write_buffer(
+            encoder,
+            &indirect_entry.buffer,
+            0,
+            indirect_draw_size,
+            device,
+        );
+        indirect_draw_view_mut.copy_from_slice(indirect_draw_bytes);
+        indirect_entry.element_count = indirect_draws.len() as u32;
+    }
+
+    if let Err(err) = ModelUniform::reserve_models(
         device,
-    );
-    indirect_draw_view_mut.copy_from_slice(bytemuck::bytes_of(&indirect_draw));
-    std::mem::drop(indirect_draw_view_mut);
+        model_bind_group_layout,
+        gpu_buffer_registry,
+        frame_index,
+        model_matrices.len() as u64,
+    ) {
+        error!("failed to grow model buffer {err}");
+    }
 
     let model_buffer_key =
         RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>("model_gpu_uniform_triple");
@@ -300,14 +756,248 @@ pub fn upload_indirect_draw_commands(
     let model_entry = model_buffer.get_write(frame_index);
     model_entry.element_count = model_matrices.len() as u32;
 
-    let model_matrices_bytes = bytemuck::cast_slice(&model_matrices);
-    let total_model_matrices_size = BufferSize::new(model_matrices_bytes.len() as u64).unwrap();
-    let mut model_matrices_view_mut = staging_belt.write_buffer(
-        encoder,
-        &model_entry.buffer,
-        0,
-        total_model_matrices_size,
-        device,
-    );
-    model_matrices_view_mut.copy_from_slice(model_matrices_bytes);
+    let model_matrices_bytes: &[u8] = bytemuck::cast_slice(&model_matrices);
+    if let Some(total_model_matrices_size) = BufferSize::new(model_matrices_bytes.len() as u64) {
+        let mut model_matrices_view_mut = staging_belt.write_buffer(
+            encoder,
+            &model_entry.buffer,
+            0,
+            total_model_matrices_size,
+            device,
+        );
+        model_matrices_view_mut.copy_from_slice(model_matrices_bytes);
+    }
+
+    (FrameStats::from_draws(&indirect_draws), draw_list)
+}
+
+// One single-instance draw per renderable in `stream`, with first_instance
+// indexing the model buffer written by upload_indirect_draw_commands. Without
+// a camera nothing is culled.
+// `instance_order` is the model buffer's order, see DrawList::instance_order.
+pub fn upload_cull_instances(
+    snapshot: &RenderSnapshot,
+    camera: Option<&CameraSnapshot>,
+    instance_order: &[usize],
+    stream: u32,
+    gpu: &dyn GpuDevice,
+    mesh_allocator: &MeshAllocator,
+    culler: &mut GpuCuller,
+) -> Result<(), String> {
+    let frustum = camera.map(|camera| {
+        let (view, projection) = camera_matrices(&camera.camera, &camera.position);
+        Frustum::from_view_proj(projection * view)
+    });
+    let vertex_stride = mesh_allocator
+        .vertex_stride(stream)
+        .unwrap_or(size_of::<Vertex>() as u64);
+
+    let instances: Vec<CullInstance> = instance_order
+        .iter()
+        .enumerate()
+        .filter_map(|(instance, &index)| {
+            let (_, mesh) = snapshot.renderables.get(index)?;
+            if mesh.stream != stream {
+                return None;
+            }
+            let bounds = snapshot.bounds.get(index).copied().flatten();
+            let draw = IndirectDraw {
+                index_count: mesh.index_count,
+                instance_count: 1,
+                first_index: (mesh.index_offset / size_of::<u32>() as u64) as u32,
+                base_vertex: (mesh.vertex_offset / vertex_stride) as i32,
+                first_instance: instance as u32,
+            };
+            Some(CullInstance::new(draw, frustum.and(bounds.as_ref())))
+        })
+        .collect();
+
+    culler.upload(
+        gpu,
+        &frustum.unwrap_or(Frustum::from_view_proj(Mat4::IDENTITY)),
+        &instances,
+    )
+}
+
+#[cfg(all(test, feature = "test-mock-gpu"))]
+mod mock_gpu_tests {
+    use super::*;
+    use crate::graphics::gpu_device::MockGpuDevice;
+
+    fn element_count<T: 'static>(
+        registry: &Registry<Box<dyn BufferInterface>>,
+        label: &'static str,
+        frame_index: usize,
+    ) -> u32 {
+        registry
+            .get(&RegisterKey::from_label::<GpuRingBuffer<T>>(label))
+            .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<T>>())
+            .expect("buffer should be registered")
+            .get_read(frame_index)
+            .element_count
+    }
+
+    fn set_element_count<T: 'static>(
+        registry: &mut Registry<Box<dyn BufferInterface>>,
+        label: &'static str,
+        frame_index: usize,
+        count: u32,
+    ) {
+        registry
+            .get_mut(&RegisterKey::from_label::<GpuRingBuffer<T>>(label))
+            .and_then(|entry| entry.as_mut_any().downcast_mut::<GpuRingBuffer<T>>())
+            .expect("buffer should be registered")
+            .get_write(frame_index)
+            .element_count = count;
+    }
+
+    // Slots left over from an earlier frame must not be drawn through when
+    // the world has no camera and nothing to draw.
+    #[test]
+    fn empty_world_empties_the_slots() {
+        let gpu = MockGpuDevice::new();
+        let device = gpu.device();
+        let mut registry = Registry::<Box<dyn BufferInterface>>::default();
+        let camera_layout = CameraUniform::default().create_bind_group_layout(device);
+        let model_layout = ModelUniform::default().create_bind_group_layout(device);
+        let indirect_layout = IndirectDraw::default().create_bind_group_layout(device);
+        CameraUniform::default()
+            .create_and_store_buffers(device, &gpu, &camera_layout, &mut registry, 0)
+            .unwrap();
+        ModelUniform::default()
+            .create_and_store_buffers(device, &gpu, &model_layout, &mut registry, 0)
+            .unwrap();
+        IndirectDraw::default()
+            .create_and_store_buffers(device, &gpu, &indirect_layout, &mut registry, 0)
+            .unwrap();
+        let mesh_allocator = MeshAllocator::new(&gpu, 256, 256, 256, 256);
+
+        let frame_index = 1;
+        set_element_count::<CameraUniform>(
+            &mut registry,
+            "camera_gpu_uniform_triple",
+            frame_index,
+            2,
+        );
+        set_element_count::<ModelUniform>(
+            &mut registry,
+            "model_gpu_uniform_triple",
+            frame_index,
+            3,
+        );
+        set_element_count::<IndirectDraw>(&mut registry, "indirect_draw_buffer", frame_index, 3);
+
+        let mut snapshot = RenderSnapshot::default();
+        ecs::World::new().extract_render_snapshot(&mut snapshot);
+        let mut staging_belt = StagingBelt::new(1024);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        upload_camera_data(
+            &snapshot,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            &mut registry,
+        );
+        let (frame_stats, draw_list) = upload_indirect_draw_commands(
+            &snapshot,
+            None,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            &mut registry,
+            &model_layout,
+            &mesh_allocator,
+            &[DrawOrder::default()],
+        );
+        staging_belt.finish();
+
+        assert!(draw_list.is_empty());
+        assert_eq!(frame_stats.draw_calls, 0);
+        assert_eq!(
+            element_count::<CameraUniform>(&registry, "camera_gpu_uniform_triple", frame_index),
+            0
+        );
+        assert_eq!(
+            element_count::<ModelUniform>(&registry, "model_gpu_uniform_triple", frame_index),
+            0
+        );
+        assert_eq!(
+            element_count::<IndirectDraw>(&registry, "indirect_draw_buffer", frame_index),
+            0
+        );
+    }
+
+    #[test]
+    fn empty_snapshot_builds_no_draws() {
+        let gpu = MockGpuDevice::new();
+        let mesh_allocator = MeshAllocator::new(&gpu, 256, 256, 256, 256);
+        let snapshot = RenderSnapshot::default();
+
+        let (draw_list, model_matrices) =
+            build_indirect_draws(&snapshot, None, &mesh_allocator, &[]);
+        assert!(draw_list.is_empty());
+        assert!(draw_list.instance_order().is_empty());
+        assert!(model_matrices.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ecs::components::Transform;
+    use glam::Vec3;
+    use wgpu::InstanceDescriptor;
+
+    use super::*;
+
+    #[test]
+    fn init_without_backends_reports_why() {
+        let instance = Instance::new(&InstanceDescriptor {
+            backends: Backends::empty(),
+            ..Default::default()
+        });
+        let err = GPUContext::init(&instance, Backends::empty(), None).unwrap_err();
+        assert!(err.starts_with("no suitable adapter"), "{err}");
+        assert!(err.contains("adapters on those backends: []"), "{err}");
+    }
+
+    fn camera_at_origin() -> CameraSnapshot {
+        let position = Position(Vec3::ZERO);
+        CameraSnapshot {
+            entity: ecs::World::new().spawn((position,)),
+            camera: FpsCamera {
+                yaw: 0.0,
+                pitch: 0.0,
+                roll: 0.0,
+                speed: 1.0,
+                sprint_multiplier: 2.0,
+                sensitivity: 0.002,
+            },
+            position,
+        }
+    }
+
+    #[test]
+    fn instance_order_sorts_by_distance_as_the_draw_order_says() {
+        let mut snapshot = RenderSnapshot::default();
+        for z in [5.0, 1.0, 3.0] {
+            snapshot.renderables.push((
+                Transform(Mat4::from_translation(Vec3::new(0.0, 0.0, z))),
+                MeshHandle {
+                    vertex_offset: 0,
+                    index_offset: 0,
+                    vertex_count: 3,
+                    index_count: 3,
+                    stream: 0,
+                },
+            ));
+        }
+        let camera = camera_at_origin();
+
+        let front_to_back = instance_order(&snapshot, Some(&camera), &[DrawOrder::FrontToBack]);
+        assert_eq!(front_to_back, [1, 2, 0]);
+        let back_to_front = instance_order(&snapshot, Some(&camera), &[DrawOrder::BackToFront]);
+        assert_eq!(back_to_front, [0, 2, 1]);
+    }
 }
@@ -0,0 +1,175 @@
+use bytemuck::{Pod, Zeroable};
+use ecs::snapshot::ParticleSnapshot;
+use wgpu::{
+    BindGroup, BindGroupLayout, ColorTargetState, ColorWrites, CommandEncoder, Device,
+    FragmentState, MultisampleState, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, TextureFormat, TextureView, VertexAttribute, VertexBufferLayout, VertexState,
+    VertexStepMode, vertex_attr_array,
+};
+
+use crate::graphics::{
+    gpu_device::GpuDevice,
+    mesh::immediate::ImmediateGeometry,
+    passes::{DepthConfig, DepthMode, PassConfig},
+};
+
+// Room the instance buffers start with, they grow if a frame has more.
+pub const PARTICLE_CAPACITY: u64 = 4096;
+
+// Vertices per particle quad, generated in the shader from the vertex index.
+const QUAD_VERTICES: u32 = 6;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct ParticleInstance {
+    pub position: [f32; 3],
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl ParticleInstance {
+    const ATTRIBUTES: [VertexAttribute; 3] = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32,
+        2 => Float32x4
+    ];
+
+    pub fn create_buffer_layout<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    // Fades out over the particle's lifetime.
+    pub fn from_snapshot(particle: &ParticleSnapshot) -> Self {
+        Self {
+            position: particle.position.to_array(),
+            size: particle.size,
+            color: [1.0, 1.0, 1.0, (1.0 - particle.life).clamp(0.0, 1.0)],
+        }
+    }
+}
+
+// Draws every live particle as a camera-facing quad in one instanced draw.
+// The instances are rebuilt each frame through immediate geometry, blended
+// over the scene and depth tested against it without writing depth.
+pub struct ParticleRenderer {
+    pipeline: RenderPipeline,
+    instances: ImmediateGeometry<ParticleInstance>,
+    instance_data: Vec<ParticleInstance>,
+    instance_count: u32,
+    frame_index: usize,
+}
+
+impl ParticleRenderer {
+    // `shader` is particles.wgsl, `camera_layout` the scene's camera layout
+    // so the scene's camera bind group can be reused.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        gpu: &dyn GpuDevice,
+        shader: &ShaderModule,
+        camera_layout: &BindGroupLayout,
+        color_format: TextureFormat,
+        depth_format: TextureFormat,
+        sample_count: u32,
+        initial_capacity: u64,
+    ) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("particles"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let depth_mode = DepthMode::Transparent;
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("particles"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[ParticleInstance::create_buffer_layout()],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: depth_mode.blend(),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(depth_mode.depth_stencil_state(depth_format)),
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            // Only the vertex buffers are used, as instance buffers.
+            instances: ImmediateGeometry::new(gpu, "particle instances", initial_capacity, 0),
+            instance_data: Vec::new(),
+            instance_count: 0,
+            frame_index: 0,
+        }
+    }
+
+    pub fn prepare(
+        &mut self,
+        gpu: &dyn GpuDevice,
+        particles: &[ParticleSnapshot],
+        frame_index: usize,
+    ) {
+        self.instance_data.clear();
+        self.instance_data
+            .extend(particles.iter().map(ParticleInstance::from_snapshot));
+        self.instances.push(&self.instance_data, &[]);
+        self.instances.flush(gpu, frame_index);
+        self.instance_count = self.instance_data.len() as u32;
+        self.frame_index = frame_index;
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instance_count
+    }
+
+    // `camera_bind_group` and `camera_offset` select the view, as for the
+    // scene pass.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        color: &TextureView,
+        depth: Option<&TextureView>,
+        camera_bind_group: &BindGroup,
+        camera_offset: u32,
+    ) {
+        if self.instance_count == 0 {
+            return;
+        }
+        let pass_config = PassConfig::overlay(DepthConfig::ReadOnly);
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("particles"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: color,
+                resolve_target: None,
+                ops: pass_config.color_ops(),
+            })],
+            depth_stencil_attachment: pass_config.depth_attachment(depth),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[camera_offset]);
+        render_pass.set_vertex_buffer(0, self.instances.vertex_buffer(self.frame_index).slice(..));
+        render_pass.draw(0..QUAD_VERTICES, 0..self.instance_count);
+    }
+}
@@ -0,0 +1,124 @@
+use wgpu::{
+    BlendState, Color, CompareFunction, DepthBiasState, DepthStencilState, LoadOp, Operations,
+    RenderPassDepthStencilAttachment, StencilState, StoreOp, TextureFormat, TextureView,
+};
+
+use crate::graphics::draw_list::DrawOrder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthConfig {
+    // The pass has no depth attachment, e.g. a UI drawn in painter's order.
+    None,
+    Clear(f32),
+    // Keeps and updates the depth an earlier pass wrote.
+    Load,
+    // Tests against an earlier pass's depth without writing it, the pipeline
+    // has to be created with depth writes off.
+    ReadOnly,
+}
+
+// How a pass treats the color and depth it renders into. Layered passes,
+// e.g. UI over the 3D scene, load what the pass below left behind instead
+// of clearing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassConfig {
+    pub color: LoadOp<Color>,
+    pub depth: DepthConfig,
+}
+
+impl PassConfig {
+    // The first pass of a frame, clears everything.
+    pub fn scene(background: Color) -> Self {
+        Self {
+            color: LoadOp::Clear(background),
+            depth: DepthConfig::Clear(1.0),
+        }
+    }
+
+    // Composites over whatever the earlier passes drew.
+    pub fn overlay(depth: DepthConfig) -> Self {
+        Self {
+            color: LoadOp::Load,
+            depth,
+        }
+    }
+
+    pub fn color_ops(&self) -> Operations<Color> {
+        Operations {
+            load: self.color,
+            store: StoreOp::Store,
+        }
+    }
+
+    // None when the pass doesn't use depth or there's no depth view to use.
+    pub fn depth_attachment<'a>(
+        &self,
+        view: Option<&'a TextureView>,
+    ) -> Option<RenderPassDepthStencilAttachment<'a>> {
+        let depth_ops = match self.depth {
+            DepthConfig::None => return None,
+            DepthConfig::Clear(depth) => Some(Operations {
+                load: LoadOp::Clear(depth),
+                store: StoreOp::Store,
+            }),
+            DepthConfig::Load => Some(Operations {
+                load: LoadOp::Load,
+                store: StoreOp::Store,
+            }),
+            DepthConfig::ReadOnly => None,
+        };
+        Some(RenderPassDepthStencilAttachment {
+            view: view?,
+            depth_ops,
+            stencil_ops: None,
+        })
+    }
+}
+
+// How a pipeline's draws use the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthMode {
+    // Tested and written, for regular geometry.
+    #[default]
+    Opaque,
+    // Drawn at the far plane behind everything, so it passes where the depth
+    // is still cleared and never writes.
+    Skybox,
+    // Always drawn on top without writing, e.g. debug geometry.
+    Overlay,
+    // Alpha blended, tested against opaque geometry without hiding what is
+    // behind it.
+    Transparent,
+}
+
+impl DepthMode {
+    pub fn depth_stencil_state(self, format: TextureFormat) -> DepthStencilState {
+        let (depth_write_enabled, depth_compare) = match self {
+            DepthMode::Opaque => (true, CompareFunction::Less),
+            DepthMode::Skybox => (false, CompareFunction::LessEqual),
+            DepthMode::Overlay => (false, CompareFunction::Always),
+            DepthMode::Transparent => (false, CompareFunction::Less),
+        };
+        DepthStencilState {
+            format,
+            depth_write_enabled,
+            depth_compare,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }
+    }
+
+    pub fn blend(self) -> Option<BlendState> {
+        match self {
+            DepthMode::Transparent => Some(BlendState::ALPHA_BLENDING),
+            DepthMode::Opaque | DepthMode::Skybox | DepthMode::Overlay => None,
+        }
+    }
+
+    pub fn draw_order(self) -> DrawOrder {
+        match self {
+            DepthMode::Transparent => DrawOrder::BackToFront,
+            DepthMode::Opaque | DepthMode::Skybox | DepthMode::Overlay => DrawOrder::FrontToBack,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use wgpu::{
+    AddressMode, BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource,
+    BindingType, CommandEncoder, Device, FilterMode, Operations, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModule, ShaderStages, TextureFormat, TextureSampleType, TextureView,
+    TextureViewDimension,
+};
+
+use crate::graphics::{
+    buffers::{bindgroups::create_bind_group, layouts::create_bind_group_layout},
+    fullscreen::{create_fullscreen_pipeline, draw_fullscreen},
+};
+
+// A fullscreen triangle pass sampling a single source texture. `shader` only
+// needs an fs_main, the vertex stage is the shared fullscreen stub.
+#[derive(Debug)]
+pub struct PostProcessPass {
+    label: &'static str,
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    bind_group: Option<BindGroup>,
+}
+
+impl PostProcessPass {
+    pub fn new(
+        device: &Device,
+        label: &'static str,
+        shader: &ShaderModule,
+        target_format: TextureFormat,
+    ) -> Self {
+        let bind_group_layout = create_bind_group_layout(
+            label,
+            device,
+            &vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline =
+            create_fullscreen_pipeline(device, label, &pipeline_layout, shader, target_format);
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            label,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            bind_group: None,
+        }
+    }
+
+    // Has to be called again whenever the source texture is recreated, e.g. on resize.
+    pub fn set_source(&mut self, device: &Device, source: &TextureView) {
+        self.bind_group = Some(create_bind_group(
+            self.label,
+            device,
+            &self.bind_group_layout,
+            &vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(source),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        ));
+    }
+
+    pub fn encode(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(self.label),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, Some(bind_group), &[]);
+        draw_fullscreen(&mut render_pass);
+    }
+}
@@ -0,0 +1,139 @@
+use wgpu::CommandEncoder;
+
+pub type PassExecute<'a> = Box<dyn FnMut(&mut CommandEncoder) + 'a>;
+
+pub struct RenderGraphPass<'a> {
+    pub name: &'static str,
+    pub reads: Vec<&'static str>,
+    pub writes: Vec<&'static str>,
+    execute: PassExecute<'a>,
+}
+
+// Passes name the textures they read and write. A pass runs after every other
+// pass that writes one of its inputs; otherwise passes keep the order they
+// were added in.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    passes: Vec<RenderGraphPass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[&'static str],
+        writes: &[&'static str],
+        execute: impl FnMut(&mut CommandEncoder) + 'a,
+    ) {
+        self.passes.push(RenderGraphPass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            execute: Box::new(execute),
+        });
+    }
+
+    pub fn passes(&self) -> &[RenderGraphPass<'a>] {
+        &self.passes
+    }
+
+    pub fn execution_order(&self) -> Result<Vec<usize>, String> {
+        let pass_count = self.passes.len();
+        let mut dependents = vec![Vec::new(); pass_count];
+        let mut remaining_dependencies = vec![0; pass_count];
+
+        for (reader_index, reader) in self.passes.iter().enumerate() {
+            for (writer_index, writer) in self.passes.iter().enumerate() {
+                if writer_index != reader_index
+                    && writer
+                        .writes
+                        .iter()
+                        .any(|write| reader.reads.contains(write))
+                {
+                    dependents[writer_index].push(reader_index);
+                    remaining_dependencies[reader_index] += 1;
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(pass_count);
+        let mut scheduled = vec![false; pass_count];
+        while order.len() < pass_count {
+            let Some(next) =
+                (0..pass_count).find(|&i| !scheduled[i] && remaining_dependencies[i] == 0)
+            else {
+                let blocked: Vec<&str> = (0..pass_count)
+                    .filter(|&i| !scheduled[i])
+                    .map(|i| self.passes[i].name)
+                    .collect();
+                return Err(format!("render graph has a cycle between {blocked:?}"));
+            };
+
+            scheduled[next] = true;
+            for &dependent in &dependents[next] {
+                remaining_dependencies[dependent] -= 1;
+            }
+            order.push(next);
+        }
+
+        Ok(order)
+    }
+
+    pub fn execute(&mut self, encoder: &mut CommandEncoder) -> Result<(), String> {
+        for index in self.execution_order()? {
+            (self.passes[index].execute)(encoder);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(graph: &RenderGraph) -> Vec<&'static str> {
+        graph
+            .execution_order()
+            .unwrap()
+            .into_iter()
+            .map(|index| graph.passes()[index].name)
+            .collect()
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass("post_process", &["scene_color"], &["swapchain"], |_| {});
+        graph.add_pass("overlay", &["swapchain"], &["swapchain"], |_| {});
+        graph.add_pass("opaque", &["shadow_map"], &["scene_color", "depth"], |_| {});
+        graph.add_pass("shadow", &[], &["shadow_map"], |_| {});
+
+        assert_eq!(
+            names(&graph),
+            ["shadow", "opaque", "post_process", "overlay"]
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass("a", &[], &["a"], |_| {});
+        graph.add_pass("b", &[], &["b"], |_| {});
+        graph.add_pass("c", &[], &["c"], |_| {});
+
+        assert_eq!(names(&graph), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass("a", &["b"], &["a"], |_| {});
+        graph.add_pass("b", &["a"], &["b"], |_| {});
+
+        assert!(graph.execution_order().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState, PolygonMode};
+
+use crate::graphics::passes::DepthMode;
+
+// What the scene pass draws, for debugging. Every mode but Normal replaces
+// the entities' own pipelines with one debug pipeline from debug.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Normal,
+    // Edges only, filled in a flat color where line rasterization isn't
+    // supported.
+    Wireframe,
+    // World space normals mapped to 0..1.
+    Normals,
+    // Distance from the camera, near is white.
+    Depth,
+    // Every fragment adds a little, ignoring depth, so bright areas are
+    // shaded many times.
+    Overdraw,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 5] = [
+        RenderMode::Normal,
+        RenderMode::Wireframe,
+        RenderMode::Normals,
+        RenderMode::Depth,
+        RenderMode::Overdraw,
+    ];
+
+    // Wraps back to Normal after the last mode.
+    pub fn next(self) -> RenderMode {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // The debug modes, in the order their pipelines are built.
+    pub fn debug_modes() -> impl Iterator<Item = RenderMode> {
+        Self::ALL
+            .into_iter()
+            .filter(|&mode| mode != RenderMode::Normal)
+    }
+
+    // Index of the mode's pipeline among the debug pipelines, None for
+    // Normal, which draws with each entity's pipeline.
+    pub fn debug_pipeline(self) -> Option<usize> {
+        Self::debug_modes().position(|mode| mode == self)
+    }
+
+    pub fn fragment_entry(self) -> &'static str {
+        match self {
+            RenderMode::Normal => "fs_main",
+            RenderMode::Wireframe => "fs_wireframe",
+            RenderMode::Normals => "fs_normals",
+            RenderMode::Depth => "fs_depth",
+            RenderMode::Overdraw => "fs_overdraw",
+        }
+    }
+
+    pub fn depth_mode(self) -> DepthMode {
+        match self {
+            RenderMode::Overdraw => DepthMode::Overlay,
+            _ => DepthMode::Opaque,
+        }
+    }
+
+    pub fn blend(self) -> Option<BlendState> {
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        match self {
+            RenderMode::Overdraw => Some(BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+            _ => None,
+        }
+    }
+
+    // Line needs POLYGON_MODE_LINE, without it wireframe falls back to Fill.
+    pub fn polygon_mode(self, line_supported: bool) -> PolygonMode {
+        match self {
+            RenderMode::Wireframe if line_supported => PolygonMode::Line,
+            _ => PolygonMode::Fill,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycling_selects_each_pipeline_and_wraps_to_normal() {
+        let mut mode = RenderMode::Normal;
+        let mut selected = vec![mode.debug_pipeline()];
+        for _ in 0..RenderMode::ALL.len() {
+            mode = mode.next();
+            assert_ne!(Some(&mode.debug_pipeline()), selected.last());
+            selected.push(mode.debug_pipeline());
+        }
+
+        assert_eq!(mode, RenderMode::Normal);
+        assert_eq!(selected, [None, Some(0), Some(1), Some(2), Some(3), None]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::mpsc;
+
+use wgpu::{
+    BufferUsages, COPY_BYTES_PER_ROW_ALIGNMENT, CommandEncoderDescriptor, Device, Extent3d,
+    MapMode, Origin3d, PollType, Queue, SurfaceConfiguration, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureFormat,
+    TextureUsages, TextureView, wgt::TextureDescriptor,
+};
+
+use crate::graphics::buffers;
+
+// A color texture that can be rendered into and then sampled by a later pass
+// or copied out, e.g. for post processing or picking.
+#[derive(Debug)]
+pub struct RenderTarget {
+    pub texture: Texture,
+    pub view: TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&Default::default());
+
+        Self {
+            texture,
+            view,
+            format,
+            width,
+            height,
+        }
+    }
+
+    pub fn for_viewport(device: &Device, label: &str, config: &SurfaceConfiguration) -> Self {
+        Self::new(
+            device,
+            label,
+            config.width.max(1),
+            config.height.max(1),
+            config.format,
+        )
+    }
+
+    // Blocks until the GPU has finished all submitted work, only meant for
+    // one-off reads like picking or debugging.
+    pub fn read_pixel(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        x: u32,
+        y: u32,
+    ) -> Result<Vec<u8>, String> {
+        if x >= self.width || y >= self.height {
+            return Err(format!(
+                "pixel ({x}, {y}) is outside the {}x{} render target",
+                self.width, self.height
+            ));
+        }
+        let Some(pixel_size) = self.format.block_copy_size(None) else {
+            return Err(format!(
+                "cannot read back pixels of format {:?}",
+                self.format
+            ));
+        };
+
+        let buffer = buffers::create_buffer(
+            device,
+            "render target readback",
+            COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            vec![BufferUsages::COPY_DST, BufferUsages::MAP_READ],
+            false,
+        );
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("render target readback"),
+        });
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..pixel_size as u64);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device
+            .poll(PollType::Wait)
+            .map_err(|err| format!("failed to wait for readback {err}"))?;
+        receiver
+            .recv()
+            .map_err(|err| format!("readback callback was dropped {err}"))?
+            .map_err(|err| format!("failed to map readback buffer {err}"))?;
+
+        let pixel = slice.get_mapped_range().to_vec();
+        buffer.unmap();
+        Ok(pixel)
+    }
+}
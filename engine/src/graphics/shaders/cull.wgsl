@@ -0,0 +1,80 @@
+struct IndirectDraw {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+// Scalar padding, see LightUniform in shader.wgsl.
+struct CullParams {
+    planes: array<vec4<f32>, 6>,
+    draw_count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
+// Local space bounds of the mesh drawn by `draw`, the model matrix is read
+// from models[draw.first_instance].
+struct CullInstance {
+    bounds_min: vec3<f32>,
+    cullable: u32,
+    bounds_max: vec3<f32>,
+    _padding0: u32,
+    draw: IndirectDraw,
+    _padding1: u32,
+    _padding2: u32,
+    _padding3: u32,
+};
+
+struct ModelData {
+    model: mat4x4<f32>,
+    bone_offset: u32,
+    bone_count: u32,
+    _padding: vec2<u32>,
+};
+
+@group(0) @binding(0)
+var<uniform> params: CullParams;
+
+@group(0) @binding(1)
+var<storage, read> models: array<ModelData>;
+
+@group(0) @binding(2)
+var<storage, read> instances: array<CullInstance>;
+
+@group(0) @binding(3)
+var<storage, read_write> visible_draws: array<IndirectDraw>;
+
+@group(0) @binding(4)
+var<storage, read_write> visible_count: atomic<u32>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.draw_count {
+        return;
+    }
+    let instance = instances[index];
+
+    if instance.cullable != 0u {
+        let model = models[instance.draw.first_instance].model;
+        let center = (model * vec4((instance.bounds_min + instance.bounds_max) * 0.5, 1.0)).xyz;
+        let half_extent = (instance.bounds_max - instance.bounds_min) * 0.5;
+        // Half extents of the world space box around the transformed bounds.
+        let extent = abs(model[0].xyz) * half_extent.x
+            + abs(model[1].xyz) * half_extent.y
+            + abs(model[2].xyz) * half_extent.z;
+
+        for (var i = 0u; i < 6u; i++) {
+            let plane = params.planes[i];
+            if dot(plane.xyz, center) + plane.w + dot(abs(plane.xyz), extent) < 0.0 {
+                return;
+            }
+        }
+    }
+
+    let slot = atomicAdd(&visible_count, 1u);
+    visible_draws[slot] = instance.draw;
+}
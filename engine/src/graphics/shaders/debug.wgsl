@@ -0,0 +1,97 @@
+// Debug render modes, see RenderMode. Drawn with the scene's pipeline layout,
+// the vertex stage matches shader.wgsl.
+struct CameraUniform {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+};
+
+struct CameraView {
+    index: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> cameras: array<CameraUniform>;
+
+@group(0) @binding(1)
+var<uniform> view: CameraView;
+
+struct ModelData {
+    model: mat4x4<f32>,
+    bone_offset: u32,
+    bone_count: u32,
+    _padding: vec2<u32>,
+};
+
+@group(1) @binding(0)
+var<storage, read> models: array<ModelData>;
+
+@group(1) @binding(1)
+var<storage, read> bones: array<mat4x4<f32>>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) joint_indices: vec4<u32>,
+    @location(3) joint_weights: vec4<f32>,
+    @builtin(instance_index) instance_idx: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    // Distance in front of the camera.
+    @location(1) view_depth: f32,
+};
+
+// Depth mode fades to black at this distance.
+const DEPTH_RANGE: f32 = 50.0;
+
+fn skin_matrix(model_data: ModelData, joint_indices: vec4<u32>, joint_weights: vec4<f32>) -> mat4x4<f32> {
+    let last = model_data.bone_count - 1u;
+    return bones[model_data.bone_offset + min(joint_indices.x, last)] * joint_weights.x
+        + bones[model_data.bone_offset + min(joint_indices.y, last)] * joint_weights.y
+        + bones[model_data.bone_offset + min(joint_indices.z, last)] * joint_weights.z
+        + bones[model_data.bone_offset + min(joint_indices.w, last)] * joint_weights.w;
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let model_data = models[in.instance_idx];
+    var model_matrix = model_data.model;
+    if model_data.bone_count > 0u {
+        model_matrix = model_matrix * skin_matrix(model_data, in.joint_indices, in.joint_weights);
+    }
+    let camera = cameras[view.index];
+    let view_pos = camera.view * model_matrix * vec4(in.position, 1.0);
+
+    var out: VertexOutput;
+    out.position = camera.proj * view_pos;
+    out.normal = (model_matrix * vec4(in.normal, 0.0)).xyz;
+    out.view_depth = -view_pos.z;
+    return out;
+}
+
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.1, 1.0, 0.3, 1.0);
+}
+
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(normalize(in.normal) * 0.5 + 0.5, 1.0);
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+    let brightness = 1.0 - clamp(in.view_depth / DEPTH_RANGE, 0.0, 1.0);
+    return vec4<f32>(vec3<f32>(brightness), 1.0);
+}
+
+// Blended additively, ten overlapping fragments saturate red.
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.1, 0.04, 0.02, 1.0);
+}
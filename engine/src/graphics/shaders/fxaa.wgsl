@@ -0,0 +1,63 @@
+// Drawn with the fullscreen.wgsl vertex stage.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+const FXAA_SPAN_MAX: f32 = 8.0;
+const FXAA_REDUCE_MUL: f32 = 0.125;
+const FXAA_REDUCE_MIN: f32 = 0.0078125;
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+
+    let color_m = textureSample(source_texture, source_sampler, in.uv);
+    let luma_nw = luma(textureSample(source_texture, source_sampler, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = luma(textureSample(source_texture, source_sampler, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = luma(textureSample(source_texture, source_sampler, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = luma(textureSample(source_texture, source_sampler, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = luma(color_m.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blur along the edge, i.e. perpendicular to the luma gradient.
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN,
+    );
+    let inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(
+        direction * inverse_direction_min,
+        vec2<f32>(-FXAA_SPAN_MAX),
+        vec2<f32>(FXAA_SPAN_MAX),
+    ) * texel;
+
+    let color_a = 0.5 * (
+        textureSample(source_texture, source_sampler, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + textureSample(source_texture, source_sampler, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        textureSample(source_texture, source_sampler, in.uv - direction * 0.5).rgb
+        + textureSample(source_texture, source_sampler, in.uv + direction * 0.5).rgb
+    );
+
+    // The wider blend picked up colors from across the edge, fall back to the narrow one.
+    let luma_b = luma(color_b);
+    let color = select(color_b, color_a, luma_b < luma_min || luma_b > luma_max);
+    return vec4<f32>(color, color_m.a);
+}
@@ -8,10 +8,8 @@ pub fn load_shader(device: &Device, shader_name: String) -> ShaderModule {
         Err(err) => panic!("failed to load file, {}", err),
     };
 
-    let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+    device.create_shader_module(ShaderModuleDescriptor {
         label: Some("hello triangle"),
         source: ShaderSource::Wgsl(Cow::Borrowed(&shader)),
-    });
-
-    shader_module
+    })
 }
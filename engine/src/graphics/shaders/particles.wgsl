@@ -0,0 +1,63 @@
+struct CameraUniform {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+};
+
+struct CameraView {
+    index: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> cameras: array<CameraUniform>;
+
+@group(0) @binding(1)
+var<uniform> view: CameraView;
+
+// One per particle, stepped per instance.
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+    @builtin(vertex_index) vertex_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+// Two triangles, the quad needs no vertex buffer of its own.
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, 0.5),
+);
+
+// Expanded in view space so every quad faces the camera.
+@vertex
+fn vs_main(in: ParticleInput) -> VertexOutput {
+    let camera = cameras[view.index];
+    let corner = CORNERS[in.vertex_index % 6u];
+    var view_pos = camera.view * vec4<f32>(in.position, 1.0);
+    view_pos = vec4<f32>(view_pos.xy + corner * in.size, view_pos.zw);
+
+    var out: VertexOutput;
+    out.clip_position = camera.proj * view_pos;
+    out.corner = corner;
+    out.color = in.color;
+    return out;
+}
+
+// Soft round particles, fading out toward the quad's edge.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.25, 0.5, length(in.corner));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
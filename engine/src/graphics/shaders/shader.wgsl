@@ -13,17 +13,71 @@ struct IndirectDraw {
     _padding: vec2<u32>,
 };
 
+struct DirectionalLight {
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    _padding: f32,
+};
+
+const MAX_LIGHTS: u32 = 16u;
+
+// Scalar padding, a vec3<u32> would be 16 byte aligned and push the
+// lights array past where LightUniform puts it.
+struct LightUniform {
+    light_count: u32,
+    shadows_enabled: u32,
+    _padding0: u32,
+    _padding1: u32,
+    light_view_proj: mat4x4<f32>,
+    lights: array<DirectionalLight, MAX_LIGHTS>,
+};
+
+// Every camera uploaded this frame, the pass picks one through the dynamic
+// offset of `view`.
+struct CameraView {
+    index: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
 @group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+var<storage, read> cameras: array<CameraUniform>;
+
+@group(0) @binding(1)
+var<uniform> view: CameraView;
+
+struct ModelData {
+    model: mat4x4<f32>,
+    bone_offset: u32,
+    bone_count: u32,
+    _padding: vec2<u32>,
+};
 
 @group(1) @binding(0)
-var<storage, read> models: array<mat4x4<f32>>;
+var<storage, read> models: array<ModelData>;
+
+@group(1) @binding(1)
+var<storage, read> bones: array<mat4x4<f32>>;
 
 @group(2) @binding(0)
 var<storage, read> draw_commands: array<IndirectDraw>;
 
+@group(3) @binding(0)
+var<uniform> light_data: LightUniform;
+
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d;
+
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) joint_indices: vec4<u32>,
+    @location(3) joint_weights: vec4<f32>,
     @builtin(instance_index) instance_idx: u32,
     // @builtin(draw_index) draw_idx: u32,
 };
@@ -31,22 +85,63 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+}
+
+fn skin_matrix(model_data: ModelData, joint_indices: vec4<u32>, joint_weights: vec4<f32>) -> mat4x4<f32> {
+    let last = model_data.bone_count - 1u;
+    return bones[model_data.bone_offset + min(joint_indices.x, last)] * joint_weights.x
+        + bones[model_data.bone_offset + min(joint_indices.y, last)] * joint_weights.y
+        + bones[model_data.bone_offset + min(joint_indices.z, last)] * joint_weights.z
+        + bones[model_data.bone_offset + min(joint_indices.w, last)] * joint_weights.w;
 }
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
-    let model_matrix = models[in.instance_idx];
+    let model_data = models[in.instance_idx];
+    var model_matrix = model_data.model;
+    if model_data.bone_count > 0u {
+        model_matrix = model_matrix * skin_matrix(model_data, in.joint_indices, in.joint_weights);
+    }
     let world_pos = model_matrix * vec4(in.position, 1.0);
+    let camera = cameras[view.index];
     let view_pos = camera.view * world_pos;
     let clip_pos = camera.proj * view_pos;
 
     var out: VertexOutput;
     out.position = clip_pos;
     out.color = vec3<f32>(f32(in.instance_idx) * 0.1, 0.75, 0.75);
+    out.normal = (model_matrix * vec4(in.normal, 0.0)).xyz;
+    out.world_position = world_pos.xyz;
     return out;
 }
 
+// 1.0 where the first light reaches the fragment, 0.0 where the shadow pass
+// found something closer to it. Outside the shadow map is lit.
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+    let light_pos = light_data.light_view_proj * vec4(world_position, 1.0);
+    let ndc = light_pos.xyz / light_pos.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let visibility = textureSampleCompareLevel(shadow_map, shadow_sampler, uv, ndc.z);
+    if light_data.shadows_enabled == 0u || any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    return visibility;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    let normal = normalize(in.normal);
+    let shadow = shadow_factor(in.world_position);
+    var lighting = vec3<f32>(0.1);
+    for (var i = 0u; i < min(light_data.light_count, MAX_LIGHTS); i++) {
+        let light = light_data.lights[i];
+        var diffuse = max(dot(normal, -light.direction), 0.0) * light.color * light.intensity;
+        if i == 0u {
+            diffuse *= shadow;
+        }
+        lighting += diffuse;
+    }
+    return vec4<f32>(in.color * lighting, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+struct DirectionalLight {
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    _padding: f32,
+};
+
+const MAX_LIGHTS: u32 = 16u;
+
+// Has to match LightUniform in shader.wgsl, the shadow pass binds the same
+// buffer.
+struct LightUniform {
+    light_count: u32,
+    shadows_enabled: u32,
+    _padding0: u32,
+    _padding1: u32,
+    light_view_proj: mat4x4<f32>,
+    lights: array<DirectionalLight, MAX_LIGHTS>,
+};
+
+@group(0) @binding(0)
+var<uniform> light_data: LightUniform;
+
+struct ModelData {
+    model: mat4x4<f32>,
+    bone_offset: u32,
+    bone_count: u32,
+    _padding: vec2<u32>,
+};
+
+@group(1) @binding(0)
+var<storage, read> models: array<ModelData>;
+
+@group(1) @binding(1)
+var<storage, read> bones: array<mat4x4<f32>>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) joint_indices: vec4<u32>,
+    @location(3) joint_weights: vec4<f32>,
+    @builtin(instance_index) instance_idx: u32,
+};
+
+fn skin_matrix(model_data: ModelData, joint_indices: vec4<u32>, joint_weights: vec4<f32>) -> mat4x4<f32> {
+    let last = model_data.bone_count - 1u;
+    return bones[model_data.bone_offset + min(joint_indices.x, last)] * joint_weights.x
+        + bones[model_data.bone_offset + min(joint_indices.y, last)] * joint_weights.y
+        + bones[model_data.bone_offset + min(joint_indices.z, last)] * joint_weights.z
+        + bones[model_data.bone_offset + min(joint_indices.w, last)] * joint_weights.w;
+}
+
+// Depth only, there is no fragment stage.
+@vertex
+fn vs_main(in: VertexInput) -> @builtin(position) vec4<f32> {
+    let model_data = models[in.instance_idx];
+    var model_matrix = model_data.model;
+    if model_data.bone_count > 0u {
+        model_matrix = model_matrix * skin_matrix(model_data, in.joint_indices, in.joint_weights);
+    }
+    return light_data.light_view_proj * model_matrix * vec4(in.position, 1.0);
+}
@@ -0,0 +1,265 @@
+use glam::{Mat4, Vec3};
+use log::error;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingType,
+    BufferBindingType, BufferSize, CommandEncoder, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, Extent3d, FilterMode, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor,
+    ShaderModule, ShaderStages, StencilState, StoreOp, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, VertexBufferLayout, VertexState,
+};
+
+use crate::{
+    graphics::{
+        buffers::{
+            BufferInterface, GpuRingBuffer, RING_BUFFER_DEPTH,
+            bindgroups::create_bind_group,
+            layouts::create_bind_group_layout,
+            submissions::{IndirectDraw, LightUniform, ModelUniform},
+        },
+        draw_list::DrawList,
+        mesh::mesh_allocator::MeshAllocator,
+    },
+    utils::{RegisterKey, Registry},
+};
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+// Half the width of the square around the camera the shadow map covers,
+// anything outside it is lit.
+pub const SHADOW_RADIUS: f32 = 40.0;
+
+// An orthographic projection looking along `direction` at a square of
+// `radius` around `focus`, deep enough for casters up to `radius` behind it.
+pub fn light_view_projection(direction: Vec3, focus: Vec3, radius: f32) -> Mat4 {
+    let direction = direction.try_normalize().unwrap_or(Vec3::NEG_Y);
+    // look_to needs an up that isn't parallel to the view direction.
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let eye = focus - direction * radius * 2.0;
+    let view = Mat4::look_to_rh(eye, direction, up);
+    let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+    projection * view
+}
+
+// Depth of the scene as seen from the first directional light, rendered
+// before the opaque pass and sampled by it through the light bind group.
+#[derive(Debug)]
+pub struct ShadowMap {
+    pub texture: Texture,
+    pub view: TextureView,
+    // Compares against the stored depth, filtered for softer edges.
+    pub sampler: Sampler,
+    // Just the light uniform, the shadow map itself can't be bound while it
+    // is being rendered into.
+    light_layout: BindGroupLayout,
+    light_bind_groups: Vec<BindGroup>,
+    pipeline: Option<RenderPipeline>,
+}
+
+impl ShadowMap {
+    pub fn new(device: &Device, size: u32) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("shadow map"),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("shadow map sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let light_layout = create_bind_group_layout(
+            "shadow_light_bind_group_layout",
+            device,
+            &vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(size_of::<LightUniform>() as u64),
+                },
+                count: None,
+            }],
+        );
+
+        Self {
+            texture,
+            view,
+            sampler,
+            light_layout,
+            light_bind_groups: Vec::new(),
+            pipeline: None,
+        }
+    }
+
+    // The pass reads the light_view_proj the opaque pass uses from the same
+    // light buffers, so it only has to be uploaded once.
+    pub fn bind_lights(&mut self, device: &Device, lights: &GpuRingBuffer<LightUniform>) {
+        self.light_bind_groups = (0..RING_BUFFER_DEPTH)
+            .map(|frame_index| {
+                create_bind_group(
+                    "shadow_light_bind_group",
+                    device,
+                    &self.light_layout,
+                    &vec![BindGroupEntry {
+                        binding: 0,
+                        resource: lights.get_read(frame_index).buffer.as_entire_binding(),
+                    }],
+                )
+            })
+            .collect();
+    }
+
+    // Depth only, `shader` has a vs_main taking the same vertex layout as the
+    // scene and no fragment stage.
+    pub fn create_pipeline(
+        &mut self,
+        device: &Device,
+        shader: &ShaderModule,
+        model_layout: &BindGroupLayout,
+        vertex_layout: VertexBufferLayout,
+    ) {
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("shadow pipeline layout"),
+            bind_group_layouts: &[&self.light_layout, model_layout],
+            push_constant_ranges: &[],
+        });
+        self.pipeline = Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("shadow pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[vertex_layout],
+            },
+            fragment: None,
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: StencilState::default(),
+                // Pushes the stored depth back so surfaces don't shadow themselves.
+                bias: DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+    }
+
+    // Replays the frame's indirect draws into the shadow map. `casters` is
+    // indexed by PipelineKey, draws of pipelines that are false or missing,
+    // e.g. a skybox, are skipped.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        gpu_buffer_registry: &Registry<Box<dyn BufferInterface>>,
+        frame_index: usize,
+        mesh_allocator: &MeshAllocator,
+        draw_list: &DrawList,
+        casters: &[bool],
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("shadow pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &self.view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let (Some(pipeline), Some(light_bind_group)) = (
+            self.pipeline.as_ref(),
+            self.light_bind_groups.get(frame_index % RING_BUFFER_DEPTH),
+        ) else {
+            return;
+        };
+        let Some(models) = gpu_buffer_registry
+            .get(&RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>(
+                "model_gpu_uniform_triple",
+            ))
+            .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<ModelUniform>>())
+        else {
+            return;
+        };
+        let Some(indirect_draws) = gpu_buffer_registry
+            .get(&RegisterKey::from_label::<GpuRingBuffer<IndirectDraw>>(
+                "indirect_draw_buffer",
+            ))
+            .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<IndirectDraw>>())
+        else {
+            return;
+        };
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, Some(light_bind_group), &[]);
+        render_pass.set_bind_group(1, models.get_read(frame_index).bind_group.as_ref(), &[]);
+
+        let indirect_entry = indirect_draws.get_read(frame_index);
+        let mut bound_stream = None;
+        for (i, command) in draw_list
+            .commands()
+            .iter()
+            .enumerate()
+            .take(indirect_entry.element_count as usize)
+        {
+            if !casters
+                .get(command.pipeline.0 as usize)
+                .copied()
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let stream = command.stream;
+            if bound_stream != Some(stream) {
+                let (Some(vertex_buffer), Some(index_buffer)) = (
+                    mesh_allocator.get_current_vertex_buffer(stream, frame_index),
+                    mesh_allocator.get_current_index_buffer(stream, frame_index),
+                ) else {
+                    error!("shadow draw {i} uses missing mesh stream {stream}");
+                    continue;
+                };
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                bound_stream = Some(stream);
+            }
+            render_pass.draw_indexed_indirect(
+                &indirect_entry.buffer,
+                i as u64 * size_of::<IndirectDraw>() as u64,
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use crate::graphics::buffers::submissions::IndirectDraw;
+
+// Content counts for the last rendered frame, taken from the indirect draw
+// commands that were uploaded for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub triangles: u64,
+}
+
+impl FrameStats {
+    pub fn from_draws(draws: &[IndirectDraw]) -> Self {
+        draws.iter().fold(Self::default(), |stats, draw| Self {
+            draw_calls: stats.draw_calls + 1,
+            instances: stats.instances + draw.instance_count,
+            triangles: stats.triangles + (draw.index_count / 3) as u64 * draw.instance_count as u64,
+        })
+    }
+}
+
+// Averages over a fixed number of rendered frames, for running the engine as
+// a reproducible benchmark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkReport {
+    pub frames: u32,
+    pub average_cpu_ms: f64,
+    // None without timestamp queries. The GPU time lags a few frames, so it
+    // may be averaged over fewer frames than the CPU time.
+    pub average_gpu_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameBenchmark {
+    frame_limit: u32,
+    frames: u32,
+    cpu_total: Duration,
+    gpu_total_ms: f64,
+    gpu_frames: u32,
+}
+
+impl FrameBenchmark {
+    pub fn new(frame_limit: u32) -> Self {
+        Self {
+            frame_limit,
+            frames: 0,
+            cpu_total: Duration::ZERO,
+            gpu_total_ms: 0.0,
+            gpu_frames: 0,
+        }
+    }
+
+    // Returns true once the frame limit is reached, later frames are ignored.
+    pub fn record(&mut self, cpu_time: Duration, gpu_time_ms: Option<f32>) -> bool {
+        if self.is_finished() {
+            return true;
+        }
+        self.frames += 1;
+        self.cpu_total += cpu_time;
+        if let Some(gpu_time_ms) = gpu_time_ms {
+            self.gpu_total_ms += gpu_time_ms as f64;
+            self.gpu_frames += 1;
+        }
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames >= self.frame_limit
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn report(&self) -> BenchmarkReport {
+        BenchmarkReport {
+            frames: self.frames,
+            average_cpu_ms: if self.frames == 0 {
+                0.0
+            } else {
+                self.cpu_total.as_secs_f64() * 1000.0 / self.frames as f64
+            },
+            average_gpu_ms: (self.gpu_frames > 0)
+                .then(|| self.gpu_total_ms / self.gpu_frames as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(index_count: u32, instance_count: u32) -> IndirectDraw {
+        IndirectDraw {
+            index_count,
+            instance_count,
+            first_index: 0,
+            base_vertex: 0,
+            first_instance: 0,
+        }
+    }
+
+    #[test]
+    fn totals_count_every_instance() {
+        let stats = FrameStats::from_draws(&[draw(36, 1), draw(6, 10), draw(3, 0)]);
+        assert_eq!(
+            stats,
+            FrameStats {
+                draw_calls: 3,
+                instances: 11,
+                triangles: 12 + 2 * 10,
+            }
+        );
+        assert_eq!(FrameStats::from_draws(&[]), FrameStats::default());
+    }
+}
@@ -0,0 +1,148 @@
+use wgpu::{
+    AddressMode, CommandEncoder, Device, Extent3d, FilterMode, Sampler, SamplerDescriptor,
+    ShaderModule, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor,
+};
+
+use crate::graphics::post_process::PostProcessPass;
+
+// wgpu caps anisotropy_clamp at 16 on every backend that supports it at all.
+pub const MAX_ANISOTROPY: u16 = 16;
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
+}
+
+// Trilinear filtering, plus anisotropic when `anisotropy` is above 1. The
+// requested value is clamped to `max_anisotropy`, see GPUContext::max_anisotropy.
+pub fn sampler_descriptor(
+    label: &str,
+    anisotropy: u16,
+    max_anisotropy: u16,
+) -> SamplerDescriptor<'_> {
+    SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        address_mode_w: AddressMode::Repeat,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Linear,
+        anisotropy_clamp: anisotropy.clamp(1, max_anisotropy.clamp(1, MAX_ANISOTROPY)),
+        ..Default::default()
+    }
+}
+
+pub fn create_sampler(
+    device: &Device,
+    label: &str,
+    anisotropy: u16,
+    max_anisotropy: u16,
+) -> Sampler {
+    device.create_sampler(&sampler_descriptor(label, anisotropy, max_anisotropy))
+}
+
+// A sampled 2D texture with a full mip chain that generate_mipmaps can render into.
+pub fn create_mipmapped_texture(
+    device: &Device,
+    label: &str,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: mip_level_count(width, height),
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+// Fills in mip levels for textures that were loaded without them by blitting
+// each level into the next one with linear filtering.
+pub struct MipmapGenerator {
+    blit: PostProcessPass,
+    format: TextureFormat,
+}
+
+impl MipmapGenerator {
+    // `shader` is the blit shader, one generator is needed per texture format.
+    pub fn new(device: &Device, shader: &ShaderModule, format: TextureFormat) -> Self {
+        Self {
+            blit: PostProcessPass::new(device, "mipmap generation", shader, format),
+            format,
+        }
+    }
+
+    pub fn generate(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) -> Result<(), String> {
+        if texture.format() != self.format {
+            return Err(format!(
+                "mipmap generator for {:?} can't write {:?} textures",
+                self.format,
+                texture.format()
+            ));
+        }
+        let required_usage = TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT;
+        if !texture.usage().contains(required_usage) {
+            return Err(format!(
+                "generating mipmaps needs {required_usage:?}, texture has {:?}",
+                texture.usage()
+            ));
+        }
+
+        let level_view = |level| {
+            texture.create_view(&TextureViewDescriptor {
+                label: Some("mipmap level"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        for level in 1..texture.mip_level_count() {
+            self.blit.set_source(device, &level_view(level - 1));
+            self.blit.encode(encoder, &level_view(level));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_is_clamped_to_the_adapter_maximum() {
+        let clamp =
+            |max_anisotropy| sampler_descriptor("test", 16, max_anisotropy).anisotropy_clamp;
+        assert_eq!(clamp(1), 1);
+        assert_eq!(clamp(4), 4);
+        assert_eq!(clamp(16), 16);
+        // Requests below one still give a valid sampler.
+        assert_eq!(sampler_descriptor("test", 0, 16).anisotropy_clamp, 1);
+    }
+
+    #[test]
+    fn mip_chain_goes_down_to_one_texel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(256, 1), 9);
+        assert_eq!(mip_level_count(300, 17), 9);
+        assert_eq!(mip_level_count(0, 0), 1);
+    }
+}
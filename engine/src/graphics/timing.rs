@@ -0,0 +1,176 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU8, Ordering},
+};
+
+use wgpu::{
+    Buffer, BufferUsages, CommandEncoder, Device, Features, MapMode, PollType, QUERY_SIZE,
+    QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites,
+};
+
+use crate::graphics::buffers::{RING_BUFFER_DEPTH, create_buffer};
+
+const TIMESTAMP_COUNT: u32 = 2;
+
+const MAP_PENDING: u8 = 0;
+const MAP_OK: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+#[derive(PartialEq)]
+enum ReadbackState {
+    Idle,
+    Copied,
+    Mapping,
+}
+
+struct ReadbackSlot {
+    buffer: Buffer,
+    state: ReadbackState,
+    map_status: Arc<AtomicU8>,
+}
+
+// Writes a timestamp at the start and end of the main pass. Each frame's
+// pair is copied into its own ring slot and mapped once the GPU is done, so
+// reading a result never stalls; the reported time lags a few frames.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_slots: Vec<ReadbackSlot>,
+    period_ns: f32,
+    last_frame_time_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    // Returns None when the device wasn't created with TIMESTAMP_QUERY.
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("gpu frame timer"),
+            ty: QueryType::Timestamp,
+            count: TIMESTAMP_COUNT,
+        });
+
+        let resolve_size = Self::resolve_buffer_size();
+        let resolve_buffer = create_buffer(
+            device,
+            "gpu frame timer resolve",
+            resolve_size,
+            vec![BufferUsages::QUERY_RESOLVE, BufferUsages::COPY_SRC],
+            false,
+        );
+
+        let readback_slots = (0..RING_BUFFER_DEPTH)
+            .map(|_| ReadbackSlot {
+                buffer: create_buffer(
+                    device,
+                    "gpu frame timer readback",
+                    resolve_size,
+                    vec![BufferUsages::COPY_DST, BufferUsages::MAP_READ],
+                    false,
+                ),
+                state: ReadbackState::Idle,
+                map_status: Arc::new(AtomicU8::new(MAP_PENDING)),
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_slots,
+            period_ns: queue.get_timestamp_period(),
+            last_frame_time_ms: None,
+        })
+    }
+
+    pub fn resolve_buffer_size() -> u64 {
+        TIMESTAMP_COUNT as u64 * QUERY_SIZE as u64
+    }
+
+    pub fn query_set(&self) -> &QuerySet {
+        &self.query_set
+    }
+
+    pub fn resolve_buffer(&self) -> &Buffer {
+        &self.resolve_buffer
+    }
+
+    pub fn timestamp_writes(&self) -> RenderPassTimestampWrites<'_> {
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    // Skips the copy when the slot from RING_BUFFER_DEPTH frames ago still
+    // hasn't been read back.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder, frame_index: usize) {
+        let slot = &mut self.readback_slots[frame_index % RING_BUFFER_DEPTH];
+        if slot.state != ReadbackState::Idle {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..TIMESTAMP_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &slot.buffer,
+            0,
+            Self::resolve_buffer_size(),
+        );
+        slot.state = ReadbackState::Copied;
+    }
+
+    // Has to run after the encoder from resolve() was submitted, a buffer
+    // with a pending map can't be used by a submission.
+    pub fn map_resolved(&mut self) {
+        for slot in &mut self.readback_slots {
+            if slot.state != ReadbackState::Copied {
+                continue;
+            }
+            slot.state = ReadbackState::Mapping;
+            slot.map_status.store(MAP_PENDING, Ordering::Release);
+            let map_status = slot.map_status.clone();
+            slot.buffer
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    let status = if result.is_ok() { MAP_OK } else { MAP_FAILED };
+                    map_status.store(status, Ordering::Release);
+                });
+        }
+    }
+
+    // Collects any finished readbacks, returning the newest GPU frame time.
+    pub fn collect(&mut self, device: &Device) -> Option<f32> {
+        let _ = device.poll(PollType::Poll);
+        for slot in &mut self.readback_slots {
+            if slot.state != ReadbackState::Mapping {
+                continue;
+            }
+            match slot.map_status.load(Ordering::Acquire) {
+                MAP_OK => {}
+                MAP_FAILED => {
+                    slot.state = ReadbackState::Idle;
+                    continue;
+                }
+                _ => continue,
+            }
+            let timestamps: Vec<u64> = {
+                let view = slot.buffer.slice(..).get_mapped_range();
+                bytemuck::cast_slice(&view).to_vec()
+            };
+            slot.buffer.unmap();
+            slot.state = ReadbackState::Idle;
+
+            let elapsed_ticks = timestamps[1].wrapping_sub(timestamps[0]);
+            self.last_frame_time_ms = Some(elapsed_ticks as f32 * self.period_ns / 1_000_000.0);
+        }
+        self.last_frame_time_ms
+    }
+
+    pub fn last_frame_time_ms(&self) -> Option<f32> {
+        self.last_frame_time_ms
+    }
+}
@@ -1,13 +1,14 @@
 use std::sync::Arc;
 
-use log::info;
+use ecs::entities::EntityId;
+use log::{info, warn};
 use wgpu::{
-    Color, Device, Extent3d, Surface, SurfaceConfiguration, Texture, TextureFormat, TextureUsages,
-    TextureView, wgt::TextureDescriptor,
+    Color, Device, Extent3d, Instance, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture,
+    Texture, TextureFormat, TextureUsages, TextureView, wgt::TextureDescriptor,
 };
 use winit::window::Window;
 
-use crate::graphics::GPUContext;
+use crate::graphics::{GPUContext, buffers::RING_BUFFER_DEPTH, render_target::RenderTarget};
 
 #[derive(Debug)]
 pub struct Viewport {
@@ -15,58 +16,285 @@ pub struct Viewport {
     pub config: SurfaceConfiguration,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum SurfaceErrorAction {
+    Reconfigure,
+    SkipFrame,
+    Fatal,
+}
+
+// Surfaces list their preferred format first, which isn't always sRGB. The
+// shaders write linear color, so without an sRGB target the output is too dark.
+pub fn select_surface_format(formats: &[TextureFormat]) -> Option<TextureFormat> {
+    if let Some(format) = formats.iter().find(|format| format.is_srgb()) {
+        return Some(*format);
+    }
+    let format = formats.first()?;
+    warn!("surface has no srgb format, using {format:?}, colors will be too dark");
+    Some(*format)
+}
+
+// Lost and Outdated happen routinely on resize, minimize or a GPU reset and
+// go away once the surface is configured again.
+pub fn handle_surface_error(error: &SurfaceError) -> SurfaceErrorAction {
+    match error {
+        SurfaceError::Lost | SurfaceError::Outdated => SurfaceErrorAction::Reconfigure,
+        SurfaceError::Timeout | SurfaceError::Other => SurfaceErrorAction::SkipFrame,
+        SurfaceError::OutOfMemory => SurfaceErrorAction::Fatal,
+    }
+}
+
+impl Viewport {
+    // Returns Ok(None) when this frame should be skipped, and only errors
+    // when the surface can't be recovered.
+    pub fn acquire_texture(&self, device: &Device) -> Result<Option<SurfaceTexture>, String> {
+        let surface = self.description.target.surface();
+        let error = match surface.get_current_texture() {
+            Ok(texture) => return Ok(Some(texture)),
+            Err(error) => error,
+        };
+
+        match handle_surface_error(&error) {
+            SurfaceErrorAction::Reconfigure => {
+                // A minimized window reports a zero size, which can't be configured.
+                if self.config.width == 0 || self.config.height == 0 {
+                    return Ok(None);
+                }
+                warn!("surface {error}, reconfiguring");
+                surface.configure(device, &self.config);
+                match surface.get_current_texture() {
+                    Ok(texture) => Ok(Some(texture)),
+                    Err(error) if handle_surface_error(&error) == SurfaceErrorAction::Fatal => {
+                        Err(format!("failed to acquire surface texture {error}"))
+                    }
+                    Err(error) => {
+                        warn!("surface {error} after reconfiguring, skipping frame");
+                        Ok(None)
+                    }
+                }
+            }
+            SurfaceErrorAction::SkipFrame => {
+                warn!("surface {error}, skipping frame");
+                Ok(None)
+            }
+            SurfaceErrorAction::Fatal => Err(format!("failed to acquire surface texture {error}")),
+        }
+    }
+
+    // Reconfigures the surface for a new window size. Returns false without
+    // touching anything when the size is the one already configured, so
+    // callers only rebuild render targets on a real change. A zero size,
+    // e.g. a minimized window, is recorded but can't be configured.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) -> bool {
+        if (self.config.width, self.config.height) == (width, height) {
+            return false;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        if width > 0 && height > 0 {
+            self.description
+                .target
+                .surface()
+                .configure(device, &self.config);
+        }
+        true
+    }
+}
+
 #[derive(Debug)]
 pub struct DepthResources {
     pub texture: Texture,
     pub view: TextureView,
     pub format: TextureFormat,
+    pub sample_count: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DepthResources {
+    pub fn new(device: &Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let format = TextureFormat::Depth32Float;
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("depth texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+
+        Self {
+            texture,
+            view,
+            format,
+            sample_count,
+            width,
+            height,
+        }
+    }
+
+    // Keeps the existing texture when it already has this size and sample
+    // count. Returns whether a new one was created.
+    pub fn ensure(
+        depth: &mut Option<Self>,
+        device: &Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> bool {
+        if let Some(existing) = depth
+            && (existing.width, existing.height, existing.sample_count)
+                == (width, height, sample_count)
+        {
+            return false;
+        }
+        *depth = Some(Self::new(device, width, height, sample_count));
+        true
+    }
+}
+
+// A surface together with the window it presents to. The surface is created
+// from a clone of the window's Arc and keeps it alive itself, so it is
+// 'static without laundering the lifetime. The fields are private so the two
+// can't be separated, and the surface is declared first so it is dropped
+// before this struct's handle to the window.
+#[derive(Debug)]
+pub struct WindowSurface {
+    surface: Surface<'static>,
+    window: Arc<Window>,
+}
+
+impl WindowSurface {
+    pub fn new(instance: &Instance, window: Arc<Window>) -> Result<Self, String> {
+        let surface = instance
+            .create_surface(window.clone())
+            .map_err(|err| format!("failed to create surface {err}"))?;
+        Ok(Self { surface, window })
+    }
+
+    pub fn surface(&self) -> &Surface<'static> {
+        &self.surface
+    }
+
+    pub fn window(&self) -> &Arc<Window> {
+        &self.window
+    }
 }
 
 #[derive(Debug)]
 pub struct ViewportDescription {
-    pub window: Arc<Window>,
+    pub target: WindowSurface,
     pub background: Color,
-    pub surface: Surface<'static>,
+    // The camera entity this viewport draws from, None for the first active
+    // camera.
+    pub camera: Option<EntityId>,
     pub depth: Option<DepthResources>,
+    pub color: Option<RenderTarget>,
+    pub frame_latency: u32,
+    pub render_scale: f32,
+    // Depth and the pipelines drawing into it are built with this many
+    // samples, they have to match the color target's.
+    pub sample_count: u32,
 }
 
 impl ViewportDescription {
-    pub fn new(window: Arc<Window>, background: Color, surface: Surface<'static>) -> Self {
+    pub fn new(target: WindowSurface, background: Color) -> Self {
         Self {
-            window: window.clone(),
+            target,
             background,
-            surface,
+            camera: None,
             depth: None,
+            color: None,
+            frame_latency: RING_BUFFER_DEPTH as u32,
+            render_scale: 1.0,
+            sample_count: 1,
         }
     }
 
-    pub fn create_depth_resources(&mut self, device: &Device, config: &SurfaceConfiguration) {
-        let size = Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
-        };
+    pub fn with_camera(mut self, camera: EntityId) -> Self {
+        self.camera = Some(camera);
+        self
+    }
 
-        let format = TextureFormat::Depth32Float;
+    // The surface may queue up to `frame_latency` frames, each reading its own
+    // ring buffer slot, so it can never exceed the ring depth without the CPU
+    // overwriting a slot the GPU is still reading.
+    pub fn with_frame_latency(
+        mut self,
+        frame_latency: u32,
+        ring_depth: usize,
+    ) -> Result<Self, String> {
+        if frame_latency == 0 || frame_latency as usize > ring_depth {
+            return Err(format!(
+                "frame latency {frame_latency} must be between 1 and the ring buffer depth {ring_depth}"
+            ));
+        }
+        self.frame_latency = frame_latency;
+        Ok(self)
+    }
 
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some("depth texture"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
+    // The scene is drawn at render_scale times the surface size and the
+    // post process pass scales it back up to the swapchain.
+    pub fn with_render_scale(mut self, render_scale: f32) -> Result<Self, String> {
+        self.set_render_scale(render_scale)?;
+        Ok(self)
+    }
 
-        let view = texture.create_view(&Default::default());
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<(), String> {
+        if !(render_scale > 0.0 && render_scale <= 2.0) {
+            return Err(format!(
+                "render scale {render_scale} must be greater than 0 and at most 2"
+            ));
+        }
+        self.render_scale = render_scale;
+        Ok(())
+    }
 
-        self.depth = Some(DepthResources {
-            texture,
-            view,
-            format,
-        })
+    pub fn render_size(&self, config: &SurfaceConfiguration) -> (u32, u32) {
+        let scale = |length: u32| ((length as f32 * self.render_scale).round() as u32).max(1);
+        (scale(config.width), scale(config.height))
+    }
+
+    // Returns whether the depth texture was recreated.
+    pub fn create_depth_resources(
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+    ) -> bool {
+        let (width, height) = self.render_size(config);
+        DepthResources::ensure(&mut self.depth, device, width, height, sample_count)
+    }
+
+    // Offscreen target the scene is drawn into before post processing writes
+    // the swapchain image. Returns whether it was recreated, anything
+    // sampling it has to be pointed at the new one.
+    pub fn create_color_resources(
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
+    ) -> bool {
+        let (width, height) = self.render_size(config);
+        if let Some(color) = &self.color
+            && (color.width, color.height, color.format) == (width, height, config.format)
+        {
+            return false;
+        }
+        self.color = Some(RenderTarget::new(
+            device,
+            "scene color texture",
+            width,
+            height,
+            config.format,
+        ));
+        true
     }
 
     pub fn build_viewport(mut self, gpu_context: &Arc<GPUContext>) -> Viewport {
@@ -74,7 +302,7 @@ impl ViewportDescription {
         let adapter = &gpu_context.adapter;
         let device = &gpu_context.device;
         info!("getting size");
-        let size = self.window.as_ref().inner_size();
+        let size = self.target.window().inner_size();
         info!("checking size");
 
         if size.width == 0 || size.height == 0 {
@@ -83,7 +311,9 @@ impl ViewportDescription {
             info!("size is not zero");
         }
         info!("getting surface config");
-        let format = self.surface.get_capabilities(adapter).formats[0];
+        let format =
+            select_surface_format(&self.target.surface().get_capabilities(adapter).formats)
+                .expect("surface should support at least one format");
         let config = SurfaceConfiguration {
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
             format,
@@ -92,11 +322,12 @@ impl ViewportDescription {
             present_mode: wgpu::PresentMode::Fifo,
             view_formats: vec![],
             usage: TextureUsages::RENDER_ATTACHMENT,
-            desired_maximum_frame_latency: 3,
+            desired_maximum_frame_latency: self.frame_latency,
         };
         info!("configuring surface");
-        self.surface.configure(device, &config);
-        self.create_depth_resources(device, &config);
+        self.target.surface().configure(device, &config);
+        self.create_depth_resources(device, &config, self.sample_count);
+        self.create_color_resources(device, &config);
         info!("finished settingup viewport");
         Viewport {
             description: self,
@@ -104,3 +335,27 @@ impl ViewportDescription {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_format_is_preferred_over_a_linear_first_choice() {
+        let formats = [
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgba8Unorm,
+            TextureFormat::Bgra8UnormSrgb,
+            TextureFormat::Rgba8UnormSrgb,
+        ];
+        assert_eq!(
+            select_surface_format(&formats),
+            Some(TextureFormat::Bgra8UnormSrgb)
+        );
+        assert_eq!(
+            select_surface_format(&formats[..2]),
+            Some(TextureFormat::Bgra8Unorm)
+        );
+        assert_eq!(select_surface_format(&[]), None);
+    }
+}
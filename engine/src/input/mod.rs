@@ -6,6 +6,7 @@ pub struct InputState {
     pub key_d: bool,
     pub key_space: bool,
     pub key_ctrl: bool,
+    pub key_shift: bool,
     pub mouse_delta_x: f32,
     pub mouse_delta_y: f32,
 }
@@ -19,6 +20,7 @@ impl Default for InputState {
             key_d: false,
             key_space: false,
             key_ctrl: false,
+            key_shift: false,
             mouse_delta_x: 0.0,
             mouse_delta_y: 0.0,
         }
@@ -1,7 +1,6 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use log::{debug, error, info};
 use std::{
-    mem::transmute,
     process,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
@@ -9,45 +8,88 @@ use std::{
 #[cfg(feature = "tracy")]
 use tracy_client::{plot, span};
 use wgpu::{
-    BindGroupLayout, Color, DepthBiasState, DepthStencilState, FragmentState, Instance,
-    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModule, StencilState, Surface, VertexAttribute,
-    VertexBufferLayout, VertexFormat, VertexState, util::StagingBelt,
+    Backends, BindGroup, BindGroupLayout, BlendState, ColorTargetState, ColorWrites, Device,
+    FragmentState, Instance, InstanceDescriptor, MultisampleState, PipelineLayoutDescriptor,
+    PollType, PolygonMode, PrimitiveState, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    ShaderModule, SurfaceConfiguration, TextureFormat, VertexState, util::StagingBelt,
 };
 use winit::{
     application::ApplicationHandler,
-    event::ElementState,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowAttributes},
 };
 
 use crate::{
-    r#async::FrameIndex,
+    assets::AssetServer,
+    r#async::RingIndices,
+    audio::AudioEngine,
+    config::EngineConfig,
     graphics::{
         buffers::{
-            BufferInterface,
-            submissions::{CameraUniform, IndirectDraw, ModelUniform},
+            BufferInterface, GpuRingBuffer,
+            submissions::{
+                CameraUniform, IndirectDraw, LightUniform, MAX_INDIRECT_DRAWS, ModelUniform,
+                camera_view_offset,
+            },
+        },
+        camera_matrices,
+        culling::GpuCuller,
+        debug_text::{DEBUG_GLYPH_CAPACITY, DebugOverlay, debug_overlay_text},
+        draw_list::DrawOrder,
+        frame_encoder::FrameEncoder,
+        gpu_device::GpuDevice,
+        mesh::{
+            DEFAULT_VERTEX_LAYOUT, Vertex, VertexLayout, VertexLayoutRegistry,
+            mesh_allocator::{DEFAULT_MESH_STREAM, MeshAllocator},
         },
-        mesh::{Vertex, mesh_allocator::MeshAllocator},
-        upload_camera_data, upload_indirect_draw_commands,
+        particles::{PARTICLE_CAPACITY, ParticleRenderer},
+        passes::{DepthMode, PassConfig},
+        render_mode::RenderMode,
+        shadows::{SHADOW_MAP_SIZE, ShadowMap},
+        upload_bone_palette, upload_camera_data, upload_cull_instances,
+        upload_indirect_draw_commands, upload_light_data, viewport_camera_view,
     },
-    utils::{FPSCounter, RegisterKey, Registry, ThreadPool},
+    utils::{FPSCounter, FixedTimestep, RegisterKey, Registry, SimScheduler, ThreadPool},
+    windowing::{GrabOutcome, grab_cursor},
 };
 use ecs::{
     World,
-    commands::IndirectDrawCommand,
-    components::{self, Camera, FpsCamera, Position},
+    components::{
+        self, Bounds, Camera, DirectionalLight, FpsCamera, PipelineKey, PlaySound, Position,
+    },
+    entities::EntityId,
+    input::{Button, InputQueue, MouseMode},
+    math::{Aabb, Ray},
+    meshes::{MeshId, MeshManager},
+    resources::SystemStats,
+    rng::Rng,
+    snapshot::SnapshotBuffer,
+    system_params::IntoSystem,
 };
 use graphics::{
     GPUContext, init_render_pass,
+    post_process::PostProcessPass,
+    render_graph::RenderGraph,
     shaders::load_shader,
-    viewports::{Viewport, ViewportDescription},
+    stats::{BenchmarkReport, FrameBenchmark, FrameStats},
+    textures::{self, MipmapGenerator},
+    timing::GpuTimer,
+    viewports::{Viewport, ViewportDescription, WindowSurface},
 };
 
-pub(crate) mod r#async;
+pub mod assets;
+pub mod r#async;
+pub mod audio;
+pub mod config;
 pub mod graphics;
 pub mod input;
 pub mod utils;
+pub mod windowing;
+
+// How often the event loop checks back on a sim job that is still running.
+const SIM_POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 //TODO move to the ecs
 pub const CUBE_VERTICES: [Vec3; 8] = [
@@ -80,67 +122,236 @@ pub const CUBE_INDICES: [u32; 36] = [
     3, 2, 6, 6, 7, 3, // Top
     0, 1, 5, 5, 4, 0, // Bottom
 ];
+
+// Each face's corners in the order the face first uses them.
+const CUBE_FACE_UVS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+// Splits the shared cube corners per face so each face gets its own normal.
+pub fn cube_mesh() -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for face in CUBE_INDICES.chunks(6) {
+        let [a, b, c] = [face[0], face[1], face[2]].map(|i| CUBE_VERTICES[i as usize]);
+        let mut normal = (b - a).cross(c - a).normalize();
+        if normal.dot(a + b + c) < 0.0 {
+            normal = -normal;
+        }
+
+        let base = vertices.len() as u32;
+        let mut corners: Vec<u32> = Vec::new();
+        for &index in face {
+            let corner = match corners.iter().position(|&corner| corner == index) {
+                Some(corner) => corner,
+                None => {
+                    corners.push(index);
+                    corners.len() - 1
+                }
+            };
+            indices.push(base + corner as u32);
+        }
+        vertices.extend(corners.iter().zip(CUBE_FACE_UVS).map(|(&i, uv)| Vertex {
+            position: CUBE_VERTICES[i as usize].to_array(),
+            normal: normal.to_array(),
+            joint_indices: [0; 4],
+            joint_weights: [0.0; 4],
+            uv,
+        }));
+    }
+    (vertices, indices)
+}
 //
 
 pub struct Engine {
     startup: bool,
     thread_pool: Option<ThreadPool>,
+    sim_scheduler: SimScheduler,
+    worker_pool: Option<ThreadPool>,
+    asset_server: Option<AssetServer>,
     world: Arc<Mutex<World>>,
+    render_snapshots: SnapshotBuffer,
     window: Option<Arc<Window>>,
     instance: Option<Arc<Instance>>,
     gpu_context: Option<Arc<GPUContext>>,
     viewports: Vec<Viewport>,
-    render_pipeline: Option<RenderPipeline>,
+    // Indexed by PipelineKey, the default pipeline comes first. Rebuilt from
+    // pipeline_shaders whenever the GPU is set up.
+    render_pipelines: Vec<RenderPipeline>,
+    pipeline_shaders: Vec<(String, DepthMode)>,
+    render_mode: RenderMode,
+    // One per RenderMode::debug_modes, built with the others.
+    render_mode_pipelines: Vec<RenderPipeline>,
+    post_process: Option<PostProcessPass>,
+    fxaa: Option<PostProcessPass>,
+    fxaa_enabled: bool,
+    debug_overlay: Option<DebugOverlay>,
+    particle_renderer: Option<ParticleRenderer>,
+    debug_overlay_enabled: bool,
+    entity_count: usize,
     fps_counter: Option<FPSCounter>,
-    sim_frame_index: FrameIndex,
-    frame_index: FrameIndex,
+    gpu_timer: Option<GpuTimer>,
+    ring: RingIndices,
     bind_group_layout_registry: Option<Registry<BindGroupLayout>>,
     staging_belt: Option<Arc<Mutex<StagingBelt>>>,
     gpu_buffer_registry: Option<Registry<Box<dyn BufferInterface>>>,
     mesh_allocator: Option<MeshAllocator>,
+    culler: Option<GpuCuller>,
+    shadow_map: Option<ShadowMap>,
+    // One per ring slot, only created with gpu_driven_draws.
+    indirect_draw_writable_bind_groups: Vec<BindGroup>,
+    vertex_layouts: VertexLayoutRegistry,
     input_state: ecs::input::InputState,
+    input_queue: InputQueue,
+    // The latest size from Resized events not yet applied.
+    pending_resize: Option<PhysicalSize<u32>>,
+    cursor_locked: bool,
+    // Set while the cursor is locked, None when it is free.
+    cursor_grab: Option<GrabOutcome>,
+    audio: AudioEngine,
+    frame_stats: FrameStats,
+    // Set when the config has a frame limit.
+    benchmark: Option<FrameBenchmark>,
     last_time: Instant,
-    accumulator: Duration,
-    delta_time: Duration,
+    timestep: FixedTimestep,
+    // Set while a requested redraw hasn't been delivered, e.g. the window is
+    // hidden. about_to_wait submits sim ticks itself then.
+    redraw_requested: bool,
+    // Set when setting up the window or GPU failed, the event loop exits then.
+    init_error: Option<String>,
+    config: EngineConfig,
 }
 
-impl<'a> Default for Engine {
-    fn default() -> Self {
-        Engine {
+impl Engine {
+    pub fn new(config: EngineConfig) -> Result<Self, String> {
+        config.validate()?;
+        let mut vertex_layouts = VertexLayoutRegistry::default();
+        vertex_layouts.register_key(
+            RegisterKey::from_label::<VertexLayout>(DEFAULT_VERTEX_LAYOUT),
+            Vertex::create_buffer_layout().into(),
+        );
+
+        Ok(Engine {
             startup: true,
-            world: Arc::new(Mutex::new(World::new())),
+            world: Arc::new(Mutex::new(
+                World::builder()
+                    .with_resource(Rng::new(config.seed))
+                    .with_resource(MeshManager::new())
+                    .build(),
+            )),
+            render_snapshots: SnapshotBuffer::default(),
             window: None,
             instance: None,
             gpu_context: None,
-            render_pipeline: None,
-            sim_frame_index: FrameIndex::new(3),
-            frame_index: FrameIndex::new(3),
+            render_pipelines: Vec::new(),
+            pipeline_shaders: vec![(String::from("shader.wgsl"), DepthMode::Opaque)],
+            render_mode: RenderMode::default(),
+            render_mode_pipelines: Vec::new(),
+            post_process: None,
+            fxaa: None,
+            fxaa_enabled: false,
+            debug_overlay: None,
+            particle_renderer: None,
+            debug_overlay_enabled: false,
+            entity_count: 0,
+            ring: RingIndices::new(config.ring_depth),
             fps_counter: None,
+            gpu_timer: None,
             bind_group_layout_registry: None,
             mesh_allocator: None,
+            culler: None,
+            shadow_map: None,
+            indirect_draw_writable_bind_groups: Vec::new(),
+            vertex_layouts,
             staging_belt: None,
             gpu_buffer_registry: None,
             thread_pool: None,
+            sim_scheduler: SimScheduler::new(),
+            worker_pool: None,
+            asset_server: None,
             viewports: Vec::new(),
-            input_state: ecs::input::InputState::default(),
+            input_state: ecs::input::InputState {
+                mouse_mode: MouseMode::Relative,
+                ..Default::default()
+            },
+            input_queue: InputQueue::default(),
+            pending_resize: None,
+            cursor_locked: true,
+            cursor_grab: None,
+            audio: AudioEngine::default(),
+            frame_stats: FrameStats::default(),
+            benchmark: config.frame_limit.map(FrameBenchmark::new),
             last_time: Instant::now(),
-            accumulator: Duration::ZERO,
-            delta_time: Duration::from_secs_f64(1.0 / 240.0),
-        }
+            timestep: FixedTimestep::new(config.sim_delta_time()),
+            redraw_requested: false,
+            init_error: None,
+            config,
+        })
     }
-}
 
-impl Engine {
-    fn init(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        info!("starting threadpool");
-        self.thread_pool = Some(ThreadPool::new(4));
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    // Runs the world's systems one tick at a time, created when the window
+    // is resumed.
+    pub fn sim_pool(&self) -> Option<&ThreadPool> {
+        self.thread_pool.as_ref()
+    }
+
+    // For short parallel jobs such as splitting a query across threads. Kept
+    // apart from the sim pool so they never wait behind a tick.
+    pub fn worker_pool(&self) -> Option<&ThreadPool> {
+        self.worker_pool.as_ref()
+    }
+
+    // Decodes the OBJ at `path` in the background. The id stays pending, and
+    // renderables using it draw nothing, until a later frame uploads it. None
+    // before the window is resumed.
+    pub fn load_mesh(&mut self, path: impl AsRef<std::path::Path>) -> Option<MeshId> {
+        let asset_server = self.asset_server.as_mut()?;
+        let mut world = self.world.lock().unwrap();
+        let meshes = world.resource_mut::<MeshManager>()?;
+        Some(asset_server.load_mesh(meshes, path))
+    }
+
+    // Layouts are looked up by label when pipelines are created, so register
+    // custom ones before the window is resumed.
+    pub fn register_vertex_layout(
+        &mut self,
+        label: &'static str,
+        layout: impl Into<VertexLayout>,
+    ) -> RegisterKey {
+        let key = RegisterKey::from_label::<VertexLayout>(label);
+        self.vertex_layouts.register_key(key.clone(), layout.into());
+        key
+    }
+
+    pub fn vertex_layout(&self, label: &'static str) -> Option<&VertexLayout> {
+        self.vertex_layouts
+            .get(&RegisterKey::from_label::<VertexLayout>(label))
+    }
+
+    fn init(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) -> Result<(), String> {
+        info!("starting threadpools");
+        self.thread_pool = Some(ThreadPool::named("sim", self.config.threads));
+        self.worker_pool = Some(ThreadPool::named("worker", self.config.worker_threads));
+        self.asset_server = Some(AssetServer::new(
+            ThreadPool::named("asset", 1),
+            DEFAULT_MESH_STREAM,
+        ));
         event_loop.listen_device_events(winit::event_loop::DeviceEvents::Always);
 
-        info!("creating instance");
-        self.instance = Some(Arc::new(Instance::default()));
+        // WGPU_BACKEND still wins so a backend can be forced without a rebuild.
+        self.config.backends = Backends::from_env().unwrap_or(self.config.backends);
+        info!("creating instance with backends {:?}", self.config.backends);
+        self.instance = Some(Arc::new(Instance::new(&InstanceDescriptor {
+            backends: self.config.backends,
+            ..InstanceDescriptor::from_env_or_default()
+        })));
 
         info!("creating window");
-        self.window = match event_loop.create_window(WindowAttributes::default()) {
+        self.window = match event_loop
+            .create_window(WindowAttributes::default().with_title(self.config.window_title.clone()))
+        {
             Ok(window) => Some(Arc::new(window)),
             Err(err) => {
                 error!("Failed to create window. {:?}", err);
@@ -148,25 +359,63 @@ impl Engine {
             }
         };
 
-        self.create_main_viewport();
-
-        let shader = &self.load_shaders();
+        self.create_main_viewport()?;
 
         self.setup_buffers();
 
-        self.create_render_pipeline(shader);
+        self.create_render_pipelines();
+        self.create_render_mode_pipelines();
+        self.create_shadow_pipeline();
+
+        let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
+        self.gpu_timer = GpuTimer::new(&gpu_context.device, &gpu_context.queue);
+        if self.gpu_timer.is_none() {
+            info!("timestamp queries unsupported, gpu frame timing disabled");
+        }
+
+        let blit_shader = &self.load_shaders("blit.wgsl");
+        self.post_process = Some(self.create_post_process("blit post process", blit_shader));
+
+        let fxaa_shader = &self.load_shaders("fxaa.wgsl");
+        self.fxaa = Some(self.create_post_process("fxaa post process", fxaa_shader));
+
+        let debug_text_shader = &self.load_shaders("debug_text.wgsl");
+        let gpu_context = self.gpu_context.as_ref().unwrap();
+        self.debug_overlay = Some(DebugOverlay::new(
+            gpu_context,
+            debug_text_shader,
+            self.viewports[0].config.format,
+            DEBUG_GLYPH_CAPACITY,
+        ));
+
+        let particle_shader = &self.load_shaders("particles.wgsl");
+        self.particle_renderer = Some(self.create_particle_renderer(particle_shader));
+
+        let device = &self.gpu_context.as_ref().unwrap().device;
+        if GpuCuller::supported(device) {
+            let cull_shader = &self.load_shaders("cull.wgsl");
+            let device = &self.gpu_context.as_ref().unwrap().device;
+            self.culler = Some(GpuCuller::new(
+                device,
+                cull_shader,
+                MAX_INDIRECT_DRAWS as u32,
+            ));
+        } else {
+            info!("indirect draw count unsupported, gpu culling disabled");
+        }
 
         Self::init_scene(
             &mut self.world.lock().unwrap(),
             self.mesh_allocator.as_mut().unwrap(),
-            &self.gpu_context.as_ref().unwrap().queue,
+            self.gpu_context.as_ref().unwrap().as_ref(),
         );
+        Ok(())
     }
 
     fn setup_buffers(&mut self) {
         let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
         let device = &gpu_context.device;
-        let queue = &gpu_context.queue;
+        let gpu: &dyn GpuDevice = gpu_context.as_ref();
 
         let chunck_size = 128 * 1024 * 1024;
         self.staging_belt = Some(Arc::new(Mutex::new(StagingBelt::new(chunck_size))));
@@ -188,19 +437,24 @@ impl Engine {
             RegisterKey::from_label::<BindGroupLayout>("model_bind_group_layout");
         let model_uniform_bind_group_layout = model_uniform.create_bind_group_layout(device);
 
+        let light_uniform = LightUniform::default();
+        let light_bind_group_layout_key =
+            RegisterKey::from_label::<BindGroupLayout>("light_bind_group_layout");
+        let light_uniform_bind_group_layout = light_uniform.create_bind_group_layout(device);
+
         let indirect_draw = IndirectDraw::default();
         let indirect_draw_bind_group_layout_key =
             RegisterKey::from_label::<BindGroupLayout>("indirect_draw_bind_group_layout");
         let indirect_draw_bind_group_layout = indirect_draw.create_bind_group_layout(device);
 
         info!("creating uniform buffers");
-        self.mesh_allocator = Some(MeshAllocator::new(device, 3000.0 as u64, 3000.0 as u64));
+        self.mesh_allocator = Some(MeshAllocator::new(gpu, 3000, 3000, 3000, 3000));
 
         info!("{:?}", camera_uniform);
-        let _ = camera_uniform
+        camera_uniform
             .create_and_store_buffers(
                 device,
-                queue,
+                gpu,
                 &camera_uniform_bind_group_layout,
                 self.gpu_buffer_registry
                     .as_mut()
@@ -215,7 +469,7 @@ impl Engine {
         info!("{:?}", model_uniform);
         let _ = model_uniform.create_and_store_buffers(
             device,
-            queue,
+            gpu,
             &model_uniform_bind_group_layout,
             self.gpu_buffer_registry
                 .as_mut()
@@ -223,11 +477,44 @@ impl Engine {
             0,
         );
 
+        info!("creating shadow map");
+        let mut shadow_map = ShadowMap::new(device, SHADOW_MAP_SIZE);
+
+        info!("{:?}", light_uniform);
+        light_uniform
+            .create_and_store_buffers(
+                device,
+                gpu,
+                &light_uniform_bind_group_layout,
+                &shadow_map,
+                self.gpu_buffer_registry
+                    .as_mut()
+                    .expect("buffer registry should exist"),
+                0,
+            )
+            .unwrap_or_else(|err| {
+                error!("failed to init light buffer {err}");
+                process::exit(1)
+            });
+        if let Some(lights) = self
+            .gpu_buffer_registry
+            .as_ref()
+            .and_then(|registry| {
+                registry.get(&RegisterKey::from_label::<GpuRingBuffer<LightUniform>>(
+                    "light_gpu_uniform_triple",
+                ))
+            })
+            .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<LightUniform>>())
+        {
+            shadow_map.bind_lights(device, lights);
+        }
+        self.shadow_map = Some(shadow_map);
+
         info!("creating other buffers");
         info!("{:?}", indirect_draw);
         let _ = indirect_draw.create_and_store_buffers(
             device,
-            queue,
+            gpu,
             &indirect_draw_bind_group_layout,
             self.gpu_buffer_registry
                 .as_mut()
@@ -235,6 +522,35 @@ impl Engine {
             0,
         );
 
+        if self.config.gpu_driven_draws {
+            let writable_layout = indirect_draw.create_writable_bind_group_layout(device);
+            if let Some(indirect_draws) = self
+                .gpu_buffer_registry
+                .as_ref()
+                .and_then(|registry| {
+                    registry.get(&RegisterKey::from_label::<GpuRingBuffer<IndirectDraw>>(
+                        "indirect_draw_buffer",
+                    ))
+                })
+                .and_then(|entry| entry.as_any().downcast_ref::<GpuRingBuffer<IndirectDraw>>())
+            {
+                self.indirect_draw_writable_bind_groups = IndirectDraw::create_writable_bind_groups(
+                    device,
+                    &writable_layout,
+                    indirect_draws,
+                );
+            }
+            self.bind_group_layout_registry
+                .as_mut()
+                .unwrap()
+                .register_key(
+                    RegisterKey::from_label::<BindGroupLayout>(
+                        "indirect_draw_writable_bind_group_layout",
+                    ),
+                    writable_layout,
+                );
+        }
+
         let bind_group_layout_registry = self.bind_group_layout_registry.as_mut().unwrap();
         bind_group_layout_registry.register_key(
             camera_bind_group_layout_key,
@@ -242,37 +558,30 @@ impl Engine {
         );
         bind_group_layout_registry
             .register_key(model_bind_group_layout_key, model_uniform_bind_group_layout);
+        bind_group_layout_registry
+            .register_key(light_bind_group_layout_key, light_uniform_bind_group_layout);
         bind_group_layout_registry.register_key(
             indirect_draw_bind_group_layout_key,
             indirect_draw_bind_group_layout,
         );
     }
 
-    fn create_main_viewport(&mut self) {
-        let surface = self
-            .instance
-            .as_ref()
-            .expect("instance must exist")
-            .create_surface(self.window.as_ref().unwrap().clone())
-            .map_err(|err| {
-                error!("failed to create surface {err}");
-                std::process::exit(1);
-            })
-            .map(|surface| unsafe { transmute::<Surface<'_>, Surface<'static>>(surface) });
-
-        let surface = surface.unwrap();
+    fn create_main_viewport(&mut self) -> Result<(), String> {
+        let target = WindowSurface::new(
+            self.instance.as_ref().expect("instance must exist"),
+            self.window.as_ref().expect("window should exist").clone(),
+        )?;
 
         info!("creating main viewport");
-        let viewport_description: ViewportDescription = ViewportDescription::new(
-            self.window.as_ref().expect("window should exist").clone(),
-            Color::BLACK,
-            surface,
-        );
+        let viewport_description = ViewportDescription::new(target, self.config.clear_color)
+            .with_frame_latency(self.config.surface_frame_latency(), self.config.ring_depth)
+            .map_err(|err| format!("failed to create main viewport {err}"))?;
 
         let gpu_context = Arc::new(GPUContext::init(
             self.instance.as_ref().expect("instance must exist"),
-            &viewport_description.surface,
-        ));
+            self.config.backends,
+            Some(viewport_description.target.surface()),
+        )?);
 
         self.gpu_context = Some(gpu_context.clone());
 
@@ -280,95 +589,992 @@ impl Engine {
             .build_viewport(self.gpu_context.as_ref().expect("gpu context should exist"));
 
         self.viewports.push(viewport);
+        Ok(())
     }
 
-    fn init_scene(world: &mut World, mesh_allocator: &mut MeshAllocator, queue: &Queue) {
+    fn init_scene(world: &mut World, mesh_allocator: &mut MeshAllocator, gpu: &dyn GpuDevice) {
         world.spawn((
             Camera,
             FpsCamera {
                 yaw: 0.0,
                 pitch: 0.0,
+                roll: 0.0,
                 speed: 5.0,
+                sprint_multiplier: 2.0,
                 sensitivity: 0.002,
             },
             Position(Vec3::new(0.0, 0.0, 0.0)),
         ));
 
-        let vertices: Vec<Vertex> = CUBE_VERTICES
-            .iter()
-            .map(|v| Vertex {
-                position: v.to_array(),
-            })
-            .collect();
+        world.spawn((DirectionalLight {
+            direction: Vec3::new(-0.4, -1.0, -0.6),
+            color: Vec3::ONE,
+            intensity: 1.0,
+        },));
+
+        let (vertices, indices) = cube_mesh();
 
         let static_mesh_handles = mesh_allocator
-            .upload_static_mesh(queue, &vertices, &CUBE_INDICES)
+            .upload_static_mesh(gpu, DEFAULT_MESH_STREAM, &vertices, &indices)
             .unwrap();
 
+        let cube_mesh = world
+            .resource_mut::<MeshManager>()
+            .expect("engine world should have a mesh manager")
+            .insert(static_mesh_handles[0]);
+
+        let mut cubes = Vec::new();
         for i in (0..30).step_by(2) {
             for j in (0..10).step_by(2) {
                 for k in (0..20).step_by(2) {
-                    world.spawn((
+                    cubes.push((
                         components::Transform(Mat4::from_translation(Vec3 {
                             x: i as f32,
                             y: j as f32,
                             z: k as f32,
                         })),
-                        static_mesh_handles[0],
+                        cube_mesh,
+                        Bounds(Aabb::from_points(&CUBE_VERTICES)),
                     ));
                 }
             }
         }
+        world.spawn_batch(cubes);
     }
 
-    fn load_shaders(&mut self) -> ShaderModule {
-        info!("loading shaders");
-        let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
+    // Scene setup that runs on the sim thread before the first world update.
+    pub fn add_startup_system(&mut self, system: impl FnOnce(&mut World) + Send + 'static) {
+        self.world.lock().unwrap().add_startup_system(system);
+    }
+
+    pub fn startup_ran(&self) -> bool {
+        self.world.lock().unwrap().startup_ran()
+    }
+
+    pub fn set_system_timing(&mut self, enabled: bool) {
+        self.world.lock().unwrap().set_system_timing(enabled);
+    }
+
+    // None unless system timing is enabled.
+    pub fn system_stats(&self) -> Option<SystemStats> {
+        self.world
+            .lock()
+            .unwrap()
+            .resource::<SystemStats>()
+            .cloned()
+    }
+
+    // Runs on the sim thread every tick, see World::add_system.
+    pub fn add_system<Params>(&mut self, system: impl IntoSystem<Params>) -> Result<(), String> {
+        self.world.lock().unwrap().add_system(system)
+    }
+
+    pub fn pick_entity(&self) -> Option<EntityId> {
+        if !self.input_state.cursor_in_window {
+            return None;
+        }
+        let window = self.window.as_ref()?;
+        let size = window.inner_size();
+        let mut world = self.world.lock().unwrap();
+
+        let (view, projection) = world
+            .query::<(&FpsCamera, &Position, &Camera)>()
+            .next()
+            .map(|(camera, position, _)| camera_matrices(camera, position))?;
+
+        let ray = Ray::from_screen(
+            Vec2::new(self.input_state.cursor_x, self.input_state.cursor_y),
+            Vec2::new(size.width as f32, size.height as f32),
+            (projection * view).inverse(),
+        );
+        world.pick(&ray)
+    }
+
+    pub fn gpu_frame_time_ms(&self) -> Option<f32> {
+        self.gpu_timer
+            .as_ref()
+            .and_then(GpuTimer::last_frame_time_ms)
+    }
+
+    // The ring slot the current frame renders from.
+    pub fn frame_index(&self) -> usize {
+        self.ring.read_slot()
+    }
+
+    pub fn ring_indices(&self) -> RingIndices {
+        self.ring
+    }
+
+    // The current frame's indirect draw buffer bound writable, for a compute
+    // pass generating draws. None unless gpu_driven_draws is set.
+    pub fn indirect_draw_writable_bind_group(&self) -> Option<&BindGroup> {
+        self.indirect_draw_writable_bind_groups
+            .get(self.ring.read_slot())
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    pub fn cycle_render_mode(&mut self) -> RenderMode {
+        self.render_mode = self.render_mode.next();
+        info!("render mode {:?}", self.render_mode);
+        self.render_mode
+    }
+
+    pub fn fxaa_enabled(&self) -> bool {
+        self.fxaa_enabled
+    }
+
+    pub fn set_fxaa_enabled(&mut self, enabled: bool) {
+        self.fxaa_enabled = enabled;
+    }
+
+    pub fn debug_overlay_enabled(&self) -> bool {
+        self.debug_overlay_enabled
+    }
+
+    // Draws FPS, entity count and the last frame's draw stats in the top left.
+    pub fn set_debug_overlay_enabled(&mut self, enabled: bool) {
+        self.debug_overlay_enabled = enabled;
+    }
+
+    pub fn viewport_camera(&self, viewport: usize) -> Option<EntityId> {
+        self.viewports.get(viewport)?.description.camera
+    }
+
+    // None draws the viewport from the first active camera.
+    pub fn set_viewport_camera(
+        &mut self,
+        viewport: usize,
+        camera: Option<EntityId>,
+    ) -> Result<(), String> {
+        let viewport = self
+            .viewports
+            .get_mut(viewport)
+            .ok_or_else(|| format!("no viewport {viewport}"))?;
+        viewport.description.camera = camera;
+        Ok(())
+    }
+
+    pub fn render_scale(&self) -> Option<f32> {
+        Some(self.viewports.first()?.description.render_scale)
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<(), String> {
+        let viewport = self
+            .viewports
+            .first_mut()
+            .ok_or("no viewport to set the render scale on")?;
+        viewport.description.set_render_scale(render_scale)?;
+
+        let device = &self
+            .gpu_context
+            .as_ref()
+            .ok_or("gpu context does not exist yet")?
+            .device;
+        let config = viewport.config.clone();
+        Self::recreate_render_targets(
+            viewport,
+            &config,
+            device,
+            [self.post_process.as_mut(), self.fxaa.as_mut()],
+        );
+        Ok(())
+    }
+
+    fn apply_pending_resize(&mut self) {
+        let Some(size) = self.pending_resize.take() else {
+            return;
+        };
+        let (Some(viewport), Some(gpu_context)) =
+            (self.viewports.get_mut(0), self.gpu_context.as_ref())
+        else {
+            return;
+        };
         let device = &gpu_context.device;
-        let shader_name = String::from(
-            "/home/sevenofnine/Git/potato-engine/engine/src/graphics/shaders/shader.wgsl",
+        if !viewport.resize(device, size.width, size.height) {
+            return;
+        }
+        let config = viewport.config.clone();
+        Self::recreate_render_targets(
+            viewport,
+            &config,
+            device,
+            [self.post_process.as_mut(), self.fxaa.as_mut()],
+        );
+    }
+
+    fn recreate_render_targets(
+        viewport: &mut Viewport,
+        config: &SurfaceConfiguration,
+        device: &Device,
+        post_processes: [Option<&mut PostProcessPass>; 2],
+    ) {
+        // Nothing to draw into at zero size, they are rebuilt once restored.
+        if config.width == 0 || config.height == 0 {
+            return;
+        }
+        let sample_count = viewport.description.sample_count;
+        viewport
+            .description
+            .create_depth_resources(device, config, sample_count);
+        if viewport.description.create_color_resources(device, config)
+            && let Some(color) = &viewport.description.color
+        {
+            for post_process in post_processes.into_iter().flatten() {
+                post_process.set_source(device, &color.view);
+            }
+        }
+    }
+
+    pub fn cursor_locked(&self) -> bool {
+        self.cursor_locked
+    }
+
+    // Locking hides the cursor and grabs it for mouse look; unlocking hands it
+    // back to the desktop and stops mouse motion from turning the camera.
+    pub fn set_cursor_locked(&mut self, locked: bool) {
+        self.cursor_locked = locked;
+        self.input_state.set_mouse_mode(if locked {
+            MouseMode::Relative
+        } else {
+            MouseMode::Absolute
+        });
+        self.apply_cursor_lock(locked);
+    }
+
+    fn apply_cursor_lock(&mut self, locked: bool) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        if locked {
+            self.cursor_grab = Some(grab_cursor(self.config.cursor_grab, |mode| {
+                window.set_cursor_grab(mode)
+            }));
+        } else {
+            let _ = window.set_cursor_grab(winit::window::CursorGrabMode::None);
+            self.cursor_grab = None;
+        }
+        window.set_cursor_visible(!locked);
+        self.recenter_cursor();
+    }
+
+    // Stands in for a grab on platforms that support neither mode.
+    fn recenter_cursor(&self) {
+        if self.cursor_grab != Some(GrabOutcome::Recenter) {
+            return;
+        }
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        let size = window.inner_size();
+        let _ = window.set_cursor_position(PhysicalPosition::new(
+            size.width as f64 / 2.0,
+            size.height as f64 / 2.0,
+        ));
+    }
+
+    // Waits for every sim tick submitted so far, so the snapshot is taken
+    // from the world as the last of them left it and never from one between
+    // ticks. The world lock is only held long enough to copy out what the
+    // renderer needs.
+    pub fn begin_frame(&mut self) {
+        self.sim_scheduler.wait();
+        {
+            let mut world = self.world.lock().unwrap();
+            world.extract_render_snapshot(self.render_snapshots.back_mut());
+            self.render_snapshots.swap();
+            self.entity_count = world.entity_count();
+
+            for event in world.take_events::<PlaySound>() {
+                if let Err(err) = self.audio.play_label(&event.sound) {
+                    error!("{err}");
+                }
+            }
+
+            if let Some(asset_server) = self.asset_server.as_mut()
+                && asset_server.pending() > 0
+                && let (Some(gpu_context), Some(mesh_allocator)) =
+                    (self.gpu_context.as_ref(), self.mesh_allocator.as_mut())
+                && let Some(meshes) = world.resource_mut::<MeshManager>()
+            {
+                asset_server.upload_ready(gpu_context.as_ref(), mesh_allocator, meshes);
+            }
+        }
+        self.audio.flush();
+    }
+
+    // Runs the steps that came due since the last job. Called right after
+    // the frame's snapshot is taken, so the ticks for the next frame overlap
+    // this one's rendering. All of them run in order in one job, holding the
+    // world lock throughout, so nothing sees the world between them.
+    fn submit_sim_ticks(&mut self) {
+        let Some((steps, signal)) = self.sim_scheduler.take_job() else {
+            return;
+        };
+        let world = self.world.clone();
+        self.ring.advance_sim();
+        let frame_index = self.ring.write_slot();
+        self.input_state.apply_events(&self.input_queue.drain());
+        let mut input_state = self.input_state;
+        debug!("{:?}", input_state);
+        let delta_time = self.timestep.delta_time();
+        #[cfg(feature = "tracy")]
+        span!("ECS Tick Submission");
+        let run_ticks = move || {
+            let _signal = signal;
+            #[cfg(feature = "tracy")]
+            span!("World.run_systems");
+            let mut world = world.lock().unwrap();
+            for _ in 0..steps {
+                world.run_systems(frame_index, &input_state, delta_time.as_secs_f32());
+                // Mouse movement and button transitions only apply to the
+                // first tick.
+                input_state.mouse_delta_x = 0.0;
+                input_state.mouse_delta_y = 0.0;
+                input_state.clear_transitions();
+            }
+        };
+        match self.thread_pool.as_ref() {
+            Some(thread_pool) if self.config.threaded_sim => thread_pool.submit(run_ticks),
+            _ => run_ticks(),
+        }
+
+        self.input_state.mouse_delta_x = 0.0;
+        self.input_state.mouse_delta_y = 0.0;
+        self.input_state.clear_transitions();
+    }
+
+    // Safe to call more than once. Sim jobs finish before the GPU resources go
+    // and the surfaces go before the window they were created from.
+    pub fn shutdown(&mut self) {
+        if let Some(asset_server) = self.asset_server.take() {
+            info!("joining asset pool");
+            asset_server.shutdown();
+        }
+        if let Some(thread_pool) = self.thread_pool.take() {
+            info!("joining threadpool");
+            thread_pool.shutdown();
+        }
+        if let Some(worker_pool) = self.worker_pool.take() {
+            info!("joining worker pool");
+            worker_pool.shutdown();
+        }
+
+        if let Some(gpu_context) = &self.gpu_context {
+            let _ = gpu_context.device.poll(PollType::Wait);
+        }
+        self.gpu_timer = None;
+        self.culler = None;
+        self.shadow_map = None;
+        self.indirect_draw_writable_bind_groups.clear();
+        self.debug_overlay = None;
+        self.particle_renderer = None;
+        self.fxaa = None;
+        self.post_process = None;
+        self.render_pipelines.clear();
+        self.render_mode_pipelines.clear();
+        self.mesh_allocator = None;
+        self.gpu_buffer_registry = None;
+        self.bind_group_layout_registry = None;
+        self.staging_belt = None;
+        self.viewports.clear();
+        self.gpu_context = None;
+        self.instance = None;
+        self.window = None;
+    }
+
+    // Stops running the world's systems, rendering carries on with the last
+    // snapshot.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.timestep.set_paused(paused);
+    }
+
+    pub fn paused(&self) -> bool {
+        self.timestep.paused()
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    // Why the event loop exited before the first frame, if it did.
+    pub fn init_error(&self) -> Option<&str> {
+        self.init_error.as_deref()
+    }
+
+    // Averages over the frames rendered so far, None without a frame limit.
+    pub fn benchmark_report(&self) -> Option<BenchmarkReport> {
+        self.benchmark.as_ref().map(FrameBenchmark::report)
+    }
+
+    // Adds a rendered frame to the benchmark, true once the limit is reached.
+    fn record_benchmark_frame(&mut self, cpu_time: Duration) -> bool {
+        let gpu_time_ms = self
+            .gpu_timer
+            .as_ref()
+            .and_then(GpuTimer::last_frame_time_ms);
+        let Some(benchmark) = self.benchmark.as_mut() else {
+            return false;
+        };
+        if !benchmark.record(cpu_time, gpu_time_ms) {
+            return false;
+        }
+        let report = benchmark.report();
+        match report.average_gpu_ms {
+            Some(gpu_ms) => info!(
+                "benchmark: {} frames, CPU {:.3} ms, GPU {gpu_ms:.3} ms",
+                report.frames, report.average_cpu_ms
+            ),
+            None => info!(
+                "benchmark: {} frames, CPU {:.3} ms, GPU time unavailable",
+                report.frames, report.average_cpu_ms
+            ),
+        }
+        true
+    }
+
+    pub fn audio(&self) -> &AudioEngine {
+        &self.audio
+    }
+
+    pub fn audio_mut(&mut self) -> &mut AudioEngine {
+        &mut self.audio
+    }
+
+    // False when nothing was submitted, e.g. the surface had no texture to
+    // draw into, so the frame shouldn't be counted or its ring slot advanced.
+    pub fn render(&mut self) -> bool {
+        let Some(viewport) = self.viewports.first() else {
+            return false;
+        };
+        let descriptor = &viewport.description;
+        let render_pipelines = &self.render_pipelines;
+        let pipeline_override = self
+            .render_mode
+            .debug_pipeline()
+            .and_then(|index| self.render_mode_pipelines.get(index));
+        assert!(!render_pipelines.is_empty(), "render pipeline must exist");
+
+        descriptor.target.window().pre_present_notify();
+        let device = &self
+            .gpu_context
+            .as_ref()
+            .expect("gpu_context should exist")
+            .device;
+        let output = match viewport.acquire_texture(device) {
+            Ok(Some(output)) => output,
+            Ok(None) => return false,
+            Err(err) => {
+                error!("{err}");
+                process::exit(1);
+            }
+        };
+
+        let view = output.texture.create_view(&Default::default());
+
+        let gpu_context = self.gpu_context.clone().expect("gpu_context should exist");
+        // Uploads and passes go in separate encoders, submitted together.
+        let mut frame_encoder = FrameEncoder::new();
+        let mut encoder = gpu_context.begin_encoder("frame uploads");
+
+        let mut staging_belt = self.staging_belt.as_mut().unwrap().lock().unwrap();
+        let gpu_buffer_registry = self.gpu_buffer_registry.as_mut().unwrap();
+        let device = &self.gpu_context.as_ref().unwrap().device;
+        let frame_index = self.ring.read_slot();
+        let snapshot = self.render_snapshots.front();
+        // Culling and draw order follow the camera the viewport draws from.
+        let camera_view = viewport_camera_view(snapshot, descriptor.camera);
+        let camera = snapshot.views.get(camera_view as usize);
+        upload_camera_data(
+            snapshot,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            gpu_buffer_registry,
+        );
+
+        upload_light_data(
+            snapshot,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            gpu_buffer_registry,
+        );
+
+        upload_bone_palette(
+            snapshot,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            gpu_buffer_registry,
+        );
+
+        let draw_orders: Vec<DrawOrder> = self
+            .pipeline_shaders
+            .iter()
+            .map(|(_, depth_mode)| depth_mode.draw_order())
+            .collect();
+        let (frame_stats, draw_list) = upload_indirect_draw_commands(
+            snapshot,
+            camera,
+            frame_index,
+            &mut staging_belt,
+            device,
+            &mut encoder,
+            gpu_buffer_registry,
+            self.bind_group_layout_registry
+                .as_ref()
+                .unwrap()
+                .get(&RegisterKey::from_label::<BindGroupLayout>(
+                    "model_bind_group_layout",
+                ))
+                .expect("model bind group layout should exist"),
+            self.mesh_allocator.as_ref().unwrap(),
+            &draw_orders,
+        );
+        self.frame_stats = frame_stats;
+
+        // The cull pass reads the model matrices uploaded above. It draws
+        // with one pipeline from one stream, so lists needing more than that
+        // take the normal draw path.
+        let mut cull_models = None;
+        if let Some(culler) = self.culler.as_mut()
+            && let Some((_, stream)) = draw_list.single_batch()
+        {
+            let gpu: &dyn GpuDevice = self.gpu_context.as_ref().unwrap().as_ref();
+            match upload_cull_instances(
+                snapshot,
+                camera,
+                draw_list.instance_order(),
+                stream,
+                gpu,
+                self.mesh_allocator.as_ref().unwrap(),
+                culler,
+            ) {
+                Ok(()) => {
+                    cull_models = gpu_buffer_registry
+                        .get(&RegisterKey::from_label::<GpuRingBuffer<ModelUniform>>(
+                            "model_gpu_uniform_triple",
+                        ))
+                        .and_then(|entry| {
+                            entry.as_any().downcast_ref::<GpuRingBuffer<ModelUniform>>()
+                        })
+                        .map(|models| models.get_read(frame_index).buffer.clone());
+                }
+                Err(err) => error!("failed to upload draws for culling {err}"),
+            }
+        }
+
+        frame_encoder.push("frame uploads", encoder.finish());
+        let mut encoder = gpu_context.begin_encoder("frame passes");
+
+        let scene_color = &descriptor
+            .color
+            .as_ref()
+            .expect("scene color target must exist")
+            .view;
+        let (post_process_name, post_process) = if self.fxaa_enabled {
+            ("fxaa", self.fxaa.as_ref().expect("fxaa pass must exist"))
+        } else {
+            (
+                "post_process",
+                self.post_process
+                    .as_ref()
+                    .expect("post process pass must exist"),
+            )
+        };
+        let debug_overlay = match self.debug_overlay.as_mut() {
+            Some(debug_overlay) if self.debug_overlay_enabled => {
+                let gpu: &dyn GpuDevice = self.gpu_context.as_ref().unwrap().as_ref();
+                let (fps, gpu_ms) = self
+                    .fps_counter
+                    .as_ref()
+                    .map(|fps_counter| (fps_counter.fps(), fps_counter.gpu_frame_time_ms()))
+                    .unwrap_or_default();
+                let text = debug_overlay_text(fps, gpu_ms, self.entity_count, &self.frame_stats);
+                debug_overlay.prepare(
+                    gpu,
+                    &text,
+                    viewport.config.width,
+                    viewport.config.height,
+                    frame_index,
+                );
+                Some(&*debug_overlay)
+            }
+            _ => None,
+        };
+        let particle_renderer = match self.particle_renderer.as_mut() {
+            Some(particle_renderer) => {
+                let gpu: &dyn GpuDevice = self.gpu_context.as_ref().unwrap().as_ref();
+                particle_renderer.prepare(gpu, &snapshot.particles, frame_index);
+                Some(&*particle_renderer)
+            }
+            None => None,
+        };
+        let camera_bind_group = gpu_buffer_registry
+            .get(&RegisterKey::from_label::<GpuRingBuffer<CameraUniform>>(
+                "camera_gpu_uniform_triple",
+            ))
+            .and_then(|entry| {
+                entry
+                    .as_any()
+                    .downcast_ref::<GpuRingBuffer<CameraUniform>>()
+            })
+            .and_then(|cameras| cameras.get_read(frame_index).bind_group.clone());
+        let mesh_allocator = self.mesh_allocator.as_ref().unwrap();
+        let gpu_buffer_registry = &*gpu_buffer_registry;
+
+        let gpu_timer = self.gpu_timer.as_ref();
+        // Without models the upload failed or was skipped, and the culler
+        // holds no draws for this frame.
+        let culler = self.culler.as_ref().filter(|_| cull_models.is_some());
+        let shadow_map = self.shadow_map.as_ref();
+        // Only geometry that writes depth casts shadows, not skyboxes or overlays.
+        let shadow_casters: Vec<bool> = self
+            .pipeline_shaders
+            .iter()
+            .map(|(_, depth_mode)| *depth_mode == DepthMode::Opaque)
+            .collect();
+
+        let mut render_graph = RenderGraph::new();
+        if let (Some(culler), Some(models)) = (culler, cull_models.as_ref()) {
+            render_graph.add_pass("cull", &[], &["draw_commands"], |encoder| {
+                culler.encode(device, encoder, models)
+            });
+        }
+        // Replays the whole draw list even when culling, casters outside the
+        // camera frustum can still shadow what is inside it.
+        if let Some(shadow_map) = shadow_map {
+            render_graph.add_pass("shadow", &[], &["shadow_map"], |encoder| {
+                shadow_map.encode(
+                    encoder,
+                    gpu_buffer_registry,
+                    frame_index,
+                    mesh_allocator,
+                    &draw_list,
+                    &shadow_casters,
+                )
+            });
+        }
+        render_graph.add_pass(
+            "opaque",
+            &["draw_commands", "shadow_map"],
+            &["scene_color", "depth"],
+            |encoder| {
+                init_render_pass(
+                    encoder,
+                    scene_color,
+                    descriptor,
+                    render_pipelines,
+                    gpu_buffer_registry,
+                    frame_index,
+                    mesh_allocator,
+                    &draw_list,
+                    culler,
+                    camera_view,
+                    PassConfig::scene(descriptor.background),
+                    gpu_timer.map(GpuTimer::timestamp_writes),
+                    pipeline_override,
+                )
+            },
         );
-        load_shader(device, shader_name)
+        // Without a camera no view was uploaded to face the particles toward.
+        if let (Some(particle_renderer), Some(camera_bind_group), Some(_)) =
+            (particle_renderer, camera_bind_group.as_ref(), camera)
+        {
+            render_graph.add_pass(
+                "particles",
+                &["scene_color", "depth"],
+                &["scene_color"],
+                |encoder| {
+                    particle_renderer.encode(
+                        encoder,
+                        scene_color,
+                        descriptor.depth.as_ref().map(|depth| &depth.view),
+                        camera_bind_group,
+                        camera_view_offset(camera_view),
+                    )
+                },
+            );
+        }
+        render_graph.add_pass(
+            post_process_name,
+            &["scene_color"],
+            &["swapchain"],
+            |encoder| post_process.encode(encoder, &view),
+        );
+        if let Some(debug_overlay) = debug_overlay {
+            render_graph.add_pass("debug_overlay", &["swapchain"], &["swapchain"], |encoder| {
+                debug_overlay.encode(encoder, &view)
+            });
+        }
+        if let Err(err) = render_graph.execute(&mut encoder) {
+            error!("failed to execute render graph {err}");
+        }
+        // Release the borrows held by the pass closures.
+        drop(render_graph);
+
+        if let Some(gpu_timer) = self.gpu_timer.as_mut() {
+            gpu_timer.resolve(&mut encoder, self.ring.read_slot());
+        }
+
+        frame_encoder.push("frame passes", encoder.finish());
+        staging_belt.finish();
+        frame_encoder.submit(&gpu_context.queue);
+
+        if let Some(gpu_timer) = self.gpu_timer.as_mut() {
+            gpu_timer.map_resolved();
+        }
+
+        output.present();
+        true
     }
 
-    fn create_render_pipeline(&mut self, shader: &ShaderModule) {
+    pub fn end_frame(&mut self) {
+        if let Some(staging_belt) = &self.staging_belt {
+            staging_belt.lock().unwrap().recall();
+        }
+
+        self.ring.advance_render();
+        if let Some(fps_counter) = self.fps_counter.as_mut() {
+            if let Some(gpu_timer) = self.gpu_timer.as_mut()
+                && let Some(gpu_context) = self.gpu_context.as_ref()
+            {
+                fps_counter.set_gpu_frame_time(gpu_timer.collect(&gpu_context.device));
+            }
+            fps_counter.tick();
+        }
+    }
+
+    fn load_shaders(&mut self, file_name: &str) -> ShaderModule {
+        info!("loading shader {file_name}");
         let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
         let device = &gpu_context.device;
-        let adapter = &gpu_context.adapter;
-        let surface = &self
-            .viewports
-            .get(0)
+        let shader_name = self.config.shader_dir.join(file_name);
+        load_shader(device, shader_name.to_string_lossy().into_owned())
+    }
+
+    // Uses the configured anisotropy, clamped to what the adapter supports.
+    pub fn create_sampler(&self, label: &str) -> Sampler {
+        let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
+        textures::create_sampler(
+            &gpu_context.device,
+            label,
+            self.config.anisotropy,
+            gpu_context.max_anisotropy(),
+        )
+    }
+
+    pub fn create_mipmap_generator(&mut self, format: TextureFormat) -> MipmapGenerator {
+        let shader = self.load_shaders("blit.wgsl");
+        let device = &self
+            .gpu_context
             .as_ref()
-            .expect("viewport must exist")
+            .expect("gpu context should exist")
+            .device;
+        MipmapGenerator::new(device, &shader, format)
+    }
+
+    fn create_post_process(&self, label: &'static str, shader: &ShaderModule) -> PostProcessPass {
+        let device = &self
+            .gpu_context
+            .as_ref()
+            .expect("gpu context should exist")
+            .device;
+        let viewport = self.viewports.first().expect("viewport must exist");
+
+        info!("creating {label} pass");
+        let mut post_process = PostProcessPass::new(device, label, shader, viewport.config.format);
+        if let Some(color) = &viewport.description.color {
+            post_process.set_source(device, &color.view);
+        }
+        post_process
+    }
+
+    // Adds a pipeline drawing with `shader_file`'s vs_main and fs_main, for
+    // entities given the returned key as a component. The shader has to use
+    // the same bind groups and vertex layout as shader.wgsl.
+    pub fn add_pipeline(&mut self, shader_file: impl Into<String>) -> PipelineKey {
+        self.add_pipeline_with_depth(shader_file, DepthMode::Opaque)
+    }
+
+    // Pipelines draw in key order, so ones added later, e.g. a skybox, draw
+    // after the default one.
+    pub fn add_pipeline_with_depth(
+        &mut self,
+        shader_file: impl Into<String>,
+        depth_mode: DepthMode,
+    ) -> PipelineKey {
+        let key = PipelineKey(self.pipeline_shaders.len() as u32);
+        let shader_file = shader_file.into();
+        // Before the GPU is set up the pipeline is built along with the rest.
+        if !self.render_pipelines.is_empty() {
+            let shader = self.load_shaders(&shader_file);
+            let pipeline = self.create_render_pipeline(&shader, depth_mode);
+            self.render_pipelines.push(pipeline);
+        }
+        self.pipeline_shaders.push((shader_file, depth_mode));
+        key
+    }
+
+    pub fn pipeline_depth_mode(&self, key: PipelineKey) -> Option<DepthMode> {
+        self.pipeline_shaders
+            .get(key.0 as usize)
+            .map(|(_, depth_mode)| *depth_mode)
+    }
+
+    fn create_render_pipelines(&mut self) {
+        self.render_pipelines.clear();
+        for (shader_file, depth_mode) in self.pipeline_shaders.clone() {
+            let shader = self.load_shaders(&shader_file);
+            let pipeline = self.create_render_pipeline(&shader, depth_mode);
+            self.render_pipelines.push(pipeline);
+        }
+    }
+
+    fn create_render_mode_pipelines(&mut self) {
+        let shader = self.load_shaders("debug.wgsl");
+        let line_supported = self
+            .gpu_context
+            .as_ref()
+            .expect("gpu context should exist")
+            .capabilities()
+            .polygon_mode_line;
+        if !line_supported {
+            info!("polygon mode line unsupported, wireframe render mode draws filled");
+        }
+        self.render_mode_pipelines = RenderMode::debug_modes()
+            .map(|mode| {
+                self.create_scene_pipeline(
+                    &shader,
+                    mode.fragment_entry(),
+                    mode.depth_mode(),
+                    mode.blend(),
+                    mode.polygon_mode(line_supported),
+                )
+            })
+            .collect();
+    }
+
+    fn create_shadow_pipeline(&mut self) {
+        let Some(mut shadow_map) = self.shadow_map.take() else {
+            return;
+        };
+        let shader = self.load_shaders("shadow.wgsl");
+        let device = &self
+            .gpu_context
+            .as_ref()
+            .expect("gpu context should exist")
+            .device;
+        let model_bind_group_layout = self
+            .bind_group_layout_registry
+            .as_ref()
+            .expect("bind group layout registry must exist")
+            .get(&RegisterKey::from_label::<BindGroupLayout>(
+                "model_bind_group_layout",
+            ))
+            .expect("model bind group layout should exist");
+        let vertex_layout = self
+            .vertex_layout(DEFAULT_VERTEX_LAYOUT)
+            .expect("default vertex layout must be registered")
+            .buffer_layout();
+        shadow_map.create_pipeline(device, &shader, model_bind_group_layout, vertex_layout);
+        self.shadow_map = Some(shadow_map);
+    }
+
+    fn create_particle_renderer(&self, shader: &ShaderModule) -> ParticleRenderer {
+        let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
+        let viewport = self.viewports.first().expect("viewport must exist");
+        let depth = viewport
             .description
-            .surface;
+            .depth
+            .as_ref()
+            .expect("depth resources must exist");
+        let camera_bind_group_layout = self
+            .bind_group_layout_registry
+            .as_ref()
+            .expect("bind group layout registry must exist")
+            .get(&RegisterKey::from_label::<BindGroupLayout>(
+                "camera_bind_group_layout",
+            ))
+            .expect("camera bind group layout should exist");
+        ParticleRenderer::new(
+            &gpu_context.device,
+            gpu_context.as_ref(),
+            shader,
+            camera_bind_group_layout,
+            viewport.config.format,
+            depth.format,
+            depth.sample_count,
+            PARTICLE_CAPACITY,
+        )
+    }
+
+    fn create_render_pipeline(
+        &self,
+        shader: &ShaderModule,
+        depth_mode: DepthMode,
+    ) -> RenderPipeline {
+        self.create_scene_pipeline(
+            shader,
+            "fs_main",
+            depth_mode,
+            depth_mode.blend(),
+            PolygonMode::Fill,
+        )
+    }
+
+    // A pipeline for the scene pass, with its bind groups and vertex layout.
+    fn create_scene_pipeline(
+        &self,
+        shader: &ShaderModule,
+        fragment_entry: &str,
+        depth_mode: DepthMode,
+        blend: Option<BlendState>,
+        polygon_mode: PolygonMode,
+    ) -> RenderPipeline {
+        let gpu_context = self.gpu_context.as_ref().expect("gpu context should exist");
+        let device = &gpu_context.device;
+        let surface_format = self
+            .viewports
+            .first()
+            .expect("viewport must exist")
+            .config
+            .format;
         let bind_group_layout_registry = self
             .bind_group_layout_registry
             .as_ref()
             .expect("bind group layout registry must exist");
 
         info!("creating rendering pipeline");
-        let vertex_buffer_layout = VertexBufferLayout {
-            array_stride: size_of::<[f32; 3]>() as wgpu::BufferAddress,
-            attributes: &[VertexAttribute {
-                offset: 0,
-                shader_location: 0,
-                format: VertexFormat::Float32x3,
-            }],
-            step_mode: wgpu::VertexStepMode::Vertex,
-        };
+        let vertex_layout = self
+            .vertex_layout(DEFAULT_VERTEX_LAYOUT)
+            .expect("default vertex layout must be registered");
 
         let vertex = VertexState {
-            module: &shader,
+            module: shader,
             entry_point: Some("vs_main"),
             compilation_options: Default::default(),
-            buffers: &[vertex_buffer_layout],
+            buffers: &[vertex_layout.buffer_layout()],
         };
         let fragment = FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
+            module: shader,
+            entry_point: Some(fragment_entry),
             compilation_options: Default::default(),
-            targets: &[Some(surface.get_capabilities(&adapter).formats[0].into())],
+            targets: &[Some(ColorTargetState {
+                format: surface_format,
+                blend,
+                write_mask: ColorWrites::ALL,
+            })],
         };
         let camera_bind_group_layout_key =
             RegisterKey::from_label::<BindGroupLayout>("camera_bind_group_layout");
@@ -380,6 +1586,11 @@ impl Engine {
         let model_bind_group_layout = bind_group_layout_registry
             .get(&model_bind_group_layout_key)
             .unwrap();
+        let light_bind_group_layout_key =
+            RegisterKey::from_label::<BindGroupLayout>("light_bind_group_layout");
+        let light_bind_group_layout = bind_group_layout_registry
+            .get(&light_bind_group_layout_key)
+            .unwrap();
         let indirect_draw_bind_group_layout_key =
             RegisterKey::from_label::<BindGroupLayout>("indirect_draw_bind_group_layout");
         let indirect_draw_bind_group_layout = bind_group_layout_registry
@@ -389,38 +1600,45 @@ impl Engine {
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("simple pipeline layout"),
             bind_group_layouts: &[
-                &camera_bind_group_layout,
-                &model_bind_group_layout,
-                &indirect_draw_bind_group_layout,
+                camera_bind_group_layout,
+                model_bind_group_layout,
+                indirect_draw_bind_group_layout,
+                light_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
+        let depth = self
+            .viewports
+            .first()
+            .expect("viewport must exist")
+            .description
+            .depth
+            .as_ref()
+            .expect("depth resources must exist");
         let render_pipeline_descriptor = &RenderPipelineDescriptor {
             label: Some("render pipeline descriptor"),
             layout: Some(&pipeline_layout),
             vertex,
             fragment: Some(fragment),
-            primitive: PrimitiveState::default(),
-            depth_stencil: Some(DepthStencilState {
-                format: self
-                    .viewports
-                    .get(0)
-                    .unwrap()
-                    .description
-                    .depth
-                    .as_ref()
-                    .unwrap()
-                    .format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: StencilState::default(),
-                bias: DepthBiasState::default(),
-            }),
-            multisample: MultisampleState::default(),
+            primitive: PrimitiveState {
+                polygon_mode,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_mode.depth_stencil_state(depth.format)),
+            multisample: MultisampleState {
+                count: depth.sample_count,
+                ..Default::default()
+            },
             multiview: None,
             cache: None,
         };
-        self.render_pipeline = Some(device.create_render_pipeline(render_pipeline_descriptor));
+        device.create_render_pipeline(render_pipeline_descriptor)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.shutdown();
     }
 }
 
@@ -433,7 +1651,13 @@ impl ApplicationHandler for Engine {
         span!("Winit::resumed");
 
         if self.startup {
-            self.init(event_loop);
+            if let Err(err) = self.init(event_loop) {
+                error!("failed to start the engine {err}");
+                self.init_error = Some(err);
+                self.shutdown();
+                event_loop.exit();
+                return;
+            }
 
             info!("creating fps counter");
             self.fps_counter = Some(FPSCounter::default());
@@ -441,12 +1665,7 @@ impl ApplicationHandler for Engine {
             self.startup = false;
         }
 
-        self.window.as_ref().unwrap().set_cursor_visible(false);
-        let _ = self
-            .window
-            .as_ref()
-            .unwrap()
-            .set_cursor_grab(winit::window::CursorGrabMode::Locked);
+        self.apply_cursor_lock(self.cursor_locked);
 
         info!("requesting first redraw");
         self.window
@@ -455,19 +1674,14 @@ impl ApplicationHandler for Engine {
             .request_redraw();
     }
 
-    fn suspended(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        let _ = self
-            .window
-            .as_ref()
-            .unwrap()
-            .set_cursor_grab(winit::window::CursorGrabMode::None);
-        self.window.as_ref().unwrap().set_cursor_visible(true);
+    fn suspended(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        self.apply_cursor_lock(false);
     }
 
     fn new_events(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        cause: winit::event::StartCause,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _cause: winit::event::StartCause,
     ) {
     }
 
@@ -479,117 +1693,92 @@ impl ApplicationHandler for Engine {
     ) {
         debug!("processing event {:?}", event);
         match event {
+            // Drag resizing sends many of these a frame, only the last one is
+            // applied, before the next frame is drawn.
             winit::event::WindowEvent::Resized(physical_size) => {
-                let window = self.window.as_ref().expect("window must exist");
-                let viewport = self.viewports.get_mut(0).expect("viewport must exist");
-                let device = &self.gpu_context.as_ref().expect("device must exist").device;
-
-                let mut config = viewport.config.clone();
-
-                config.width = physical_size.width;
-                config.height = physical_size.height;
-
-                viewport.description.surface.configure(device, &config);
-                viewport.description.create_depth_resources(device, &config);
-
-                window.request_redraw();
+                self.pending_resize = Some(physical_size);
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
             }
             winit::event::WindowEvent::CloseRequested => {
                 info!("Close request processing");
+                self.shutdown();
                 event_loop.exit();
             }
             winit::event::WindowEvent::KeyboardInput {
-                device_id,
+                device_id: _,
                 event,
-                is_synthetic,
+                is_synthetic: _,
             } => {
                 let pressed = event.state == ElementState::Pressed;
-                match event.physical_key {
-                    PhysicalKey::Code(KeyCode::KeyW) => self.input_state.key_w = pressed,
-                    PhysicalKey::Code(KeyCode::KeyA) => self.input_state.key_a = pressed,
-                    PhysicalKey::Code(KeyCode::KeyD) => self.input_state.key_d = pressed,
-                    PhysicalKey::Code(KeyCode::KeyS) => self.input_state.key_s = pressed,
-                    PhysicalKey::Code(KeyCode::Space) => self.input_state.key_space = pressed,
-                    PhysicalKey::Code(KeyCode::ControlLeft) => self.input_state.key_ctrl = pressed,
-                    _ => {}
+                let button = match event.physical_key {
+                    PhysicalKey::Code(KeyCode::KeyW) => Button::W,
+                    PhysicalKey::Code(KeyCode::KeyA) => Button::A,
+                    PhysicalKey::Code(KeyCode::KeyD) => Button::D,
+                    PhysicalKey::Code(KeyCode::KeyS) => Button::S,
+                    PhysicalKey::Code(KeyCode::Space) => Button::Space,
+                    PhysicalKey::Code(KeyCode::ControlLeft) => Button::Ctrl,
+                    PhysicalKey::Code(KeyCode::ShiftLeft) => Button::Shift,
+                    PhysicalKey::Code(KeyCode::F1) if pressed && !event.repeat => {
+                        self.cycle_render_mode();
+                        return;
+                    }
+                    PhysicalKey::Code(KeyCode::Escape) if pressed => {
+                        self.set_cursor_locked(false);
+                        return;
+                    }
+                    _ => return,
+                };
+                self.input_queue.push(button, pressed, Instant::now());
+            }
+            winit::event::WindowEvent::CursorMoved {
+                device_id: _,
+                position,
+            } => {
+                self.input_state
+                    .cursor_moved(position.x as f32, position.y as f32);
+                self.recenter_cursor();
+            }
+            winit::event::WindowEvent::MouseInput {
+                device_id: _,
+                state,
+                button,
+            } => {
+                let pressed = state == ElementState::Pressed;
+                // The click that grabs the cursor isn't passed on.
+                if pressed && !self.cursor_locked {
+                    self.set_cursor_locked(true);
+                    return;
                 }
+                let button = match button {
+                    MouseButton::Left => Button::MouseLeft,
+                    MouseButton::Right => Button::MouseRight,
+                    _ => return,
+                };
+                self.input_queue.push(button, pressed, Instant::now());
+            }
+            winit::event::WindowEvent::CursorEntered { device_id: _ } => {
+                self.input_state.cursor_in_window = true;
+            }
+            winit::event::WindowEvent::CursorLeft { device_id: _ } => {
+                self.input_state.cursor_in_window = false;
             }
             winit::event::WindowEvent::RedrawRequested => {
                 #[cfg(feature = "tracy")]
                 span!("Winit::event::WindowEvent::RedrawRequested");
 
-                let viewport = self.viewports.get(0).expect("viewport must exist");
-                let descriptor = &viewport.description;
-                let render_pipeline = self
-                    .render_pipeline
-                    .as_ref()
-                    .expect("render pipeline must exist");
-
-                descriptor.window.pre_present_notify();
-                let output = descriptor.surface.get_current_texture().unwrap();
-
-                let view = output.texture.create_view(&Default::default());
-
-                let mut encoder = self
-                    .gpu_context
-                    .as_ref()
-                    .expect("gpu_context should exist")
-                    .device
-                    .create_command_encoder(&Default::default());
-
-                let mut staging_belt = self.staging_belt.as_mut().unwrap().lock().unwrap();
-                let gpu_buffer_registry = self.gpu_buffer_registry.as_mut().unwrap();
-                let device = &self.gpu_context.as_ref().unwrap().device;
-                let frame_index = self.frame_index.index();
-                let mut world = self.world.lock().unwrap();
-                upload_camera_data(
-                    &mut world,
-                    frame_index,
-                    &mut staging_belt,
-                    device,
-                    &mut encoder,
-                    gpu_buffer_registry,
-                );
-
-                upload_indirect_draw_commands(
-                    &mut world,
-                    frame_index,
-                    &mut staging_belt,
-                    device,
-                    &mut encoder,
-                    gpu_buffer_registry,
-                );
-
-                init_render_pass(
-                    &mut encoder,
-                    &view,
-                    descriptor,
-                    render_pipeline,
-                    self.gpu_buffer_registry
-                        .as_mut()
-                        .expect("gpu buffer registry should exist"),
-                    &mut self.frame_index,
-                    self.mesh_allocator.as_mut().unwrap(),
-                );
-
-                staging_belt.finish();
-
-                let _ = self
-                    .gpu_context
-                    .as_ref()
-                    .expect("gpu_context should exist")
-                    .queue
-                    .submit(Some(encoder.finish()));
-
-                output.present();
-
-                staging_belt.recall();
-
-                self.frame_index.advance();
-                self.fps_counter
-                    .as_mut()
-                    .expect("fps counter must exist")
-                    .tick();
+                let frame_start = Instant::now();
+                self.redraw_requested = false;
+                self.apply_pending_resize();
+                self.begin_frame();
+                self.submit_sim_ticks();
+                if self.render() {
+                    self.end_frame();
+                    if self.record_benchmark_frame(frame_start.elapsed()) {
+                        event_loop.exit();
+                    }
+                }
             }
             _ => {}
         }
@@ -597,17 +1786,12 @@ impl ApplicationHandler for Engine {
 
     fn device_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        device_id: winit::event::DeviceId,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: winit::event::DeviceId,
         event: winit::event::DeviceEvent,
     ) {
-        match event {
-            winit::event::DeviceEvent::MouseMotion { delta: (dx, dy) } => {
-                let mut input = &mut self.input_state;
-                input.mouse_delta_x += dx as f32;
-                input.mouse_delta_y += dy as f32;
-            }
-            _ => {}
+        if let winit::event::DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            self.input_state.mouse_motion(dx as f32, dy as f32);
         }
     }
 
@@ -615,52 +1799,61 @@ impl ApplicationHandler for Engine {
         #[cfg(feature = "tracy")]
         span!("Winit::about_to_wait");
 
-        if let Some(window) = &self.window {
+        if let Some(window) = self.window.clone() {
             let now = Instant::now();
             let frame_time = now - self.last_time;
             self.last_time = now;
-            self.accumulator += frame_time;
+            let steps = self.timestep.advance(frame_time);
 
             #[cfg(feature = "tracy")]
-            plot!("Accumulator (ms)", self.accumulator.as_secs_f64() * 1000.0);
+            plot!(
+                "Accumulator (ms)",
+                (self.timestep.delta_time() - self.timestep.until_next_step()).as_secs_f64()
+                    * 1000.0
+            );
             #[cfg(feature = "tracy")]
             plot!("Real Frame Time (ms)", frame_time.as_secs_f64() * 1000.0);
 
             #[cfg(feature = "tracy")]
             span!("ECS Tick Loop");
 
-            while self.accumulator >= self.delta_time {
-                let world = self.world.clone();
-                let frame_index = self.frame_index.index();
-                let input_state = self.input_state.clone();
-                debug!("{:?}", input_state);
-                let delta_time = self.delta_time;
-                #[cfg(feature = "tracy")]
-                span!("ECS Tick Submission");
-                self.thread_pool.as_ref().unwrap().submit(move || {
-                    #[cfg(feature = "tracy")]
-                    span!("World.run_systems");
-                    let mut world = world.lock().unwrap();
-                    world.run_systems(frame_index, &input_state, delta_time.as_secs_f32());
-                });
-
+            let dropped = self.sim_scheduler.add_steps(steps);
+            if dropped > 0 {
+                debug!("sim is behind, dropped {dropped} steps");
+            }
+            if self.timestep.paused() {
+                self.sim_scheduler.discard_pending();
+                // Left queued, mouse movement and presses during the pause
+                // would be applied on resume. Releases still update what is
+                // held.
+                self.input_state.apply_events(&self.input_queue.drain());
+                self.input_state.clear_transitions();
                 self.input_state.mouse_delta_x = 0.0;
                 self.input_state.mouse_delta_y = 0.0;
-
-                // self.sim_frame_index.advance();
-                self.accumulator -= self.delta_time;
             }
 
-            window.request_redraw();
+            // A frame drawn now would only wait for the sim in begin_frame,
+            // so it is requested once the job is done and submits the next
+            // one itself. Nothing wakes the loop when a job finishes, so
+            // check back shortly. Without redraws the ticks are submitted
+            // here, the sim keeps running while the window is hidden.
+            let mut next_wake = now + self.timestep.until_next_step();
+            if self.sim_scheduler.is_idle() {
+                if self.redraw_requested {
+                    self.submit_sim_ticks();
+                }
+                window.request_redraw();
+                self.redraw_requested = true;
+            } else {
+                next_wake = next_wake.min(now + SIM_POLL_INTERVAL);
+            }
 
             #[cfg(feature = "tracy")]
             tracy_client::Client::running()
                 .expect("Tracy client must be running to mark a frame")
                 .frame_mark();
 
-            let next_logic_update = now + (self.delta_time - self.accumulator);
-            event_loop
-                .set_control_flow(winit::event_loop::ControlFlow::WaitUntil(next_logic_update));
+            event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(next_wake));
         }
     }
 }
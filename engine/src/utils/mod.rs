@@ -2,7 +2,7 @@ use std::{
     any::TypeId,
     collections::VecDeque,
     sync::{Arc, Condvar, Mutex, atomic::AtomicBool},
-    thread::{JoinHandle, spawn},
+    thread::{Builder, JoinHandle},
     time::{Duration, Instant},
 };
 
@@ -12,6 +12,8 @@ use log::info;
 pub struct FPSCounter {
     last_instant: Instant,
     frame_count: u32,
+    fps: f64,
+    gpu_frame_time_ms: Option<f32>,
 }
 
 impl Default for FPSCounter {
@@ -19,11 +21,26 @@ impl Default for FPSCounter {
         Self {
             last_instant: Instant::now(),
             frame_count: 0,
+            fps: 0.0,
+            gpu_frame_time_ms: None,
         }
     }
 }
 
 impl FPSCounter {
+    pub fn set_gpu_frame_time(&mut self, gpu_frame_time_ms: Option<f32>) {
+        self.gpu_frame_time_ms = gpu_frame_time_ms;
+    }
+
+    pub fn gpu_frame_time_ms(&self) -> Option<f32> {
+        self.gpu_frame_time_ms
+    }
+
+    // Averaged over the last full second, 0 until one has passed.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
     pub fn tick(&mut self) {
         self.frame_count += 1;
         let now = Instant::now();
@@ -31,8 +48,14 @@ impl FPSCounter {
 
         if elapsed >= Duration::from_secs(1) {
             let fps = self.frame_count as f64 / elapsed.as_secs_f64();
+            self.fps = fps;
 
-            info!("FPS: {:.2}", fps);
+            match self.gpu_frame_time_ms {
+                Some(gpu_frame_time_ms) => {
+                    info!("FPS: {:.2}, GPU: {:.3} ms", fps, gpu_frame_time_ms)
+                }
+                None => info!("FPS: {:.2}", fps),
+            }
 
             self.frame_count = 0;
             self.last_instant = now;
@@ -40,6 +63,57 @@ impl FPSCounter {
     }
 }
 
+// Turns real frame time into whole fixed-length simulation steps, carrying
+// the remainder over to the next frame.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    delta_time: Duration,
+    accumulator: Duration,
+    paused: bool,
+}
+
+impl FixedTimestep {
+    pub fn new(delta_time: Duration) -> Self {
+        Self {
+            delta_time,
+            accumulator: Duration::ZERO,
+            paused: false,
+        }
+    }
+
+    pub fn delta_time(&self) -> Duration {
+        self.delta_time
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    // Time doesn't accumulate while paused, and the leftover from before the
+    // pause is dropped on resume so the sim never runs a catch-up burst.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused && !paused {
+            self.accumulator = Duration::ZERO;
+        }
+        self.paused = paused;
+    }
+
+    // Returns how many steps to run for a frame that took `frame_time`.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        self.accumulator += frame_time;
+        let steps = (self.accumulator.as_nanos() / self.delta_time.as_nanos()) as u32;
+        self.accumulator -= self.delta_time * steps;
+        steps
+    }
+
+    pub fn until_next_step(&self) -> Duration {
+        self.delta_time - self.accumulator
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RegisterKey {
     pub type_id: TypeId,
@@ -108,9 +182,115 @@ impl<T: Send + Sync> Registry<T> {
     }
 }
 
+// Counts jobs submitted against jobs finished so another thread can wait
+// for all of them, e.g. the renderer for the sim ticks it's about to draw.
+#[derive(Default)]
+pub struct JobFence {
+    submitted: u64,
+    completed: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl JobFence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Move the signal into the job. It counts as finished when dropped, so a
+    // job that panics doesn't leave waiters blocked forever.
+    pub fn submit(&mut self) -> JobSignal {
+        self.submitted += 1;
+        JobSignal {
+            completed: Arc::clone(&self.completed),
+        }
+    }
+
+    pub fn wait(&self) {
+        let (lock, cvar) = &*self.completed;
+        let _completed = cvar
+            .wait_while(lock.lock().unwrap(), |completed| {
+                *completed < self.submitted
+            })
+            .unwrap();
+    }
+
+    pub fn is_idle(&self) -> bool {
+        *self.completed.0.lock().unwrap() >= self.submitted
+    }
+}
+
+pub struct JobSignal {
+    completed: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl Drop for JobSignal {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.completed;
+        // A poisoned count is still a count, the job is over either way.
+        let mut completed = lock.lock().unwrap_or_else(|err| err.into_inner());
+        *completed += 1;
+        cvar.notify_all();
+    }
+}
+
+// Keeps at most one sim job in flight. Steps that come due while a job is
+// still running are held back and handed to the next one, so ticks never
+// race on the world lock or run out of order. Past MAX_PENDING_STEPS the
+// extra steps are dropped, a sim that can't keep up slows down instead of
+// queueing ever longer catch-up jobs.
+#[derive(Default)]
+pub struct SimScheduler {
+    fence: JobFence,
+    pending_steps: u32,
+}
+
+impl SimScheduler {
+    pub const MAX_PENDING_STEPS: u32 = 8;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns how many of `steps` were dropped.
+    pub fn add_steps(&mut self, steps: u32) -> u32 {
+        let pending = self.pending_steps.saturating_add(steps);
+        self.pending_steps = pending.min(Self::MAX_PENDING_STEPS);
+        pending - self.pending_steps
+    }
+
+    // When no job is running, takes every pending step along with the signal
+    // the job has to hold until it finishes.
+    pub fn take_job(&mut self) -> Option<(u32, JobSignal)> {
+        if self.pending_steps == 0 || !self.fence.is_idle() {
+            return None;
+        }
+        let steps = std::mem::take(&mut self.pending_steps);
+        Some((steps, self.fence.submit()))
+    }
+
+    // Steps not yet handed to a job.
+    pub fn pending_steps(&self) -> u32 {
+        self.pending_steps
+    }
+
+    pub fn discard_pending(&mut self) {
+        self.pending_steps = 0;
+    }
+
+    // True once the last submitted job has finished, a frame drawn now sees
+    // every tick submitted so far.
+    pub fn is_idle(&self) -> bool {
+        self.fence.is_idle()
+    }
+
+    pub fn wait(&self) {
+        self.fence.wait();
+    }
+}
+
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
 pub struct ThreadPool {
+    name: String,
     workers: Vec<JoinHandle<()>>,
     job_queue: Arc<(Mutex<VecDeque<Job>>, Condvar)>,
     is_running: Arc<AtomicBool>,
@@ -118,45 +298,63 @@ pub struct ThreadPool {
 
 impl ThreadPool {
     pub fn new(num_threads: usize) -> Self {
+        Self::named("worker", num_threads)
+    }
+
+    // Workers are named `{name}-{n}` so each pool shows up on its own in a
+    // debugger or profiler.
+    pub fn named(name: impl Into<String>, num_threads: usize) -> Self {
+        let name = name.into();
         let job_queue = Arc::new((Mutex::new(VecDeque::<Job>::new()), Condvar::new()));
         let is_running = Arc::new(AtomicBool::new(true));
         let mut workers = Vec::new();
 
-        for _ in 0..num_threads {
+        for worker in 0..num_threads {
             let queue = Arc::clone(&job_queue);
             let running = Arc::clone(&is_running);
 
-            let handle = spawn(move || {
-                while running.load(std::sync::atomic::Ordering::Acquire) {
-                    let job = {
-                        let (lock, cvar) = &*queue;
-                        let mut queue = lock.lock().unwrap();
-
-                        while queue.is_empty() {
-                            queue = cvar.wait(queue).unwrap();
+            let handle = Builder::new()
+                .name(format!("{name}-{worker}"))
+                .spawn(move || {
+                    while running.load(std::sync::atomic::Ordering::Acquire) {
+                        let job = {
+                            let (lock, cvar) = &*queue;
+                            let mut queue = lock.lock().unwrap();
 
-                            if !running.load(std::sync::atomic::Ordering::Acquire) {
-                                return;
+                            while queue.is_empty() {
+                                if !running.load(std::sync::atomic::Ordering::Acquire) {
+                                    return;
+                                }
+                                queue = cvar.wait(queue).unwrap();
                             }
-                        }
-                        queue.pop_front()
-                    };
+                            queue.pop_front()
+                        };
 
-                    if let Some(job) = job {
-                        job();
+                        if let Some(job) = job {
+                            job();
+                        }
                     }
-                }
-            });
+                })
+                .expect("failed to spawn worker thread");
             workers.push(handle);
         }
 
         Self {
+            name,
             workers,
             job_queue,
             is_running,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.workers.len()
+    }
+
     pub fn submit<F>(&self, job: F)
     where
         F: FnOnce() + Send + 'static,
@@ -167,10 +365,17 @@ impl ThreadPool {
         cvar.notify_one();
     }
 
+    // Jobs already running finish, queued jobs that haven't started are dropped.
     pub fn shutdown(self) {
-        self.is_running
-            .store(false, std::sync::atomic::Ordering::Release);
         let (lock, cvar) = &*self.job_queue;
+        {
+            // Holding the lock means no worker sits between its running check
+            // and cvar.wait, so none of them can miss the wakeup.
+            let mut queue = lock.lock().unwrap();
+            self.is_running
+                .store(false, std::sync::atomic::Ordering::Release);
+            queue.clear();
+        }
         cvar.notify_all();
 
         for handle in self.workers {
@@ -178,3 +383,114 @@ impl ThreadPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn shutdown_finishes_running_jobs_and_drops_queued_ones() {
+        let pool = ThreadPool::named("test", 1);
+        let finished = Arc::new(AtomicBool::new(false));
+        let dropped_ran = Arc::new(AtomicBool::new(false));
+
+        let (started_sender, started) = mpsc::channel();
+        let running_finished = finished.clone();
+        pool.submit(move || {
+            started_sender.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            running_finished.store(true, std::sync::atomic::Ordering::Release);
+        });
+        let queued_ran = dropped_ran.clone();
+        pool.submit(move || queued_ran.store(true, std::sync::atomic::Ordering::Release));
+        started.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let (joined_sender, joined) = mpsc::channel();
+        std::thread::spawn(move || {
+            pool.shutdown();
+            joined_sender.send(()).unwrap();
+        });
+        joined
+            .recv_timeout(Duration::from_secs(5))
+            .expect("shutdown should return once the running job finishes");
+
+        assert!(finished.load(std::sync::atomic::Ordering::Acquire));
+        assert!(!dropped_ran.load(std::sync::atomic::Ordering::Acquire));
+    }
+
+    #[test]
+    fn paused_timestep_runs_no_steps_and_resumes_without_a_burst() {
+        let delta_time = Duration::from_millis(10);
+        let mut timestep = FixedTimestep::new(delta_time);
+        assert_eq!(timestep.advance(Duration::from_millis(15)), 1);
+
+        timestep.set_paused(true);
+        for _ in 0..10 {
+            assert_eq!(timestep.advance(Duration::from_millis(100)), 0);
+        }
+
+        // The 5ms left over from before the pause is dropped too.
+        timestep.set_paused(false);
+        assert_eq!(timestep.until_next_step(), delta_time);
+        assert_eq!(timestep.advance(Duration::from_millis(9)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(1)), 1);
+    }
+
+    #[test]
+    fn fence_waits_for_every_signal_including_panicked_jobs() {
+        let mut fence = JobFence::new();
+        let finished = fence.submit();
+        let panicked = fence.submit();
+        let held = fence.submit();
+
+        std::thread::spawn(move || drop(finished));
+        let panicked_job = std::thread::spawn(move || {
+            let _signal = panicked;
+            panic!("job failed");
+        });
+        assert!(panicked_job.join().is_err());
+        let (release_sender, release) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let _signal = held;
+            let _ = release.recv();
+        });
+
+        let (waited_sender, waited) = mpsc::channel();
+        std::thread::spawn(move || {
+            fence.wait();
+            waited_sender.send(fence.is_idle()).unwrap();
+        });
+        assert!(waited.recv_timeout(Duration::from_millis(50)).is_err());
+
+        drop(release_sender);
+        let idle = waited
+            .recv_timeout(Duration::from_secs(5))
+            .expect("wait should return once every signal has dropped");
+        assert!(idle);
+    }
+
+    #[test]
+    fn scheduler_runs_one_job_at_a_time_and_caps_pending_steps() {
+        let mut scheduler = SimScheduler::new();
+        assert!(scheduler.take_job().is_none());
+
+        assert_eq!(scheduler.add_steps(2), 0);
+        let (steps, signal) = scheduler.take_job().expect("idle with steps due");
+        assert_eq!(steps, 2);
+        assert!(!scheduler.is_idle());
+
+        // Held back while the job runs, and capped however long it takes.
+        assert_eq!(scheduler.add_steps(3), 0);
+        assert!(scheduler.take_job().is_none());
+        assert_eq!(scheduler.add_steps(SimScheduler::MAX_PENDING_STEPS), 3);
+        assert_eq!(scheduler.pending_steps(), SimScheduler::MAX_PENDING_STEPS);
+
+        drop(signal);
+        assert!(scheduler.is_idle());
+        let (steps, _signal) = scheduler.take_job().expect("idle with steps due");
+        assert_eq!(steps, SimScheduler::MAX_PENDING_STEPS);
+        assert_eq!(scheduler.pending_steps(), 0);
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+use log::{info, warn};
+use winit::window::CursorGrabMode;
+
+// The grab mode tried first when the cursor is locked for mouse look, the
+// other one is tried if the platform refuses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorGrab {
+    // Pins the cursor in place, not supported on X11.
+    #[default]
+    Locked,
+    // Keeps the cursor inside the window, not supported on macOS.
+    Confined,
+}
+
+impl CursorGrab {
+    pub fn mode(self) -> CursorGrabMode {
+        match self {
+            CursorGrab::Locked => CursorGrabMode::Locked,
+            CursorGrab::Confined => CursorGrabMode::Confined,
+        }
+    }
+
+    pub fn fallback(self) -> CursorGrab {
+        match self {
+            CursorGrab::Locked => CursorGrab::Confined,
+            CursorGrab::Confined => CursorGrab::Locked,
+        }
+    }
+}
+
+// How the cursor ended up held once grabbing was attempted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabOutcome {
+    Grabbed(CursorGrab),
+    // Neither mode worked, the cursor has to be moved back to the window
+    // center whenever it moves.
+    Recenter,
+}
+
+// Tries `preferred` and then its fallback through `try_grab`, stopping at the
+// first mode the platform accepts.
+pub fn grab_cursor<E: Display>(
+    preferred: CursorGrab,
+    mut try_grab: impl FnMut(CursorGrabMode) -> Result<(), E>,
+) -> GrabOutcome {
+    for grab in [preferred, preferred.fallback()] {
+        match try_grab(grab.mode()) {
+            Ok(()) => {
+                info!("cursor grabbed with {grab:?}");
+                return GrabOutcome::Grabbed(grab);
+            }
+            Err(err) => warn!("cursor grab {grab:?} failed: {err}"),
+        }
+    }
+    info!("cursor grab unsupported, re-centering manually");
+    GrabOutcome::Recenter
+}
@@ -3,9 +3,7 @@ use std::process;
 use log::{error, info};
 use winit::event_loop::EventLoop;
 
-use crate::engine::Engine;
-use ecs;
-use engine;
+use engine::{Engine, config::EngineConfig};
 
 fn main() {
     env_logger::init();
@@ -16,11 +14,22 @@ fn main() {
         Err(err) => panic!("failed to start the event loop, {}", err),
     };
 
+    let mut engine = match Engine::new(EngineConfig::new().window_title("test game")) {
+        Ok(engine) => engine,
+        Err(err) => panic!("failed to create the engine, {}", err),
+    };
+
     info!("running Engine");
-    let _ = event_loop
-        .run_app(&mut Engine::default())
-        .unwrap_or_else(|err| {
-            error!("failed to run EngineState. {:?}", err);
-            process::exit(1);
-        });
+    event_loop.run_app(&mut engine).unwrap_or_else(|err| {
+        error!("failed to run EngineState. {:?}", err);
+        process::exit(1);
+    });
+    if let Some(err) = engine.init_error() {
+        error!("failed to start the engine, {err}");
+        process::exit(1);
+    }
+
+    if let Some(report) = engine.benchmark_report() {
+        info!("{report:?}");
+    }
 }